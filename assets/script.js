@@ -2,12 +2,16 @@ const API_URL = "http://127.0.0.1:3000";
 let selectedPiece = null;
 let currentPlayer = null;
 let gameMode = 'two-player'; // Default to two-player mode
+let gameId = null; // Session id handed out by the server
+let goals = []; // The two goal squares of the current game
 
 // UI Elements
 const boardElement = document.getElementById('gameBoard');
 const statusElement = document.getElementById('gameStatus');
 const playerIconElement = document.getElementById('currentPlayerIcon');
 const resetButton = document.getElementById('resetButton');
+const undoButton = document.getElementById('undoButton');
+const hintButton = document.getElementById('hintButton');
 const messageBox = document.getElementById('messageBox');
 const messageText = document.getElementById('messageText');
 const rulesModal = document.getElementById('rulesModal');
@@ -44,7 +48,7 @@ function showMessage(text, type = 'info') {
 // Fetches the current game state from the Rust server
 async function fetchBoardState() {
     try {
-        const response = await fetch(`${API_URL}/board`);
+        const response = await fetch(`${API_URL}/board/${gameId}`);
         if (!response.ok) {
             throw new Error(`Server responded with status: ${response.status}`);
         }
@@ -58,14 +62,55 @@ async function fetchBoardState() {
     }
 }
 
+// Creates a new game session on the server and remembers its id. The server
+// is told who plays, so the AI can't move for a person or the other way round.
+async function createGame() {
+    const mode = gameMode === 'solo' ? { VsAi: { ai_player: 'P2' } } : 'Human';
+    try {
+        const response = await fetch(`${API_URL}/games`, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ config: { mode } }),
+        });
+        if (!response.ok) {
+            throw new Error(`Server responded with status: ${response.status}`);
+        }
+        const session = await response.json();
+        gameId = session.id;
+        await fetchConfig();
+    } catch (error) {
+        console.error("Failed to create game:", error);
+        showMessage("Failed to connect to the server. Is it running?", 'error');
+    }
+}
+
+// Fetches where the goals are, so they can be marked on the board
+async function fetchConfig() {
+    try {
+        const response = await fetch(`${API_URL}/config/${gameId}`);
+        if (!response.ok) {
+            throw new Error(`Server responded with status: ${response.status}`);
+        }
+        const config = await response.json();
+        goals = [config.goal_p1, config.goal_p2];
+    } catch (error) {
+        console.error("Failed to fetch game config:", error);
+    }
+}
+
 // Renders the game board based on the game state
 function renderBoard(game) {
     boardElement.innerHTML = '';
+    boardElement.style.gridTemplateColumns = `repeat(${game.size}, 1fr)`;
+    boardElement.style.gridTemplateRows = `repeat(${game.size}, 1fr)`;
     game.board.forEach((row, rowIndex) => {
         row.forEach((player, colIndex) => {
             const cell = document.createElement('div');
             const cellColor = (rowIndex + colIndex) % 2 === 0 ? 'bg-gray-700' : 'bg-gray-500';
             cell.className = `cell ${cellColor} rounded-md`;
+            if (goals.some(goal => goal.row === rowIndex && goal.col === colIndex)) {
+                cell.classList.add('goal');
+            }
             
             cell.dataset.row = rowIndex;
             cell.dataset.col = colIndex;
@@ -89,8 +134,11 @@ function updateGameStatus(game) {
     if (game.status === 'Ongoing') {
         statusElement.textContent = `Player ${currentPlayer === 'P1' ? '1' : '2'}'s turn`;
         playerIconElement.className = `w-6 h-6 rounded-full ${currentPlayer === 'P1' ? 'bg-red-500' : 'bg-blue-500'}`;
+    } else if (game.status === 'Draw') {
+        statusElement.textContent = "It's a draw!";
+        playerIconElement.className = 'w-6 h-6 rounded-full bg-gray-400';
     } else {
-        const winner = game.status.Won;
+        const [winner] = game.status.Won;
         if (winner) {
             statusElement.textContent = `Player ${winner === 'P1' ? '1' : '2'} wins!`;
             playerIconElement.className = `w-6 h-6 rounded-full ${winner === 'P1' ? 'bg-red-500' : 'bg-blue-500'}`;
@@ -134,15 +182,15 @@ async function makeMove(fromRow, fromCol, toRow, toCol) {
     };
 
     try {
-        const response = await fetch(`${API_URL}/move`, {
+        const response = await fetch(`${API_URL}/move/${gameId}`, {
             method: 'POST',
             headers: { 'Content-Type': 'application/json' },
             body: JSON.stringify(moveRequest),
         });
         
         if (!response.ok) {
-            const message = await response.text();
-            showMessage(message, 'error');
+            const body = await response.json();
+            showMessage(body.error, 'error');
         }
         
         const game = await fetchBoardState();
@@ -162,7 +210,7 @@ async function makeMove(fromRow, fromCol, toRow, toCol) {
 async function makeAiMove() {
     showMessage("AI is thinking...", "info");
     try {
-        const response = await fetch(`${API_URL}/ai-move`, {
+        const response = await fetch(`${API_URL}/ai-move/${gameId}`, {
             method: 'POST',
         });
         
@@ -174,10 +222,60 @@ async function makeAiMove() {
     }
 }
 
+// Asks the server for a suggested move and highlights its squares
+async function showHint() {
+    try {
+        const response = await fetch(`${API_URL}/hint/${gameId}`, {
+            method: 'POST',
+        });
+        if (!response.ok) {
+            const message = await response.text();
+            showMessage(message, 'error');
+            return;
+        }
+        const hint = await response.json();
+        document.querySelectorAll('.cell').forEach(c => c.classList.remove('hint'));
+        [hint.from, hint.to].forEach(pos => {
+            const cell = boardElement.querySelector(`[data-row="${pos.row}"][data-col="${pos.col}"]`);
+            if (cell) {
+                cell.classList.add('hint');
+            }
+        });
+    } catch (error) {
+        console.error("Failed to get hint:", error);
+        showMessage("Failed to connect to the server.", 'error');
+    }
+}
+
+// Takes back the last move. In solo mode the AI's reply is taken back too,
+// so the human gets their own turn again.
+async function undoMove() {
+    try {
+        const response = await fetch(`${API_URL}/undo/${gameId}`, {
+            method: 'POST',
+        });
+        if (!response.ok) {
+            const message = await response.text();
+            showMessage(message, 'error');
+            return;
+        }
+        const game = await fetchBoardState();
+        if (gameMode === 'solo' && game && game.current_player === 'P2' && game.history.length > 0) {
+            await fetch(`${API_URL}/undo/${gameId}`, {
+                method: 'POST',
+            });
+            await fetchBoardState();
+        }
+    } catch (error) {
+        console.error("Failed to undo move:", error);
+        showMessage("Failed to connect to the server.", 'error');
+    }
+}
+
 // Resets the game by calling the server's reset endpoint
 async function resetGame() {
     try {
-        const response = await fetch(`${API_URL}/reset`, {
+        const response = await fetch(`${API_URL}/reset/${gameId}`, {
             method: 'POST',
         });
         const message = await response.text();
@@ -198,23 +296,31 @@ async function resetGame() {
 // Event listener for the reset button
 resetButton.addEventListener('click', resetGame);
 
+// Event listener for the undo button
+undoButton.addEventListener('click', undoMove);
+
+// Event listener for the hint button
+hintButton.addEventListener('click', showHint);
+
 // Event listener to close the rules modal
 closeModalButton.addEventListener('click', () => {
     rulesModal.style.display = 'none';
 });
 
 // Event listeners for mode selection
-soloModeButton.addEventListener('click', () => {
+soloModeButton.addEventListener('click', async () => {
     gameMode = 'solo';
     modeSelection.classList.add('hidden');
     gameContainer.classList.remove('hidden');
+    await createGame();
     fetchBoardState();
 });
 
-twoPlayerModeButton.addEventListener('click', () => {
+twoPlayerModeButton.addEventListener('click', async () => {
     gameMode = 'two-player';
     modeSelection.classList.add('hidden');
     gameContainer.classList.remove('hidden');
+    await createGame();
     fetchBoardState();
 });
 
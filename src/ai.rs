@@ -1,135 +1,1028 @@
-use crate::game::{Game, GameStatus, Player, Position};
+use crate::game::{
+    Game, GameStatus, MoveRequest, Player, Position, StalemateRule, Strategy, DEFAULT_PLAYERS,
+};
+use rand::rngs::StdRng;
+use rand::seq::IndexedRandom;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Deepest search a client may request; beyond this a move takes too long.
+pub const MAX_SEARCH_DEPTH: u8 = 6;
+/// Thinking time used by the timed search when the client doesn't ask for one.
+pub const DEFAULT_THINK_TIME_MS: u64 = 500;
+/// Longest thinking time a client may request.
+pub const MAX_THINK_TIME_MS: u64 = 10_000;
+/// Upper bound for iterative deepening, reached only when the game tree is tiny.
+pub const MAX_ITERATIVE_DEPTH: u8 = 64;
+/// Most plies the search adds beyond its depth to look at immediate wins.
+const MAX_QUIESCENCE_PLIES: u8 = 4;
+/// Bound of the search window. Not `i32::MIN`, so that it can be negated.
+const INFINITY: i32 = i32::MAX;
+/// Half-width of the window iterative deepening first searches around the
+/// previous depth's score: plenty for progress and mobility to shift, but
+/// narrower than the bonus for a threat.
+pub const DEFAULT_ASPIRATION_WINDOW: i32 = 25;
+
+/// Manhattan distance between two squares. Uses `abs_diff` so it can't
+/// underflow whichever side of the target a piece is on.
+fn manhattan_distance(a: Position, b: Position) -> i32 {
+    (a.row.abs_diff(b.row) + a.col.abs_diff(b.col)) as i32
+}
+
+/// Chebyshev distance between two squares: the larger of the row and column
+/// gaps, i.e. the number of king steps, since a diagonal step covers both at once.
+fn chebyshev_distance(a: Position, b: Position) -> i32 {
+    a.row.abs_diff(b.row).max(a.col.abs_diff(b.col)) as i32
+}
+
+/// How `evaluate` measures a piece's progress towards its target square.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DistanceMetric {
+    /// Rows plus columns. Overstates the distance of diagonally aligned pieces.
+    #[default]
+    Manhattan,
+    /// The larger of rows and columns, which matches diagonal movement.
+    Chebyshev,
+}
+
+impl DistanceMetric {
+    fn distance(self, a: Position, b: Position) -> i32 {
+        match self {
+            DistanceMetric::Manhattan => manhattan_distance(a, b),
+            DistanceMetric::Chebyshev => chebyshev_distance(a, b),
+        }
+    }
+}
+
+/// The other side of a two-player game, the only kind the AI plays.
+fn opponent(player: Player) -> Player {
+    player.next(DEFAULT_PLAYERS)
+}
+
+/// Whether `player` has a piece that could reach the opponent's goal in a single move.
+fn has_winning_move(game: &Game, player: Player) -> bool {
+    let target = game.get_goal_pos(opponent(player));
+    game.all_valid_moves(player)
+        .iter()
+        .any(|mv| mv.to == target)
+}
+
+/// How many more legal moves `player` has than their opponent.
+fn mobility(game: &Game, player: Player) -> i32 {
+    game.all_valid_moves(player).len() as i32 - game.all_valid_moves(opponent(player)).len() as i32
+}
+
+/// Coefficients of the terms in `evaluate`. Changing them gives the AI a
+/// different playing style without touching the search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EvalWeights {
+    /// Score per square of progress towards the opponent's goal.
+    pub goal_distance: i32,
+    /// Score per legal move a side has more than its opponent.
+    pub mobility: i32,
+    /// Bonus for having a piece one move from the opponent's goal
+    /// (and penalty when the opponent has one).
+    pub threat: i32,
+    /// Penalty per piece whose move towards the opponent's goal runs into another
+    /// piece, so that it can't head straight there however close it is.
+    pub blocked: i32,
+}
+
+impl Default for EvalWeights {
+    fn default() -> Self {
+        EvalWeights {
+            goal_distance: 1,
+            mobility: 1,
+            threat: 200,
+            blocked: 2,
+        }
+    }
+}
+
+/// How far the piece of `owner` on `pos` is from the opponent's goal, the square
+/// it is racing to, as `evaluate` sees it. With more than two players, this is the
+/// nearest of the other players' goals.
+pub fn goal_distance(game: &Game, pos: Position, owner: Player, metric: DistanceMetric) -> i32 {
+    game.config
+        .players()
+        .filter(|&player| player != owner)
+        .map(|player| metric.distance(pos, game.get_goal_pos(player)))
+        .min()
+        .unwrap_or(0)
+}
+
+/// Whether the piece of `owner` on `pos` is boxed in on its way to the opponent's
+/// goal. The squares it would cross moving in the goal's direction are checked,
+/// as far as it moves or until it draws level with the goal: the piece counts as
+/// blocked when one of them is taken, however clear the other routes are.
+fn is_blocked_towards_goal(game: &Game, pos: Position, owner: Player) -> bool {
+    let goal = game.get_goal_pos(opponent(owner));
+    let Some(direction) = pos.direction_to(&goal) else {
+        return false;
+    };
+    let to_goal = match (pos.row.abs_diff(goal.row), pos.col.abs_diff(goal.col)) {
+        (0, squares) | (squares, 0) => squares,
+        (rows, cols) => rows.min(cols),
+    };
+    let steps = usize::from(game.move_distance(pos)).min(to_goal);
+    !game.is_ray_clear(pos, direction, steps)
+}
 
 /// A simple heuristic to evaluate the board state.
-/// A higher score is better for the AI (Player 2).
-fn evaluate(game: &Game) -> i32 {
+/// A higher score is better for `player`.
+pub fn evaluate(game: &Game, player: Player, metric: DistanceMetric, weights: EvalWeights) -> i32 {
     let mut score = 0;
 
-    // Check for an immediate win or loss
+    // Check for an immediate win, loss or draw
     match game.status {
-        GameStatus::Won(Player::P2) => return 1000,
-        GameStatus::Won(Player::P1) => return -1000,
-        _ => {}
-    }
-
-    // Heuristic 1: Reward pieces for being closer to the opponent's goal
-    // P2's goal is (0,0), P1's goal is (6,6)
-    for r in 0..7 {
-        for c in 0..7 {
-            if let Some(player) = game.board[r][c] {
-                match player {
-                    Player::P1 => {
-                        let distance = (6 - r) + (6 - c);
-                        score -= distance as i32;
-                    }
-                    Player::P2 => {
-                        let distance = r + c;
-                        score += distance as i32;
-                    }
+        GameStatus::Won(winner, _) => return if winner == player { 1000 } else { -1000 },
+        GameStatus::Draw => return 0,
+        GameStatus::Ongoing => {}
+    }
+
+    // Heuristic 1: Reward pieces for being closer to the opponent's goal, less so
+    // when they have no way forward right now
+    for r in 0..game.size {
+        for c in 0..game.size {
+            if let Some(owner) = game.board[r][c] {
+                let pos = Position { row: r, col: c };
+                let mut distance = weights.goal_distance * goal_distance(game, pos, owner, metric);
+                if weights.blocked != 0 && is_blocked_towards_goal(game, pos, owner) {
+                    distance += weights.blocked;
+                }
+                if owner == player {
+                    score -= distance;
+                } else {
+                    score += distance;
                 }
             }
         }
     }
 
+    // Heuristic 2: Prefer positions where we have more moves than the opponent
+    if weights.mobility != 0 {
+        score += weights.mobility * mobility(game, player);
+    }
+
+    // Heuristic 3: A piece one move from the goal is a threat the other side must answer
+    if has_winning_move(game, player) {
+        score += weights.threat;
+    }
+    if has_winning_move(game, opponent(player)) {
+        score -= weights.threat;
+    }
+
     score
 }
 
-/// The main minimax recursive function.
-fn minimax(game: &Game, depth: u8, is_maximizing_player: bool) -> i32 {
-    // Base Case: If the game is over or we've reached max depth, evaluate the board.
-    if depth == 0 || !matches!(game.status, GameStatus::Ongoing) {
-        return evaluate(game);
+/// How a stored score relates to the true minimax value of its position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bound {
+    /// The score is the true value.
+    Exact,
+    /// The search failed high: the true value is at least the score.
+    Lower,
+    /// The search failed low: the true value is at most the score.
+    Upper,
+}
+
+/// A transposition table entry: the result of searching a position `depth` plies deep.
+#[derive(Debug, Clone, Copy)]
+struct TtEntry {
+    depth: u8,
+    score: i32,
+    bound: Bound,
+    /// The move that raised alpha, if one did, so a reused score keeps its line.
+    best: Option<(Position, Position)>,
+}
+
+/// A sequence of moves, starting with the side to move.
+type Line = Vec<(Position, Position)>;
+
+/// State shared by every node of a single search.
+struct Search {
+    /// The player the AI is searching for; scores are from their perspective.
+    player: Player,
+    /// Distance measure used by `evaluate`.
+    metric: DistanceMetric,
+    /// Coefficients used by `evaluate`.
+    weights: EvalWeights,
+    /// Whether candidate moves are sorted by `move_order_score` before being searched.
+    order_moves: bool,
+    /// Whether the root moves are searched concurrently on the rayon thread pool.
+    parallel: bool,
+    /// Whether positions reached by different move orders share results through `tt`.
+    use_tt: bool,
+    /// Transposition table, keyed by `Game::position_key`.
+    tt: HashMap<u64, TtEntry>,
+    /// Number of `negamax` calls made so far.
+    nodes: u64,
+    /// When set, the search gives up once this instant has passed.
+    deadline: Option<Instant>,
+    /// Set once the deadline has been hit; any score computed afterwards is meaningless.
+    timed_out: bool,
+    /// Picks among equally good root moves; without it the first one is kept.
+    rng: Option<StdRng>,
+    /// Plies the search may still add at the horizon; 0 turns the extension off.
+    quiescence_plies: u8,
+    /// Whether mirror-image root moves of a symmetric position are searched only once.
+    use_symmetry: bool,
+    /// The line expected by the last root search that finished in time, starting
+    /// with the move it picked.
+    pv: Line,
+}
+
+impl Search {
+    fn new(player: Player, metric: DistanceMetric, weights: EvalWeights) -> Self {
+        Search {
+            player,
+            metric,
+            weights,
+            order_moves: true,
+            parallel: true,
+            use_tt: true,
+            tt: HashMap::new(),
+            nodes: 0,
+            deadline: None,
+            timed_out: false,
+            rng: None,
+            quiescence_plies: MAX_QUIESCENCE_PLIES,
+            use_symmetry: true,
+            pv: Vec::new(),
+        }
     }
 
-    let player_to_move = if is_maximizing_player {
-        Player::P2
-    } else {
-        Player::P1
-    };
+    /// A fresh search with the same settings, for searching one root move on its own thread.
+    fn fork(&self) -> Self {
+        Search {
+            player: self.player,
+            metric: self.metric,
+            weights: self.weights,
+            order_moves: self.order_moves,
+            parallel: self.parallel,
+            use_tt: self.use_tt,
+            tt: HashMap::new(),
+            nodes: 0,
+            deadline: self.deadline,
+            timed_out: false,
+            rng: None,
+            quiescence_plies: self.quiescence_plies,
+            use_symmetry: self.use_symmetry,
+            pv: Vec::new(),
+        }
+    }
 
-    let mut all_valid_moves = Vec::new();
-    for r in 0..7 {
-        for c in 0..7 {
-            if game.board[r][c] == Some(player_to_move) {
-                let from_pos = Position { row: r, col: c };
-                let valid_moves = game.get_valid_moves_for_piece(from_pos);
-                for to_pos in valid_moves {
-                    all_valid_moves.push((from_pos, to_pos));
+    /// Checks the deadline, latching `timed_out` once it has passed.
+    fn out_of_time(&mut self) -> bool {
+        if !self.timed_out {
+            if let Some(deadline) = self.deadline {
+                self.timed_out = Instant::now() >= deadline;
+            }
+        }
+        self.timed_out
+    }
+}
+
+/// Collects every legal `(from, to)` move for `player`.
+fn generate_moves(game: &Game, player: Player) -> Vec<(Position, Position)> {
+    game.all_valid_moves(player)
+        .into_iter()
+        .map(|mv| (mv.from, mv.to))
+        .collect()
+}
+
+/// Cheap guess at how good a move is, used only to decide search order.
+/// Winning moves come first, then moves landing nearer the opponent's goal.
+fn move_order_score(game: &Game, player: Player, to: Position) -> i32 {
+    let target = game.get_goal_pos(opponent(player));
+    if to == target {
+        return i32::MAX;
+    }
+    -manhattan_distance(to, target)
+}
+
+/// Sorts moves so the most promising are searched first, which lets alpha-beta
+/// cut off more of the tree. The sort is stable, so ties keep generation order.
+fn order_moves(game: &Game, moves: &mut [(Position, Position)], player: Player) {
+    moves.sort_by_key(|&(_, to)| std::cmp::Reverse(move_order_score(game, player, to)));
+}
+
+/// Negamax search with alpha-beta pruning. Returns the score of `game` from the
+/// point of view of `player`, the side to move. What is good for one side is bad
+/// for the other, so each child's score is negated and the `alpha`/`beta` window
+/// flipped. `alpha` is the score `player` can already guarantee and `beta` the
+/// most the opponent will allow; once `alpha >= beta` the remaining siblings
+/// cannot affect the result and are skipped. When the score lies above `alpha`,
+/// the best line from `game` is written to `line`, which should start empty.
+fn negamax(
+    search: &mut Search,
+    game: &mut Game,
+    depth: u8,
+    mut alpha: i32,
+    mut beta: i32,
+    player: Player,
+    line: &mut Line,
+) -> i32 {
+    search.nodes += 1;
+    if search.out_of_time() {
+        return 0; // Discarded by the caller
+    }
+
+    // Base Case: If the game is over or we've reached max depth, evaluate the board.
+    if !matches!(game.status, GameStatus::Ongoing) {
+        return evaluate(game, player, search.metric, search.weights);
+    }
+    if depth == 0 {
+        // Quiescence: a side that can reach the goal right now isn't in a quiet
+        // position, so look one ply further rather than trust the heuristic
+        if search.quiescence_plies == 0 || !has_winning_move(game, player) {
+            return evaluate(game, player, search.metric, search.weights);
+        }
+        search.quiescence_plies -= 1;
+        let score = negamax(search, game, 1, alpha, beta, player, line);
+        search.quiescence_plies += 1;
+        return score;
+    }
+
+    // Reuse the result of an earlier search of this position if it went deep enough
+    // Within one search the player to move always follows from the position, so
+    // the key doesn't need to include `player` separately
+    let key = game.position_key();
+    let (original_alpha, original_beta) = (alpha, beta);
+    if search.use_tt {
+        if let Some(entry) = search.tt.get(&key).filter(|entry| entry.depth >= depth) {
+            match entry.bound {
+                Bound::Exact => {
+                    let score = entry.score;
+                    stored_line(search, game, depth, line);
+                    return score;
                 }
+                Bound::Lower => alpha = alpha.max(entry.score),
+                Bound::Upper => beta = beta.min(entry.score),
+            }
+            if alpha >= beta {
+                return entry.score;
             }
         }
     }
 
-    // If no moves are possible, it's a loss for the current player
+    let mut all_valid_moves = generate_moves(game, player);
+
+    // If no moves are possible, it's a loss (or a draw, depending on the rules)
+    // for the player to move
     if all_valid_moves.is_empty() {
-        return if is_maximizing_player { -1000 } else { 1000 };
+        return match game.config.stalemate_rule {
+            StalemateRule::OpponentWins => -1000,
+            StalemateRule::Draw => 0,
+        };
     }
 
-    if is_maximizing_player {
-        let mut best_score = i32::MIN;
-        for (from, to) in all_valid_moves {
-            let mut new_game_state = game.clone();
-            let _ = new_game_state.make_move(from, to);
-            let score = minimax(&new_game_state, depth - 1, false);
-            best_score = best_score.max(score);
+    if search.order_moves {
+        order_moves(game, &mut all_valid_moves, player);
+    }
+
+    let mut best_score = -INFINITY;
+    for (from, to) in all_valid_moves {
+        let token = game.apply_move(from, to);
+        let mut rest = Vec::new();
+        let score = -negamax(
+            search,
+            game,
+            depth - 1,
+            -beta,
+            -alpha,
+            opponent(player),
+            &mut rest,
+        );
+        game.undo_with(token);
+        best_score = best_score.max(score);
+        if score > alpha {
+            alpha = score;
+            line.clear();
+            line.push((from, to));
+            line.append(&mut rest);
         }
-        best_score
-    } else {
-        // Minimizing player
-        let mut best_score = i32::MAX;
-        for (from, to) in all_valid_moves {
-            let mut new_game_state = game.clone();
-            let _ = new_game_state.make_move(from, to);
-            let score = minimax(&new_game_state, depth - 1, true);
-            best_score = best_score.min(score);
+        if alpha >= beta {
+            break; // Cut-off: the opponent won't allow this line
         }
-        best_score
     }
+
+    // A score computed after the deadline is meaningless, so don't keep it
+    if search.use_tt && !search.timed_out {
+        let bound = if best_score <= original_alpha {
+            Bound::Upper
+        } else if best_score >= original_beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        search.tt.insert(
+            key,
+            TtEntry {
+                depth,
+                score: best_score,
+                bound,
+                best: line.first().copied(),
+            },
+        );
+    }
+    best_score
 }
 
-/// Public function to find the best move for the AI.
-pub fn find_best_move(game: &Game) -> Option<(Position, Position)> {
-    let mut best_move = None;
-    let mut best_score = i32::MIN;
+/// Writes to `line` the moves the transposition table holds from `game` on, for up
+/// to `depth` plies: the line below a position whose score was reused rather than
+/// searched again.
+fn stored_line(search: &Search, game: &mut Game, depth: u8, line: &mut Line) {
+    let mut tokens = Vec::new();
+    while tokens.len() < usize::from(depth) && game.status == GameStatus::Ongoing {
+        let Some((from, to)) = search
+            .tt
+            .get(&game.position_key())
+            .and_then(|entry| entry.best)
+            .filter(|&(from, to)| game.check_move(from, to).is_ok())
+        else {
+            break;
+        };
+        tokens.push(game.apply_move(from, to));
+        line.push((from, to));
+    }
+    for token in tokens.into_iter().rev() {
+        game.undo_with(token);
+    }
+}
+
+/// Searches every root move for `search.player` and returns the best one with its score.
+/// Ties go to a random move when the search has an RNG, otherwise to the first one searched.
+fn search_root(search: &mut Search, game: &Game, depth: u8) -> Option<(Position, Position, i32)> {
+    search_root_within(search, game, depth, -INFINITY, INFINITY)
+}
 
-    let mut all_valid_moves = Vec::new();
-    for r in 0..7 {
-        for c in 0..7 {
-            if game.board[r][c] == Some(Player::P2) {
-                let from_pos = Position { row: r, col: c };
-                let valid_moves = game.get_valid_moves_for_piece(from_pos);
-                for to_pos in valid_moves {
-                    all_valid_moves.push((from_pos, to_pos));
+/// Same as `search_root`, but only looks for scores between `alpha` and `beta`.
+/// The returned score is exact when it lies strictly inside the window; otherwise
+/// it is only a bound, and the move may not be the best one.
+fn search_root_within(
+    search: &mut Search,
+    game: &Game,
+    depth: u8,
+    alpha: i32,
+    beta: i32,
+) -> Option<(Position, Position, i32)> {
+    let (all_valid_moves, symmetric) = root_moves(search, game);
+    if all_valid_moves.is_empty() {
+        return None;
+    }
+
+    let window = (alpha, beta);
+    let mut best_moves = if search.parallel {
+        search_root_parallel(search, game, depth, &all_valid_moves, window)
+    } else {
+        search_root_sequential(search, game, depth, &all_valid_moves, window)
+    };
+    if best_moves.is_empty() {
+        return None;
+    }
+    let (from, to, score, mut line) = match &mut search.rng {
+        None => best_moves.swap_remove(0),
+        Some(rng) => {
+            let (from, to, score, mut line) =
+                best_moves.swap_remove(rng.random_range(0..best_moves.len()));
+            // The mirror image is just as good, so it is as likely to be played
+            if symmetric && rng.random_bool(0.5) {
+                for mv in &mut line {
+                    *mv = (mirror(mv.0), mirror(mv.1));
                 }
+                (mirror(from), mirror(to), score, line)
+            } else {
+                (from, to, score, line)
             }
         }
+    };
+    if !search.timed_out {
+        line.insert(0, (from, to));
+        search.pv = line;
     }
+    Some((from, to, score))
+}
 
-    if all_valid_moves.is_empty() {
-        return None;
+/// The moves `search_root` searches, in search order, and whether the position is
+/// symmetric, in which case only one of each mirror-image pair is included.
+fn root_moves(search: &Search, game: &Game) -> (Vec<(Position, Position)>, bool) {
+    let mut moves = generate_moves(game, search.player);
+
+    // A move and its mirror image lead to mirrored positions with the same score
+    let symmetric = search.use_symmetry && is_diagonally_symmetric(game);
+    if symmetric {
+        moves.retain(|&(from, to)| {
+            let key = |from: Position, to: Position| (from.row, from.col, to.row, to.col);
+            key(from, to) <= key(mirror(from), mirror(to))
+        });
     }
 
-    const SEARCH_DEPTH: u8 = 3; // Adjust this value to change AI difficulty
-    for (from, to) in all_valid_moves {
-        let mut new_game_state = game.clone();
-        let _ = new_game_state.make_move(from, to);
-        let score = minimax(&new_game_state, SEARCH_DEPTH - 1, false);
+    if search.order_moves {
+        order_moves(game, &mut moves, search.player);
+    }
+    (moves, symmetric)
+}
+
+/// Reflects a square across the main diagonal.
+fn mirror(pos: Position) -> Position {
+    Position {
+        row: pos.col,
+        col: pos.row,
+    }
+}
+
+/// Whether `game` looks the same reflected across the main diagonal: every piece
+/// has a mirror image of the same colour, and the goals lie on the diagonal.
+fn is_diagonally_symmetric(game: &Game) -> bool {
+    let goals_on_diagonal = [Player::P1, Player::P2].into_iter().all(|player| {
+        let goal = game.get_goal_pos(player);
+        goal == mirror(goal)
+    });
+    goals_on_diagonal
+        && (0..game.size).all(|r| (0..r).all(|c| game.board[r][c] == game.board[c][r]))
+}
+
+/// Searches the root moves one after another, narrowing the window as it goes.
+/// Returns the moves sharing the best score, in search order, each with the line
+/// expected after it. When ties will be broken at random, the window stays one
+/// point wider so tied scores are exact.
+fn search_root_sequential(
+    search: &mut Search,
+    game: &Game,
+    depth: u8,
+    moves: &[(Position, Position)],
+    (mut alpha, beta): (i32, i32),
+) -> Vec<(Position, Position, i32, Line)> {
+    let mut best_moves = Vec::new();
+    let mut best_score = i32::MIN;
+
+    let mut game = game.clone();
+    for &(from, to) in moves {
+        let token = game.apply_move(from, to);
+        let floor = if search.rng.is_some() {
+            alpha.saturating_sub(1).max(-INFINITY)
+        } else {
+            alpha
+        };
+        let mut line = Vec::new();
+        let score = -negamax(
+            search,
+            &mut game,
+            depth.saturating_sub(1),
+            -beta,
+            -floor,
+            opponent(search.player),
+            &mut line,
+        );
+        game.undo_with(token);
+        alpha = alpha.max(score);
         if score > best_score {
             best_score = score;
-            best_move = Some((from, to));
+            best_moves.clear();
+        }
+        if score == best_score {
+            best_moves.push((from, to, score, line));
         }
+        // Only possible with a narrowed window: the score is too good to be exact,
+        // and searching on with an empty window would give meaningless results
+        if alpha >= beta {
+            break;
+        }
+    }
+
+    best_moves
+}
+
+/// Searches each root move on its own thread within `window`. Returns the
+/// moves sharing the best score in the order of `moves`, like the sequential search.
+fn search_root_parallel(
+    search: &mut Search,
+    game: &Game,
+    depth: u8,
+    moves: &[(Position, Position)],
+    window: (i32, i32),
+) -> Vec<(Position, Position, i32, Line)> {
+    let scored = score_moves_parallel(search, game, depth, moves, window);
+    let Some(best_score) = scored.iter().map(|&(_, _, score, _)| score).max() else {
+        return Vec::new();
+    };
+    scored
+        .into_iter()
+        .filter(|&(_, _, score, _)| score == best_score)
+        .collect()
+}
+
+/// Searches each root move on its own thread within `(alpha, beta)`, so every
+/// score inside the window is exact. Returns each move with its score and the
+/// line expected after it, in the order of `moves`.
+fn score_moves_parallel(
+    search: &mut Search,
+    game: &Game,
+    depth: u8,
+    moves: &[(Position, Position)],
+    (alpha, beta): (i32, i32),
+) -> Vec<(Position, Position, i32, Line)> {
+    let results: Vec<(i32, Line, Search)> = moves
+        .par_iter()
+        .map(|&(from, to)| {
+            let mut child = search.fork();
+            let mut new_game_state = game.clone();
+            new_game_state.apply_move(from, to);
+            let mut line = Vec::new();
+            let score = -negamax(
+                &mut child,
+                &mut new_game_state,
+                depth.saturating_sub(1),
+                -beta,
+                -alpha,
+                opponent(search.player),
+                &mut line,
+            );
+            (score, line, child)
+        })
+        .collect();
+
+    let mut scored = Vec::with_capacity(moves.len());
+    for (&(from, to), (score, line, child)) in moves.iter().zip(results) {
+        search.nodes += child.nodes;
+        search.timed_out |= child.timed_out;
+        scored.push((from, to, score, line));
     }
 
-    best_move
+    scored
+}
+
+/// How much searching `find_best_move_with_stats` may do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchLimit {
+    /// Search exactly this many plies ahead.
+    Depth(u8),
+    /// Deepen iteratively until `budget` has passed or `max_depth` is reached,
+    /// keeping the result of the last depth that finished. Depth 1 always
+    /// completes, so a legal move is found whenever one exists. Each depth first searches within `aspiration_window` points of the previous
+    /// depth's score, and again with a full window if the score falls outside;
+    /// 0 always searches with a full window.
+    Time {
+        budget: Duration,
+        max_depth: u8,
+        aspiration_window: i32,
+    },
+}
+
+/// Named strengths for clients that don't want to pick search parameters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Medium,
+    Hard,
+}
+
+/// What a `Difficulty` searches with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct DifficultySettings {
+    /// Deepest iteration of the timed search.
+    pub depth: u8,
+    /// Evaluation coefficients.
+    pub weights: EvalWeights,
+    /// Thinking time.
+    pub time_ms: u64,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard];
+
+    /// Easy looks two plies ahead and only cares about distance to the goal; Medium
+    /// is the default timed search; Hard is the same with four times the thinking time.
+    pub fn settings(self) -> DifficultySettings {
+        match self {
+            Difficulty::Easy => DifficultySettings {
+                depth: 2,
+                weights: EvalWeights {
+                    goal_distance: 1,
+                    mobility: 0,
+                    threat: 0,
+                    blocked: 0,
+                },
+                time_ms: 100,
+            },
+            Difficulty::Medium => DifficultySettings {
+                depth: MAX_ITERATIVE_DEPTH,
+                weights: EvalWeights::default(),
+                time_ms: DEFAULT_THINK_TIME_MS,
+            },
+            Difficulty::Hard => DifficultySettings {
+                depth: MAX_ITERATIVE_DEPTH,
+                weights: EvalWeights::default(),
+                time_ms: 4 * DEFAULT_THINK_TIME_MS,
+            },
+        }
+    }
+}
+
+/// Measurements from a single search, for profiling.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SearchStats {
+    /// Number of `negamax` calls made.
+    pub nodes: u64,
+    /// Wall-clock time the whole search took.
+    #[serde(rename = "elapsed_ms", serialize_with = "serialize_millis")]
+    pub elapsed: Duration,
+    /// Depth of the deepest search that ran to completion.
+    pub depth: u8,
+}
+
+fn serialize_millis<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
+
+/// Public function to find the best move for the AI playing as `player`,
+/// looking `depth` plies ahead (including the move itself) and judging
+/// positions with `metric` and `weights`.
+/// Also returns the minimax score of that move; positive means `player` is ahead.
+/// Of several equally good moves, the first one searched is returned.
+pub fn find_best_move(
+    game: &Game,
+    player: Player,
+    depth: u8,
+    metric: DistanceMetric,
+    weights: EvalWeights,
+) -> Option<(Position, Position, i32)> {
+    let mut search = Search::new(player, metric, weights);
+    run_search(&mut search, game, SearchLimit::Depth(depth)).0
+}
+
+/// Same as `find_best_move`, or an iterative deepening search when `limit` is a
+/// time budget, but also reports how much work the search did.
+pub fn find_best_move_with_stats(
+    game: &Game,
+    player: Player,
+    limit: SearchLimit,
+    metric: DistanceMetric,
+    weights: EvalWeights,
+) -> (Option<(Position, Position, i32)>, SearchStats) {
+    run_search(&mut Search::new(player, metric, weights), game, limit)
+}
+
+/// Preferred moves for known positions, keyed by `Game::to_code`, played
+/// instead of searching.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct OpeningBook {
+    moves: HashMap<String, MoveRequest>,
+}
+
+impl OpeningBook {
+    /// Reads a book from a JSON file mapping position codes to moves, written
+    /// in the same forms POST /move accepts, e.g. `{ "<code>": { "notation": "d1-c1" } }`.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read opening book {}: {}", path, e))?;
+        serde_json::from_str(&json)
+            .map_err(|e| format!("Could not parse opening book {}: {}", path, e))
+    }
+
+    /// The booked move for `player` in `game`, if there is one and it is legal.
+    fn lookup(&self, game: &Game, player: Player) -> Option<MoveRequest> {
+        if game.current_player != player || game.status != GameStatus::Ongoing {
+            return None;
+        }
+        let mv = *self.moves.get(&game.to_code())?;
+        let own_piece = game
+            .board
+            .get(mv.from.row)
+            .and_then(|row| row.get(mv.from.col))
+            .is_some_and(|&square| square == Some(player));
+        (own_piece && game.get_valid_moves_for_piece(mv.from).contains(&mv.to)).then_some(mv)
+    }
+}
+
+/// Same as `find_best_move_with_stats`, but picks uniformly at random among the
+/// moves sharing the best score, so the AI doesn't always answer the same way.
+/// The same `seed` always gives the same move for the same search.
+/// A position found in `book` is answered with the booked move, without searching;
+/// its score is reported as 0 since it wasn't evaluated.
+pub fn find_best_move_seeded(
+    game: &Game,
+    player: Player,
+    limit: SearchLimit,
+    metric: DistanceMetric,
+    weights: EvalWeights,
+    seed: u64,
+    book: Option<&OpeningBook>,
+) -> (Option<(Position, Position, i32)>, SearchStats) {
+    let (best_move, stats, _) =
+        find_best_move_with_pv(game, player, limit, metric, weights, seed, book);
+    (best_move, stats)
+}
+
+/// Same as `find_best_move_seeded`, but also returns the principal variation: the
+/// line of play the search expects, starting with the chosen move. It comes from
+/// the search that chose the move, so it is as deep as the deepest completed
+/// search, or deeper where that looked further at a winning threat, unless the
+/// game ends first. A booked move is a line of its own.
+pub fn find_best_move_with_pv(
+    game: &Game,
+    player: Player,
+    limit: SearchLimit,
+    metric: DistanceMetric,
+    weights: EvalWeights,
+    seed: u64,
+    book: Option<&OpeningBook>,
+) -> (
+    Option<(Position, Position, i32)>,
+    SearchStats,
+    Vec<MoveRequest>,
+) {
+    if let Some(mv) = book.and_then(|book| book.lookup(game, player)) {
+        let stats = SearchStats {
+            nodes: 0,
+            elapsed: Duration::ZERO,
+            depth: 0,
+        };
+        return (Some((mv.from, mv.to, 0)), stats, vec![mv]);
+    }
+
+    let mut search = Search::new(player, metric, weights);
+    search.rng = Some(StdRng::seed_from_u64(seed));
+    let (best_move, stats) = run_search(&mut search, game, limit);
+    let pv = search
+        .pv
+        .into_iter()
+        .map(|(from, to)| MoveRequest { from, to })
+        .collect();
+    (best_move, stats, pv)
+}
+
+/// Picks a move for `player` without searching, for tutorial opponents.
+/// `Strategy::Random` picks any legal move, chosen with `seed`; `Strategy::Worst`
+/// the move after which `evaluate` scores the position lowest for `player`, and
+/// `Strategy::Minimax` the one it scores highest, i.e. a one-ply search. The
+/// score is `evaluate`'s for the position after the move.
+pub fn find_move_by_strategy(
+    game: &Game,
+    player: Player,
+    strategy: Strategy,
+    metric: DistanceMetric,
+    weights: EvalWeights,
+    seed: u64,
+) -> (Option<(Position, Position, i32)>, SearchStats) {
+    let start = Instant::now();
+    let moves = generate_moves(game, player);
+    let score = |&(from, to): &(Position, Position)| {
+        let mut after = game.clone();
+        after.apply_move(from, to);
+        evaluate(&after, player, metric, weights)
+    };
+
+    let chosen = match strategy {
+        Strategy::Random => moves.choose(&mut StdRng::seed_from_u64(seed)).copied(),
+        Strategy::Worst => moves.iter().copied().min_by_key(score),
+        Strategy::Minimax => moves.iter().copied().max_by_key(score),
+    };
+    let stats = SearchStats {
+        nodes: 0,
+        elapsed: start.elapsed(),
+        depth: 1,
+    };
+    (chosen.map(|mv| (mv.0, mv.1, score(&mv))), stats)
+}
+
+/// Scores every legal move for `player`, searching `depth` plies ahead like
+/// `find_best_move`, and returns them best first. Unlike the search for a single
+/// move, every score is exact rather than just a bound. Moves with equal scores
+/// keep search order, so the first one is the move `find_best_move` would pick.
+pub fn score_root_moves(
+    game: &Game,
+    player: Player,
+    depth: u8,
+    metric: DistanceMetric,
+    weights: EvalWeights,
+) -> Vec<(Position, Position, i32)> {
+    let mut search = Search::new(player, metric, weights);
+    let (moves, symmetric) = root_moves(&search, game);
+    let window = (-INFINITY, INFINITY);
+    let mut scored: Vec<_> = score_moves_parallel(&mut search, game, depth, &moves, window)
+        .into_iter()
+        .map(|(from, to, score, _)| (from, to, score))
+        .collect();
+
+    // Mirror images were skipped, but still deserve a place in the list
+    if symmetric {
+        for i in 0..scored.len() {
+            let (from, to, score) = scored[i];
+            if (mirror(from), mirror(to)) != (from, to) {
+                scored.push((mirror(from), mirror(to), score));
+            }
+        }
+    }
+
+    scored.sort_by_key(|&(_, _, score)| std::cmp::Reverse(score));
+    scored
+}
+
+/// Runs `search` on `game` within `limit`.
+fn run_search(
+    search: &mut Search,
+    game: &Game,
+    limit: SearchLimit,
+) -> (Option<(Position, Position, i32)>, SearchStats) {
+    let start = Instant::now();
+
+    let (best_move, depth) = match limit {
+        SearchLimit::Depth(depth) => (search_root(search, game, depth), depth),
+        SearchLimit::Time {
+            budget,
+            max_depth,
+            aspiration_window,
+        } => iterative_deepening(search, game, start + budget, max_depth, aspiration_window),
+    };
+
+    let stats = SearchStats {
+        nodes: search.nodes,
+        elapsed: start.elapsed(),
+        depth,
+    };
+    (best_move, stats)
+}
+
+/// Runs ever deeper searches, up to `max_depth`, until `deadline`, returning the
+/// best move from the deepest completed one along with that depth.
+fn iterative_deepening(
+    search: &mut Search,
+    game: &Game,
+    deadline: Instant,
+    max_depth: u8,
+    aspiration_window: i32,
+) -> (Option<(Position, Position, i32)>, u8) {
+    let mut best_move = search_root(search, game, 1);
+    let mut completed_depth = 1;
+
+    for depth in 2..=max_depth {
+        // A forced win or loss won't change with a deeper search
+        let previous_score = match best_move {
+            None | Some((_, _, 1000 | -1000)) => break,
+            Some((_, _, score)) => score,
+        };
+        if Instant::now() >= deadline {
+            break;
+        }
+
+        search.deadline = Some(deadline);
+        let result = aspiration_search(search, game, depth, previous_score, aspiration_window);
+        if search.timed_out {
+            break;
+        }
+        best_move = result;
+        completed_depth = depth;
+    }
+
+    (best_move, completed_depth)
+}
+
+/// Searches `depth` plies within `window` points of `guess`, which usually
+/// prunes more than a full window. If the score lands outside, the guess was
+/// wrong and the move can't be trusted, so the depth is searched again in full.
+fn aspiration_search(
+    search: &mut Search,
+    game: &Game,
+    depth: u8,
+    guess: i32,
+    window: i32,
+) -> Option<(Position, Position, i32)> {
+    if window > 0 {
+        let alpha = guess.saturating_sub(window).max(-INFINITY);
+        let beta = guess.saturating_add(window);
+        let result = search_root_within(search, game, depth, alpha, beta);
+        match result {
+            Some((_, _, score)) if score <= alpha || score >= beta => {}
+            _ => return result,
+        }
+        if search.timed_out {
+            return result;
+        }
+    }
+    search_root(search, game, depth)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::game::{GameConfig, WinReason, DEFAULT_BOARD_SIZE};
+
+    // Depth the AI used before the timed search became the default
+    const DEFAULT_SEARCH_DEPTH: u8 = 3;
 
     fn setup_test_game() -> Game {
         let mut game = Game::new();
-        game.board = [[None; 7]; 7];
+        game.set_board(vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE]);
         game.status = GameStatus::Ongoing;
         game
     }
@@ -137,29 +1030,385 @@ mod tests {
     #[test]
     fn test_evaluate_win_condition() {
         let mut game = setup_test_game();
-        game.status = GameStatus::Won(Player::P2);
-        assert_eq!(evaluate(&game), 1000);
+        game.status = GameStatus::Won(Player::P2, WinReason::ReachedGoal);
+        assert_eq!(
+            evaluate(
+                &game,
+                Player::P2,
+                DistanceMetric::Manhattan,
+                EvalWeights::default()
+            ),
+            1000
+        );
+        assert_eq!(
+            evaluate(
+                &game,
+                Player::P1,
+                DistanceMetric::Manhattan,
+                EvalWeights::default()
+            ),
+            -1000
+        );
+
+        game.status = GameStatus::Won(Player::P1, WinReason::NoMovesLeft);
+        assert_eq!(
+            evaluate(
+                &game,
+                Player::P2,
+                DistanceMetric::Manhattan,
+                EvalWeights::default()
+            ),
+            -1000
+        );
+        assert_eq!(
+            evaluate(
+                &game,
+                Player::P1,
+                DistanceMetric::Manhattan,
+                EvalWeights::default()
+            ),
+            1000
+        );
 
-        game.status = GameStatus::Won(Player::P1);
-        assert_eq!(evaluate(&game), -1000);
+        game.status = GameStatus::Draw;
+        assert_eq!(
+            evaluate(
+                &game,
+                Player::P2,
+                DistanceMetric::Manhattan,
+                EvalWeights::default()
+            ),
+            0
+        );
+        assert_eq!(
+            evaluate(
+                &game,
+                Player::P1,
+                DistanceMetric::Manhattan,
+                EvalWeights::default()
+            ),
+            0
+        );
     }
 
     #[test]
     fn test_evaluate_positional_score() {
         let mut game = setup_test_game();
 
-        game.board[5][6] = Some(Player::P2);
-        assert_eq!(evaluate(&game), (5 + 6) as i32);
+        // P2 at (5,6) is 11 squares away from its target (0,0)
+        game.set_square(Position { row: 5, col: 6 }, Some(Player::P2));
+        assert_eq!(
+            evaluate(
+                &game,
+                Player::P2,
+                DistanceMetric::Manhattan,
+                EvalWeights::default()
+            ),
+            -(5 + 6)
+        );
+        assert_eq!(
+            evaluate(
+                &game,
+                Player::P1,
+                DistanceMetric::Manhattan,
+                EvalWeights::default()
+            ),
+            5 + 6
+        );
 
-        game.board[1][0] = Some(Player::P1);
-        assert_eq!(evaluate(&game), (5 + 6) as i32 - ((6 - 1) + (6 - 0)) as i32);
+        // P1 at (2,0) is 10 squares away from its target (6,6)
+        game.set_square(Position { row: 2, col: 0 }, Some(Player::P1));
+        assert_eq!(
+            evaluate(
+                &game,
+                Player::P2,
+                DistanceMetric::Manhattan,
+                EvalWeights::default()
+            ),
+            -(5 + 6) + ((6 - 2) + 6)
+        );
+        assert_eq!(
+            evaluate(
+                &game,
+                Player::P1,
+                DistanceMetric::Manhattan,
+                EvalWeights::default()
+            ),
+            (5 + 6) - ((6 - 2) + 6)
+        );
     }
 
     #[test]
-    fn test_minimax_base_case_depth_zero() {
-        let game = setup_test_game();
-        let score = minimax(&game, 0, true);
-        assert_eq!(score, evaluate(&game));
+    fn test_evaluate_distance_near_far_corner() {
+        let mut game = setup_test_game();
+
+        // P1 at (5,5) is 2 squares away from its target (6,6)
+        game.set_square(Position { row: 5, col: 5 }, Some(Player::P1));
+        assert_eq!(
+            evaluate(
+                &game,
+                Player::P2,
+                DistanceMetric::Manhattan,
+                EvalWeights::default()
+            ),
+            2
+        );
+        assert_eq!(
+            evaluate(
+                &game,
+                Player::P1,
+                DistanceMetric::Manhattan,
+                EvalWeights::default()
+            ),
+            -2
+        );
+
+        // P2 at (6,3) is 9 squares away from its target (0,0); it stays clear
+        // of P1 so neither side has a one-move threat
+        game.set_square(Position { row: 6, col: 3 }, Some(Player::P2));
+        assert_eq!(
+            evaluate(
+                &game,
+                Player::P2,
+                DistanceMetric::Manhattan,
+                EvalWeights::default()
+            ),
+            2 - (6 + 3)
+        );
+    }
+
+    #[test]
+    fn test_chebyshev_rates_diagonal_piece_closer() {
+        let mut game = setup_test_game();
+
+        // P1 at (3,3) sits on the diagonal to (6,6): three diagonal steps,
+        // but six squares by Manhattan distance
+        game.set_square(Position { row: 3, col: 3 }, Some(Player::P1));
+        let manhattan = evaluate(
+            &game,
+            Player::P1,
+            DistanceMetric::Manhattan,
+            EvalWeights::default(),
+        );
+        let chebyshev = evaluate(
+            &game,
+            Player::P1,
+            DistanceMetric::Chebyshev,
+            EvalWeights::default(),
+        );
+        assert_eq!(manhattan, -6);
+        assert_eq!(chebyshev, -3);
+        assert!(chebyshev > manhattan);
+    }
+
+    #[test]
+    fn test_mobility_breaks_tie_between_equally_distant_moves() {
+        let mut game = setup_test_game();
+        game.set_square(Position { row: 2, col: 2 }, Some(Player::P1));
+        game.set_square(Position { row: 1, col: 3 }, Some(Player::P1));
+        game.set_square(Position { row: 6, col: 0 }, Some(Player::P2));
+        game.set_square(Position { row: 5, col: 0 }, Some(Player::P2));
+
+        // Both moves leave (1,3)'s piece 7 squares from (6,6), but only the
+        // first keeps it next to (2,2) so that P1 can still move afterwards
+        let mut together = game.clone();
+        together
+            .make_move(Position { row: 1, col: 3 }, Position { row: 2, col: 3 })
+            .unwrap();
+        let mut apart = game.clone();
+        apart
+            .make_move(Position { row: 1, col: 3 }, Position { row: 1, col: 4 })
+            .unwrap();
+
+        let difference = evaluate(
+            &together,
+            Player::P1,
+            DistanceMetric::Manhattan,
+            EvalWeights::default(),
+        ) - evaluate(
+            &apart,
+            Player::P1,
+            DistanceMetric::Manhattan,
+            EvalWeights::default(),
+        );
+        assert!(difference > 0);
+        assert_eq!(
+            difference,
+            EvalWeights::default().mobility
+                * (mobility(&together, Player::P1) - mobility(&apart, Player::P1))
+        );
+    }
+
+    #[test]
+    fn test_weights_change_the_chosen_move() {
+        let mut game = setup_test_game();
+        game.set_square(Position { row: 2, col: 2 }, Some(Player::P1));
+        game.set_square(Position { row: 1, col: 3 }, Some(Player::P1));
+        game.set_square(Position { row: 6, col: 0 }, Some(Player::P2));
+        game.set_square(Position { row: 5, col: 0 }, Some(Player::P2));
+
+        let runner = EvalWeights {
+            goal_distance: 1,
+            mobility: 0,
+            threat: 0,
+            blocked: 0,
+        };
+        let hoarder = EvalWeights {
+            goal_distance: 0,
+            mobility: 1,
+            threat: 0,
+            blocked: 0,
+        };
+        let (from_a, to_a, _) =
+            find_best_move(&game, Player::P1, 1, DistanceMetric::Manhattan, runner).unwrap();
+        let (from_b, to_b, _) =
+            find_best_move(&game, Player::P1, 1, DistanceMetric::Manhattan, hoarder).unwrap();
+        assert_ne!((from_a, to_a), (from_b, to_b));
+    }
+
+    #[test]
+    fn test_evaluate_penalizes_a_blocked_piece() {
+        // The same pieces, except that Player 1's piece at (4,3) or (2,5) is the
+        // same distance from (6,6), but only at (4,3) is it in the way of (3,2)
+        let board = |second: Position| {
+            let mut game = setup_test_game();
+            game.set_square(Position { row: 3, col: 2 }, Some(Player::P1));
+            game.set_square(second, Some(Player::P1));
+            game.set_square(Position { row: 1, col: 1 }, Some(Player::P2));
+            game
+        };
+        let blocked = board(Position { row: 4, col: 3 });
+        let open = board(Position { row: 2, col: 5 });
+        assert!(is_blocked_towards_goal(
+            &blocked,
+            Position { row: 3, col: 2 },
+            Player::P1
+        ));
+
+        let weights = EvalWeights {
+            mobility: 0,
+            threat: 0,
+            ..EvalWeights::default()
+        };
+        let score =
+            |game: &Game, weights| evaluate(game, Player::P1, DistanceMetric::Manhattan, weights);
+        assert_eq!(
+            score(&blocked, weights),
+            score(&open, weights) - weights.blocked
+        );
+        assert!(score(&blocked, weights) < score(&open, weights));
+        assert!(
+            evaluate(&blocked, Player::P2, DistanceMetric::Manhattan, weights)
+                > evaluate(&open, Player::P2, DistanceMetric::Manhattan, weights)
+        );
+
+        // Without the penalty the two look the same
+        let unweighted = EvalWeights {
+            blocked: 0,
+            ..weights
+        };
+        assert_eq!(score(&blocked, unweighted), score(&open, unweighted));
+
+        // With two neighbours, the piece at (3,2) moves two squares, so it is just as
+        // blocked by a piece at (5,4) while (4,3) is free. One at (4,5), as far from
+        // (6,6), is out of its way.
+        let board = |other: Position| {
+            let mut game = setup_test_game();
+            for square in [(3, 2), (2, 2), (3, 1)] {
+                game.set_square(
+                    Position {
+                        row: square.0,
+                        col: square.1,
+                    },
+                    Some(Player::P1),
+                );
+            }
+            game.set_square(other, Some(Player::P1));
+            game.set_square(Position { row: 1, col: 1 }, Some(Player::P2));
+            game
+        };
+        let piece = Position { row: 3, col: 2 };
+        let blocked = board(Position { row: 5, col: 4 });
+        let open = board(Position { row: 4, col: 5 });
+        assert!(blocked.is_ray_clear(piece, (1, 1), 1));
+        assert!(is_blocked_towards_goal(&blocked, piece, Player::P1));
+        assert!(!is_blocked_towards_goal(&open, piece, Player::P1));
+        assert_eq!(
+            score(&blocked, weights),
+            score(&open, weights) - weights.blocked
+        );
+    }
+
+    #[test]
+    fn test_evaluate_penalizes_open_threat() {
+        let mut game = setup_test_game();
+        game.current_player = Player::P2;
+
+        // P1 at (3,3) has three neighbors, so it can slide straight onto (6,6)
+        game.set_square(Position { row: 3, col: 3 }, Some(Player::P1));
+        game.set_square(Position { row: 2, col: 2 }, Some(Player::P2));
+        game.set_square(Position { row: 2, col: 4 }, Some(Player::P2));
+        game.set_square(Position { row: 4, col: 2 }, Some(Player::P2));
+        assert!(has_winning_move(&game, Player::P1));
+        // The distance term is negative for P2 here, so only mobility can offset the penalty
+        assert!(
+            evaluate(
+                &game,
+                Player::P2,
+                DistanceMetric::Manhattan,
+                EvalWeights::default()
+            ) <= -EvalWeights::default().threat
+                + EvalWeights::default().mobility * mobility(&game, Player::P2)
+        );
+    }
+
+    #[test]
+    fn test_ai_blocks_immediate_winning_threat() {
+        let mut game = setup_test_game();
+        game.current_player = Player::P2;
+        game.set_square(Position { row: 3, col: 3 }, Some(Player::P1));
+        game.set_square(Position { row: 2, col: 2 }, Some(Player::P2));
+        game.set_square(Position { row: 2, col: 4 }, Some(Player::P2));
+        game.set_square(Position { row: 4, col: 2 }, Some(Player::P2));
+
+        // Even a one-ply search must defuse the threat
+        let (from, to, _) = find_best_move(
+            &game,
+            Player::P2,
+            1,
+            DistanceMetric::Manhattan,
+            EvalWeights::default(),
+        )
+        .unwrap();
+        game.make_move(from, to).unwrap();
+        assert!(!has_winning_move(&game, Player::P1));
+    }
+
+    #[test]
+    fn test_negamax_base_case_depth_zero() {
+        let mut game = setup_test_game();
+        let score = negamax(
+            &mut Search::new(
+                Player::P2,
+                DistanceMetric::Manhattan,
+                EvalWeights::default(),
+            ),
+            &mut game,
+            0,
+            -INFINITY,
+            INFINITY,
+            Player::P2,
+            &mut Vec::new(),
+        );
+        assert_eq!(
+            score,
+            evaluate(
+                &game,
+                Player::P2,
+                DistanceMetric::Manhattan,
+                EvalWeights::default()
+            )
+        );
     }
 
     #[test]
@@ -168,20 +1417,732 @@ mod tests {
         game.current_player = Player::P2;
 
         // P1 is one move away from winning
-        game.board[5][5] = Some(Player::P1);
-        game.board[6][6] = None;
+        game.set_square(Position { row: 5, col: 5 }, Some(Player::P1));
+        game.set_square(Position { row: 6, col: 6 }, None);
 
         // P2 can move to block P1's winning move.
-        game.board[6][5] = Some(Player::P2);
+        game.set_square(Position { row: 6, col: 5 }, Some(Player::P2));
 
         // The piece at (6,5) has a neighbor at (5,5), which gives it a move distance of 1.
         // It can move to (5,4) to block the opponent.
 
-        let best_move_for_ai = find_best_move(&game);
+        let best_move_for_ai = find_best_move(
+            &game,
+            Player::P2,
+            DEFAULT_SEARCH_DEPTH,
+            DistanceMetric::Manhattan,
+            EvalWeights::default(),
+        )
+        .map(|(from, to, _)| (from, to));
 
         assert_eq!(
             best_move_for_ai,
             Some((Position { row: 6, col: 5 }, Position { row: 5, col: 4 }))
         );
     }
+
+    /// Plain minimax without pruning, kept as a reference for the alpha-beta search.
+    fn full_minimax(game: &Game, depth: u8, is_maximizing_player: bool) -> i32 {
+        if depth == 0 || !matches!(game.status, GameStatus::Ongoing) {
+            return evaluate(
+                game,
+                Player::P2,
+                DistanceMetric::Manhattan,
+                EvalWeights::default(),
+            );
+        }
+        let player_to_move = if is_maximizing_player {
+            Player::P2
+        } else {
+            Player::P1
+        };
+        let mut scores = Vec::new();
+        for r in 0..game.size {
+            for c in 0..game.size {
+                if game.board[r][c] == Some(player_to_move) {
+                    let from = Position { row: r, col: c };
+                    for to in game.get_valid_moves_for_piece(from) {
+                        let mut next = game.clone();
+                        let _ = next.make_move(from, to);
+                        scores.push(full_minimax(&next, depth - 1, !is_maximizing_player));
+                    }
+                }
+            }
+        }
+        match (scores.is_empty(), is_maximizing_player) {
+            (true, true) => -1000,
+            (true, false) => 1000,
+            (false, true) => *scores.iter().max().unwrap(),
+            (false, false) => *scores.iter().min().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_alpha_beta_matches_full_search() {
+        let mut game = Game::new();
+        game.make_move(Position { row: 0, col: 3 }, Position { row: 0, col: 2 })
+            .unwrap();
+
+        let mut best_move = None;
+        let mut best_score = i32::MIN;
+        for r in 0..game.size {
+            for c in 0..game.size {
+                if game.board[r][c] == Some(Player::P2) {
+                    let from = Position { row: r, col: c };
+                    for to in game.get_valid_moves_for_piece(from) {
+                        let mut next = game.clone();
+                        let _ = next.make_move(from, to);
+                        let score = full_minimax(&next, 2, false);
+                        if score > best_score {
+                            best_score = score;
+                            best_move = Some((from, to, score));
+                        }
+                    }
+                }
+            }
+        }
+
+        assert!(best_move.is_some());
+        assert_eq!(
+            find_best_move(
+                &game,
+                Player::P2,
+                DEFAULT_SEARCH_DEPTH,
+                DistanceMetric::Manhattan,
+                EvalWeights::default(),
+            ),
+            best_move
+        );
+    }
+
+    #[test]
+    fn test_ai_vs_ai_game_terminates() {
+        let mut game = Game::new();
+
+        for _ in 0..200 {
+            if game.status != GameStatus::Ongoing {
+                break;
+            }
+            let (from, to, _) = find_best_move(
+                &game,
+                game.current_player,
+                DEFAULT_SEARCH_DEPTH,
+                DistanceMetric::Manhattan,
+                EvalWeights::default(),
+            )
+            .expect("an ongoing game always has a legal move");
+            game.make_move(from, to).unwrap();
+        }
+
+        assert!(matches!(game.status, GameStatus::Won(..)));
+    }
+
+    #[test]
+    fn test_depth_extremes_produce_legal_moves() {
+        for depth in [1, 4] {
+            let game = Game::new();
+            let (from, to, _) = find_best_move(
+                &game,
+                Player::P1,
+                depth,
+                DistanceMetric::Manhattan,
+                EvalWeights::default(),
+            )
+            .expect("the starting position has legal moves");
+            assert!(game.get_valid_moves_for_piece(from).contains(&to));
+        }
+    }
+
+    #[test]
+    fn test_forced_win_scores_near_max() {
+        let mut game = setup_test_game();
+        game.current_player = Player::P2;
+
+        // P2 at (1,1) has one neighbor, so it can step straight onto its target (0,0)
+        game.set_square(Position { row: 1, col: 1 }, Some(Player::P2));
+        game.set_square(Position { row: 2, col: 2 }, Some(Player::P1));
+
+        let (from, to, score) = find_best_move(
+            &game,
+            Player::P2,
+            DEFAULT_SEARCH_DEPTH,
+            DistanceMetric::Manhattan,
+            EvalWeights::default(),
+        )
+        .unwrap();
+        assert_eq!(from, Position { row: 1, col: 1 });
+        assert_eq!(to, Position { row: 0, col: 0 });
+        assert!(score >= 900, "expected a score near 1000, got {}", score);
+    }
+
+    #[test]
+    fn test_move_ordering_reduces_nodes() {
+        let mut game = Game::new();
+        game.make_move(Position { row: 0, col: 3 }, Position { row: 0, col: 2 })
+            .unwrap();
+        game.make_move(Position { row: 6, col: 3 }, Position { row: 6, col: 4 })
+            .unwrap();
+
+        // Root moves are searched with a full window in parallel, so compare sequentially
+        let mut unordered = Search::new(
+            Player::P1,
+            DistanceMetric::Manhattan,
+            EvalWeights::default(),
+        );
+        unordered.order_moves = false;
+        unordered.parallel = false;
+        search_root(&mut unordered, &game, 4);
+
+        let mut ordered = Search::new(
+            Player::P1,
+            DistanceMetric::Manhattan,
+            EvalWeights::default(),
+        );
+        ordered.parallel = false;
+        search_root(&mut ordered, &game, 4);
+
+        assert!(ordered.nodes > 0);
+        assert!(
+            ordered.nodes < unordered.nodes,
+            "ordered search visited {} nodes, unordered {}",
+            ordered.nodes,
+            unordered.nodes
+        );
+    }
+
+    #[test]
+    fn test_transposition_table_keeps_move_and_saves_nodes() {
+        let mut game = Game::new();
+        game.make_move(Position { row: 0, col: 3 }, Position { row: 0, col: 2 })
+            .unwrap();
+        game.make_move(Position { row: 6, col: 3 }, Position { row: 6, col: 4 })
+            .unwrap();
+
+        let mut without_tt = Search::new(
+            Player::P1,
+            DistanceMetric::Manhattan,
+            EvalWeights::default(),
+        );
+        without_tt.parallel = false;
+        without_tt.use_tt = false;
+        let expected = search_root(&mut without_tt, &game, 4);
+
+        let mut with_tt = Search::new(
+            Player::P1,
+            DistanceMetric::Manhattan,
+            EvalWeights::default(),
+        );
+        with_tt.parallel = false;
+        let actual = search_root(&mut with_tt, &game, 4);
+
+        assert_eq!(actual, expected);
+        assert!(
+            with_tt.nodes < without_tt.nodes,
+            "with TT {} nodes, without {}",
+            with_tt.nodes,
+            without_tt.nodes
+        );
+    }
+
+    #[test]
+    fn test_negamax_matches_previous_minimax_choices() {
+        // Moves and scores the two-branch minimax picked on these positions, with
+        // the evaluation it had, from before blocked pieces were penalized
+        let weights = EvalWeights {
+            blocked: 0,
+            ..EvalWeights::default()
+        };
+        let cases = [
+            (
+                "...1.....1.....1.....1.....2.....2.....2.....2...1",
+                3,
+                ((0, 3), (1, 4), 3),
+            ),
+            (
+                "..1......1.....1.....1.....2.....2.....2......2..1",
+                4,
+                ((0, 2), (0, 3), -3),
+            ),
+            (
+                "..1......1.....1...........21....2.....2......2..2",
+                3,
+                ((4, 5), (2, 3), 11),
+            ),
+            (
+                "..........1.....1..................2......2......1",
+                3,
+                ((1, 3), (2, 3), 1),
+            ),
+            (
+                "................2.2.....1.....2..................2",
+                3,
+                ((2, 2), (1, 3), 1000),
+            ),
+        ];
+
+        for (code, depth, ((fr, fc), (tr, tc), score)) in cases {
+            let game = Game::from_code_with_config(code, GameConfig::default()).unwrap();
+            let expected = (
+                Position { row: fr, col: fc },
+                Position { row: tr, col: tc },
+                score,
+            );
+            let actual = find_best_move(
+                &game,
+                game.current_player,
+                depth,
+                DistanceMetric::Manhattan,
+                weights,
+            );
+            assert_eq!(actual, Some(expected), "position {}", code);
+        }
+    }
+
+    #[test]
+    fn test_scored_root_moves_start_with_the_best_move() {
+        let mut game = Game::new();
+        game.make_move(Position { row: 0, col: 3 }, Position { row: 0, col: 2 })
+            .unwrap();
+
+        for depth in 1..=3 {
+            let scored = score_root_moves(
+                &game,
+                Player::P2,
+                depth,
+                DistanceMetric::Manhattan,
+                EvalWeights::default(),
+            );
+            assert_eq!(scored.len(), game.all_valid_moves(Player::P2).len());
+            assert!(scored.windows(2).all(|pair| pair[0].2 >= pair[1].2));
+
+            let best = find_best_move(
+                &game,
+                Player::P2,
+                depth,
+                DistanceMetric::Manhattan,
+                EvalWeights::default(),
+            );
+            assert_eq!(Some(scored[0]), best, "depth {}", depth);
+        }
+    }
+
+    #[test]
+    fn test_parallel_root_matches_sequential() {
+        let mut game = Game::new();
+        game.make_move(Position { row: 0, col: 3 }, Position { row: 0, col: 2 })
+            .unwrap();
+        game.make_move(Position { row: 6, col: 3 }, Position { row: 6, col: 4 })
+            .unwrap();
+
+        for depth in 1..=4 {
+            let mut sequential = Search::new(
+                Player::P1,
+                DistanceMetric::Manhattan,
+                EvalWeights::default(),
+            );
+            sequential.parallel = false;
+            let expected = search_root(&mut sequential, &game, depth);
+
+            let mut parallel = Search::new(
+                Player::P1,
+                DistanceMetric::Manhattan,
+                EvalWeights::default(),
+            );
+            let actual = search_root(&mut parallel, &game, depth);
+            assert_eq!(actual, expected, "depth {}", depth);
+        }
+    }
+
+    #[test]
+    fn test_aspiration_windows_match_full_window_search() {
+        let deadline = Instant::now() + Duration::from_secs(600);
+        for (name, game) in crate::bench::positions() {
+            let player = game.current_player;
+            for parallel in [true, false] {
+                let search = |window| {
+                    let mut search =
+                        Search::new(player, DistanceMetric::default(), EvalWeights::default());
+                    search.parallel = parallel;
+                    iterative_deepening(&mut search, &game, deadline, 4, window)
+                };
+                let full = search(0);
+                // A window of 1 fails almost every time, exercising the re-search
+                for window in [1, DEFAULT_ASPIRATION_WINDOW] {
+                    assert_eq!(search(window), full, "{} with window {}", name, window);
+                }
+            }
+        }
+    }
+
+    // An iterative deepening search as deep as it may go within `budget`
+    fn time_limit(budget: Duration) -> SearchLimit {
+        SearchLimit::Time {
+            budget,
+            max_depth: MAX_ITERATIVE_DEPTH,
+            aspiration_window: DEFAULT_ASPIRATION_WINDOW,
+        }
+    }
+
+    #[test]
+    fn test_timed_search_returns_legal_move_with_tiny_budget() {
+        let game = Game::new();
+        let (best_move, _) = find_best_move_with_stats(
+            &game,
+            Player::P1,
+            time_limit(Duration::from_millis(1)),
+            DistanceMetric::Manhattan,
+            EvalWeights::default(),
+        );
+        let (from, to, _) = best_move.unwrap();
+        assert!(game.get_valid_moves_for_piece(from).contains(&to));
+    }
+
+    #[test]
+    fn test_timed_search_finds_forced_win() {
+        let mut game = setup_test_game();
+        game.current_player = Player::P2;
+        game.set_square(Position { row: 1, col: 1 }, Some(Player::P2));
+        game.set_square(Position { row: 2, col: 2 }, Some(Player::P1));
+
+        let (best_move, _) = find_best_move_with_stats(
+            &game,
+            Player::P2,
+            time_limit(Duration::from_millis(50)),
+            DistanceMetric::Manhattan,
+            EvalWeights::default(),
+        );
+        let (_, to, score) = best_move.unwrap();
+        assert_eq!(to, Position { row: 0, col: 0 });
+        assert_eq!(score, 1000);
+    }
+
+    #[test]
+    fn test_search_stats_grow_with_depth() {
+        let game = Game::new();
+
+        let (_, shallow) = find_best_move_with_stats(
+            &game,
+            Player::P1,
+            SearchLimit::Depth(2),
+            DistanceMetric::Manhattan,
+            EvalWeights::default(),
+        );
+        let (_, deep) = find_best_move_with_stats(
+            &game,
+            Player::P1,
+            SearchLimit::Depth(4),
+            DistanceMetric::Manhattan,
+            EvalWeights::default(),
+        );
+
+        assert!(shallow.nodes > 0);
+        assert!(deep.nodes > shallow.nodes);
+        assert_eq!(shallow.depth, 2);
+        assert_eq!(deep.depth, 4);
+    }
+
+    #[test]
+    fn test_seeded_search_breaks_ties_reproducibly() {
+        // The opening position is symmetric enough to have several best replies
+        let game = Game::new();
+        let search = |seed| {
+            find_best_move_seeded(
+                &game,
+                Player::P1,
+                SearchLimit::Depth(1),
+                DistanceMetric::Manhattan,
+                EvalWeights::default(),
+                seed,
+                None,
+            )
+            .0
+            .unwrap()
+        };
+
+        let best_score = find_best_move(
+            &game,
+            Player::P1,
+            1,
+            DistanceMetric::Manhattan,
+            EvalWeights::default(),
+        )
+        .unwrap()
+        .2;
+        let mut chosen = Vec::new();
+        for seed in 0..32 {
+            let (from, to, score) = search(seed);
+            assert_eq!(score, best_score);
+            assert_eq!(search(seed), (from, to, score));
+            if !chosen.contains(&(from, to)) {
+                chosen.push((from, to));
+            }
+        }
+        assert!(chosen.len() > 1, "every seed picked {:?}", chosen);
+    }
+
+    #[test]
+    fn test_quiescence_sees_win_beyond_horizon() {
+        // Without the threat term, the evaluation can't tell that Player 2 is one
+        // move from (0,0) when the search stops, which is exactly the horizon
+        // that quiescence looks past
+        let weights = EvalWeights {
+            goal_distance: 1,
+            mobility: 0,
+            threat: 0,
+            blocked: 0,
+        };
+        let game = Game::from_code_with_config(
+            "....1....1.....1.2....1....2.....2...........2...1",
+            GameConfig::default(),
+        )
+        .unwrap();
+        let search = |quiescence_plies| {
+            let mut search = Search::new(Player::P1, DistanceMetric::Manhattan, weights);
+            search.quiescence_plies = quiescence_plies;
+            search_root(&mut search, &game, 3).unwrap()
+        };
+        // Score for Player 1 after playing the move, searched deep enough to see the end
+        let outcome = |(from, to, _): (Position, Position, i32)| {
+            let mut after = game.clone();
+            after.make_move(from, to).unwrap();
+            let mut search = Search::new(Player::P2, DistanceMetric::Manhattan, weights);
+            -negamax(
+                &mut search,
+                &mut after,
+                4,
+                -INFINITY,
+                INFINITY,
+                Player::P2,
+                &mut Vec::new(),
+            )
+        };
+
+        let plain = search(0);
+        assert_eq!(
+            (plain.0, plain.1),
+            (Position { row: 1, col: 2 }, Position { row: 3, col: 2 })
+        );
+        assert_eq!(outcome(plain), -1000);
+
+        let quiet = search(MAX_QUIESCENCE_PLIES);
+        assert_ne!(outcome(quiet), -1000);
+    }
+
+    #[test]
+    fn test_opening_book_move_is_played_verbatim() {
+        let start = Game::new();
+        let mut second = start.clone();
+        second
+            .make_move(Position { row: 0, col: 3 }, Position { row: 0, col: 2 })
+            .unwrap();
+        let path = std::env::temp_dir().join(format!("top-cap-book-{}.json", std::process::id()));
+        let json = format!(
+            r#"{{ "{}": {{ "notation": "d1-c1" }}, "{}": {{ "from": {{ "row": 6, "col": 3 }}, "to": {{ "row": 6, "col": 4 }} }} }}"#,
+            start.to_code(),
+            second.to_code()
+        );
+        std::fs::write(&path, json).unwrap();
+        let book = OpeningBook::load(path.to_str().unwrap()).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let play = |game: &Game, book: Option<&OpeningBook>| {
+            find_best_move_seeded(
+                game,
+                game.current_player,
+                SearchLimit::Depth(2),
+                DistanceMetric::Manhattan,
+                EvalWeights::default(),
+                0,
+                book,
+            )
+        };
+
+        let (best_move, stats) = play(&start, Some(&book));
+        assert_eq!(
+            best_move,
+            Some((Position { row: 0, col: 3 }, Position { row: 0, col: 2 }, 0))
+        );
+        assert_eq!(stats.nodes, 0);
+        let (best_move, _) = play(&second, Some(&book));
+        assert_eq!(
+            best_move,
+            Some((Position { row: 6, col: 3 }, Position { row: 6, col: 4 }, 0))
+        );
+
+        // Positions missing from the book are searched as usual
+        let mut third = second.clone();
+        third
+            .make_move(Position { row: 6, col: 3 }, Position { row: 6, col: 4 })
+            .unwrap();
+        let (best_move, stats) = play(&third, Some(&book));
+        assert_eq!(best_move, play(&third, None).0);
+        assert!(stats.nodes > 0);
+    }
+
+    #[test]
+    fn test_random_strategy_plays_legal_moves() {
+        let mut game = Game::new();
+        for seed in 0..40 {
+            if game.status != GameStatus::Ongoing {
+                break;
+            }
+            let player = game.current_player;
+            let (best_move, _) = find_move_by_strategy(
+                &game,
+                player,
+                Strategy::Random,
+                DistanceMetric::Manhattan,
+                EvalWeights::default(),
+                seed,
+            );
+            let (from, to, _) = best_move.unwrap();
+            assert!(game
+                .all_valid_moves(player)
+                .contains(&MoveRequest { from, to }));
+            game.make_move(from, to).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_worst_strategy_avoids_the_best_move() {
+        // P1 wins at once by stepping onto P2's goal
+        let mut board = vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE];
+        board[4][4] = Some(Player::P1);
+        board[5][5] = Some(Player::P1);
+        board[1][1] = Some(Player::P2);
+        board[2][2] = Some(Player::P2);
+        let game = Game::from_setup(board, Player::P1).unwrap();
+        let pick = |strategy| {
+            find_move_by_strategy(
+                &game,
+                Player::P1,
+                strategy,
+                DistanceMetric::Manhattan,
+                EvalWeights::default(),
+                0,
+            )
+            .0
+            .unwrap()
+        };
+
+        let (from, to, score) = find_best_move(
+            &game,
+            Player::P1,
+            DEFAULT_SEARCH_DEPTH,
+            DistanceMetric::Manhattan,
+            EvalWeights::default(),
+        )
+        .unwrap();
+        assert_eq!(pick(Strategy::Minimax), (from, to, score));
+
+        let (worst_from, worst_to, worst_score) = pick(Strategy::Worst);
+        assert_ne!((worst_from, worst_to), (from, to));
+        assert!(worst_score < score);
+        let worst = MoveRequest {
+            from: worst_from,
+            to: worst_to,
+        };
+        assert!(game.all_valid_moves(Player::P1).contains(&worst));
+    }
+
+    #[test]
+    fn test_principal_variation_can_be_played_out() {
+        let mut game = Game::new();
+        game.make_move(Position { row: 0, col: 3 }, Position { row: 0, col: 2 })
+            .unwrap();
+        let limits = [
+            SearchLimit::Depth(4),
+            SearchLimit::Time {
+                budget: Duration::from_millis(50),
+                max_depth: 4,
+                aspiration_window: DEFAULT_ASPIRATION_WINDOW,
+            },
+        ];
+
+        for limit in limits {
+            let (best_move, stats, pv) = find_best_move_with_pv(
+                &game,
+                Player::P2,
+                limit,
+                DistanceMetric::Manhattan,
+                EvalWeights::default(),
+                7,
+                None,
+            );
+            let (from, to, score) = best_move.unwrap();
+            assert_eq!(pv[0], MoveRequest { from, to });
+            assert!(pv.len() >= stats.depth as usize);
+
+            let mut line = game.clone();
+            for mv in &pv {
+                line.make_move(mv.from, mv.to)
+                    .unwrap_or_else(|e| panic!("{} in {:?}: {}", mv.to_notation(), pv, e));
+            }
+            // The line is the one the score was found along
+            let weights = EvalWeights::default();
+            assert_eq!(
+                evaluate(&line, Player::P2, DistanceMetric::Manhattan, weights),
+                score
+            );
+        }
+
+        // The line stops where the game does
+        let mut board = vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE];
+        board[4][4] = Some(Player::P1);
+        board[5][5] = Some(Player::P1);
+        board[1][1] = Some(Player::P2);
+        board[2][2] = Some(Player::P2);
+        let game = Game::from_setup(board, Player::P1).unwrap();
+        let (_, _, pv) = find_best_move_with_pv(
+            &game,
+            Player::P1,
+            SearchLimit::Depth(3),
+            DistanceMetric::Manhattan,
+            EvalWeights::default(),
+            0,
+            None,
+        );
+        assert_eq!(
+            pv,
+            [MoveRequest {
+                from: Position { row: 5, col: 5 },
+                to: Position { row: 6, col: 6 }
+            }]
+        );
+    }
+
+    #[test]
+    fn test_symmetric_opening_searches_half_the_root_moves() {
+        let game = Game::new();
+        assert!(is_diagonally_symmetric(&game));
+
+        let run = |use_symmetry| {
+            let mut search = Search::new(
+                Player::P1,
+                DistanceMetric::Manhattan,
+                EvalWeights::default(),
+            );
+            search.parallel = false;
+            search.use_symmetry = use_symmetry;
+            let best_move = search_root(&mut search, &game, 3).unwrap();
+            (best_move, search.nodes)
+        };
+        let ((from, to, score), nodes) = run(true);
+        let ((_, _, full_score), full_nodes) = run(false);
+
+        assert!(game.get_valid_moves_for_piece(from).contains(&to));
+        assert_eq!(score, full_score);
+        assert!(
+            nodes < full_nodes,
+            "with symmetry {} nodes, without {}",
+            nodes,
+            full_nodes
+        );
+
+        // One move breaks the symmetry
+        let mut game = game.clone();
+        game.make_move(Position { row: 0, col: 3 }, Position { row: 0, col: 2 })
+            .unwrap();
+        assert!(!is_diagonally_symmetric(&game));
+    }
 }
@@ -0,0 +1,100 @@
+use crate::ai::{self, DistanceMetric, EvalWeights, SearchLimit};
+use crate::game::{Game, MoveRequest, Player, Position, DEFAULT_BOARD_SIZE};
+use std::io::{self, Write};
+
+/// The positions the benchmark searches: the opening, a game a few moves in,
+/// and one where the side to move can win at once.
+pub fn positions() -> Vec<(&'static str, Game)> {
+    let opening = Game::new();
+
+    let mut midgame = Game::new();
+    for (from, to) in [
+        ((0, 3), (0, 2)),
+        ((6, 3), (6, 4)),
+        ((0, 2), (0, 3)),
+        ((6, 4), (6, 5)),
+        ((3, 0), (4, 0)),
+    ] {
+        let square = |(row, col)| Position { row, col };
+        midgame
+            .make_move(square(from), square(to))
+            .expect("benchmark moves are legal");
+    }
+
+    // Player 1's piece on (5,5) can step onto the goal at (6,6)
+    let mut board = vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE];
+    board[5][5] = Some(Player::P1);
+    board[4][4] = Some(Player::P1);
+    board[2][1] = Some(Player::P1);
+    board[0][5] = Some(Player::P2);
+    board[1][6] = Some(Player::P2);
+    board[1][4] = Some(Player::P2);
+    let near_win = Game::from_setup(board, Player::P1).expect("benchmark position is valid");
+
+    vec![
+        ("opening", opening),
+        ("midgame", midgame),
+        ("near-win", near_win),
+    ]
+}
+
+/// Searches every benchmark position at depths 1 to `max_depth` with the real
+/// engine, writing a line per search with its time and node count.
+pub fn run<W: Write>(mut output: W, max_depth: u8) -> io::Result<()> {
+    writeln!(
+        output,
+        "{:<10} {:>5} {:>10} {:>12} {:>7}  best move",
+        "position", "depth", "time (ms)", "nodes", "score"
+    )?;
+    for (name, game) in positions() {
+        for depth in 1..=max_depth {
+            let (best_move, stats) = ai::find_best_move_with_stats(
+                &game,
+                game.current_player,
+                SearchLimit::Depth(depth),
+                DistanceMetric::default(),
+                EvalWeights::default(),
+            );
+            let (notation, score) = match best_move {
+                Some((from, to, score)) => {
+                    let mv = MoveRequest { from, to };
+                    (mv.to_notation(), score.to_string())
+                }
+                None => ("-".to_string(), "-".to_string()),
+            };
+            writeln!(
+                output,
+                "{:<10} {:>5} {:>10.2} {:>12} {:>7}  {}",
+                name,
+                depth,
+                stats.elapsed.as_secs_f64() * 1000.0,
+                stats.nodes,
+                score,
+                notation
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::GameStatus;
+
+    #[test]
+    fn test_benchmark_reports_every_position_and_depth() {
+        for (name, game) in positions() {
+            assert_eq!(game.status, GameStatus::Ongoing, "{}", name);
+        }
+
+        let mut output = Vec::new();
+        run(&mut output, 2).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 1 + 3 * 2);
+        assert!(lines[1].starts_with("opening"));
+        assert!(lines.last().unwrap().starts_with("near-win"));
+        assert!(lines.last().unwrap().ends_with("f6-g7"));
+    }
+}
@@ -1,149 +1,1414 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 // --- DATA STRUCTURES ---
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-pub enum Player {
-    P1, // Represented by 🔴
-    P2, // Represented by 🔵
+// A side in the game, numbered from 0 in turn order. Serialized as "P1", "P2" and
+// so on. Also read from "red"/"blue"/"green"/"yellow" or 1 to 4, in any case.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "RawPlayer")]
+pub struct Player(u8);
+
+// Most players a game can have, one per corner of the board
+pub const MAX_PLAYERS: u8 = 4;
+
+// Players in a game when its config doesn't say
+pub const DEFAULT_PLAYERS: u8 = 2;
+
+// How each player's pieces are drawn, and what they may be called instead of P1..P4
+const PLAYER_EMOJI: [&str; MAX_PLAYERS as usize] = ["🔴", "🔵", "🟢", "🟡"];
+const PLAYER_COLORS: [&str; MAX_PLAYERS as usize] = ["red", "blue", "green", "yellow"];
+
+// The accepted spellings of a player, before they are matched.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawPlayer {
+    Name(String),
+    Number(u64),
+}
+
+impl TryFrom<RawPlayer> for Player {
+    type Error = String;
+
+    fn try_from(raw: RawPlayer) -> Result<Self, Self::Error> {
+        let name = match raw {
+            RawPlayer::Name(name) => name.to_ascii_lowercase(),
+            RawPlayer::Number(number) => number.to_string(),
+        };
+        Player::all(MAX_PLAYERS)
+            .find(|player| {
+                let number = (player.0 + 1).to_string();
+                name == format!("p{}", number)
+                    || name == number
+                    || name == PLAYER_COLORS[player.index()]
+            })
+            .ok_or_else(|| {
+                format!(
+                    "Unknown player '{}', expected P1-P4, red/blue/green/yellow or 1-4.",
+                    name
+                )
+            })
+    }
+}
+
+impl Serialize for Player {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:?}", self))
+    }
+}
+
+impl fmt::Debug for Player {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "P{}", self.0 + 1)
+    }
 }
 
 impl Player {
-    // Returns the opponent player
-    pub fn opponent(&self) -> Player {
-        match self {
-            Player::P1 => Player::P2,
-            Player::P2 => Player::P1,
-        }
+    pub const P1: Player = Player(0); // Represented by 🔴
+    pub const P2: Player = Player(1); // Represented by 🔵
+    pub const P3: Player = Player(2); // Represented by 🟢
+    pub const P4: Player = Player(3); // Represented by 🟡
+
+    // The players of a game with `count` players, in turn order
+    pub fn all(count: u8) -> impl Iterator<Item = Player> {
+        (0..count).map(Player)
+    }
+
+    // Position in turn order, from 0
+    pub fn index(self) -> usize {
+        usize::from(self.0)
+    }
+
+    // Whoever plays after this player in a game with `count` players
+    pub fn next(self, count: u8) -> Player {
+        Player((self.0 + 1) % count)
+    }
+
+    // Whoever played before this player in a game with `count` players
+    pub fn previous(self, count: u8) -> Player {
+        Player((self.0 + count - 1) % count)
+    }
+
+    // How the player's pieces are drawn in emoji
+    pub fn emoji(self) -> &'static str {
+        PLAYER_EMOJI[self.index()]
     }
 }
 
+// How a game was won
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WinReason {
+    ReachedGoal, // A piece reached the opponent's base
+    NoMovesLeft, // The opponent was left without a legal move
+    TimeForfeit, // The opponent's clock ran out
+    Resignation, // The opponent conceded
+}
+
 // To represent the state of the game
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum GameStatus {
     Ongoing,
-    Won(Player),
+    Won(Player, WinReason),
+    Draw,
 }
 
-// Coordinates on the board (0-6)
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+// Coordinates on the board (0 to size - 1). Squares are ordered row-major: by row,
+// then by column, which is the canonical order boards are visited in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Position {
     pub row: usize,
     pub col: usize,
 }
 
-// This is the payload the client sends to make a move.
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+impl Position {
+    // The square at (`row`, `col`), if it lies on a `size` x `size` board
+    pub fn new(row: usize, col: usize, size: usize) -> Option<Position> {
+        (row < size && col < size).then_some(Position { row, col })
+    }
+
+    // The unit step, each part -1, 0 or 1, that leads from this square towards
+    // `other` when the two share a row, column or diagonal. None when they don't,
+    // or when they are the same square.
+    pub fn alignment(&self, other: &Position) -> Option<(isize, isize)> {
+        let dr = other.row as isize - self.row as isize;
+        let dc = other.col as isize - self.col as isize;
+        let aligned = dr == 0 || dc == 0 || dr.abs() == dc.abs();
+        (aligned && (dr, dc) != (0, 0)).then_some((dr.signum(), dc.signum()))
+    }
+
+    // The unit step, each part -1, 0 or 1, that heads most directly from this square
+    // towards `other`, whether or not they share a line. None when they are the same
+    // square.
+    pub fn direction_to(&self, other: &Position) -> Option<(isize, isize)> {
+        let dr = other.row as isize - self.row as isize;
+        let dc = other.col as isize - self.col as isize;
+        ((dr, dc) != (0, 0)).then_some((dr.signum(), dc.signum()))
+    }
+
+    // Formats the square in algebraic notation: column as a file letter from `a`,
+    // row as a rank number from 1, so (0,0) is "a1" and (3,2) is "c4". A square off
+    // even the largest board has no notation and is written as "(row,col)" instead.
+    pub fn to_notation(self) -> String {
+        match Position::new(self.row, self.col, MAX_BOARD_SIZE) {
+            Some(_) => format!("{}{}", (b'a' + self.col as u8) as char, self.row + 1),
+            None => format!("({},{})", self.row, self.col),
+        }
+    }
+
+    // Parses a square written by `to_notation`
+    pub fn from_notation(notation: &str) -> Result<Position, String> {
+        let invalid = || format!("Invalid square '{}'.", notation);
+        let mut chars = notation.chars();
+        let file = chars.next().ok_or_else(invalid)?;
+        let rank: usize = chars.as_str().parse().map_err(|_| invalid())?;
+
+        let col = (file as u32).wrapping_sub('a' as u32) as usize;
+        if rank == 0 {
+            return Err(invalid());
+        }
+        Position::new(rank - 1, col, MAX_BOARD_SIZE).ok_or_else(invalid)
+    }
+}
+
+// This is the payload the client sends to make a move, either as coordinates
+// or as notation like `{ "notation": "d4-c3" }`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RawMoveRequest")]
 pub struct MoveRequest {
     pub from: Position,
     pub to: Position,
 }
 
+impl MoveRequest {
+    // Formats the move as "from-to", e.g. "d4-c3"
+    pub fn to_notation(self) -> String {
+        format!("{}-{}", self.from.to_notation(), self.to.to_notation())
+    }
+
+    // Parses a move written by `to_notation`
+    pub fn from_notation(notation: &str) -> Result<MoveRequest, String> {
+        let Some((from, to)) = notation.split_once('-') else {
+            return Err(format!("Invalid move '{}', expected e.g. d4-c3.", notation));
+        };
+        Ok(MoveRequest {
+            from: Position::from_notation(from)?,
+            to: Position::from_notation(to)?,
+        })
+    }
+}
+
+// The accepted shapes of a move payload, before notation is parsed.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawMoveRequest {
+    Coordinates { from: Position, to: Position },
+    Notation { notation: String },
+}
+
+impl TryFrom<RawMoveRequest> for MoveRequest {
+    type Error = String;
+
+    fn try_from(raw: RawMoveRequest) -> Result<Self, Self::Error> {
+        match raw {
+            RawMoveRequest::Coordinates { from, to } => Ok(MoveRequest { from, to }),
+            RawMoveRequest::Notation { notation } => MoveRequest::from_notation(&notation),
+        }
+    }
+}
+
+// A move that was played, with the state needed to take it back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MoveRecord {
+    pub from: Position,
+    pub to: Position,
+    pub player: Player,
+    pub previous_status: GameStatus,
+}
+
+// What `Game::apply_move` changed, so `Game::undo_with` can put it back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UndoToken {
+    from: Position,
+    to: Position,
+    player: Player, // The player who moved
+    previous_status: GameStatus,
+    previous_ply: u32,
+}
+
+// A game's starting position, the rules it is played under and every move played
+// since: enough to replay it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transcript {
+    pub initial_config: String, // Starting position, in `Game::to_code` form
+    #[serde(default)]
+    pub config: GameConfig, // Rules and setup; the defaults when absent
+    pub moves: Vec<MoveRequest>,
+}
+
+// Why a transcript couldn't be replayed. `move_index` points at the first
+// illegal move, and is null when the starting position itself is invalid.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TranscriptError {
+    pub error: String,
+    pub move_index: Option<usize>,
+}
+
+// Query parameters for looking up the legal moves of a single piece.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct ValidMovesQuery {
+    pub row: usize,
+    pub col: usize,
+}
+
+// Legal destinations for a piece, along with how far it jumps.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidMovesResponse {
+    pub move_dist: u8,
+    pub moves: Vec<Position>,
+}
+
+// Number of pieces around a square, which is also how far a piece there moves.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct NeighborsResponse {
+    pub count: u8,
+}
+
+// Summary of whether the game is over, who won and how.
+// `winner` and `reason` are both null while the game is ongoing or drawn.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct StatusResponse {
+    pub over: bool,
+    pub winner: Option<Player>,
+    pub reason: Option<WinReason>,
+}
+
+impl From<GameStatus> for StatusResponse {
+    fn from(status: GameStatus) -> Self {
+        match status {
+            GameStatus::Ongoing => StatusResponse {
+                over: false,
+                winner: None,
+                reason: None,
+            },
+            GameStatus::Won(winner, reason) => StatusResponse {
+                over: true,
+                winner: Some(winner),
+                reason: Some(reason),
+            },
+            GameStatus::Draw => StatusResponse {
+                over: true,
+                winner: None,
+                reason: None,
+            },
+        }
+    }
+}
+
+// Width and height of the square board when none is requested
+pub const DEFAULT_BOARD_SIZE: usize = 7;
+
+// Supported board sizes. Below the minimum the two starting lines would touch.
+pub const MIN_BOARD_SIZE: usize = 5;
+pub const MAX_BOARD_SIZE: usize = 12;
+
+// Contents of every square, indexed as board[row][col]
+pub type Board = Vec<Vec<Option<Player>>>;
+
+// Number of pieces each player starts with
+pub const PIECES_PER_PLAYER: usize = 4;
+
+// The game is drawn when the same position, with the same player to move, occurs this many times
+pub const REPETITION_LIMIT: usize = 3;
+
+// Who plays a game, so the server can refuse moves from the wrong side
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameMode {
+    Human,                      // Two people share the board; the AI never moves
+    VsAi { ai_player: Player }, // The AI plays `ai_player`, a person the other side
+}
+
+// How the AI picks its moves in a game. Anything but `Minimax` plays badly on
+// purpose, as a gentle opponent for beginners.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Strategy {
+    #[default]
+    Minimax, // Searches for the best move
+    Random, // Any legal move
+    Worst,  // The move that looks worst for the AI itself
+}
+
+// What happens when the player to move has no legal move
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StalemateRule {
+    #[default]
+    OpponentWins, // The stuck player loses
+    Draw, // The game is drawn
+}
+
+// Half-moves after which a game that is still going is declared a draw
+pub const DEFAULT_PLY_LIMIT: u32 = 200;
+
+// Rule variants and setup a game is played with, chosen when it is created.
+// Starting squares and goals left out follow the standard layout for the board size.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameConfig {
+    pub players: u8, // How many sides play, taking turns P1, P2, P3...
+    pub stalemate_rule: StalemateRule,
+    pub ply_limit: u32, // The game is drawn once this many half-moves have been played
+    pub p1_start: Option<Vec<Position>>, // Squares Player 1's pieces start on
+    pub p2_start: Option<Vec<Position>>, // Squares Player 2's pieces start on
+    pub p1_goal: Option<Position>, // Player 1's base, which Player 2 tries to reach
+    pub p2_goal: Option<Position>, // Player 2's base, which Player 1 tries to reach
+    pub p3_start: Option<Vec<Position>>, // Squares Player 3's pieces start on, with three or more players
+    pub p4_start: Option<Vec<Position>>, // Squares Player 4's pieces start on, with four players
+    pub p3_goal: Option<Position>,       // Player 3's base, with three or more players
+    pub p4_goal: Option<Position>,       // Player 4's base, with four players
+    pub allow_single_step_when_isolated: bool, // A piece without neighbors may still move one square
+    pub clocks: Option<Clocks>, // Thinking time for the whole game; untimed when absent. Two players only
+    pub mode: Option<GameMode>, // Who plays which side; either endpoint may move either side when absent
+    pub first_player: Player,   // Who makes the first move
+    pub resign_out_of_turn: bool, // Either side may resign at any time, not just the player to move
+    pub ai_strategy: Strategy,  // How POST /ai-move plays; hints always look for the best move
+}
+
+// Time each side has, in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Clocks {
+    pub p1_ms: u64,
+    pub p2_ms: u64,
+}
+
+impl Clocks {
+    // Only two-player games are timed, so anyone but Player 1 uses Player 2's clock
+    fn get_mut(&mut self, player: Player) -> &mut u64 {
+        match player {
+            Player::P1 => &mut self.p1_ms,
+            _ => &mut self.p2_ms,
+        }
+    }
+}
+
+// A timed game's running clock: the time each side had left when the player to
+// move got the turn, at `turn_started_ms` (milliseconds since the Unix epoch)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClockState {
+    pub remaining: Clocks,
+    pub turn_started_ms: u64,
+}
+
+// Wall-clock time in milliseconds since the Unix epoch
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            players: DEFAULT_PLAYERS,
+            stalemate_rule: StalemateRule::default(),
+            ply_limit: DEFAULT_PLY_LIMIT,
+            p1_start: None,
+            p2_start: None,
+            p1_goal: None,
+            p2_goal: None,
+            p3_start: None,
+            p4_start: None,
+            p3_goal: None,
+            p4_goal: None,
+            allow_single_step_when_isolated: false,
+            clocks: None,
+            mode: None,
+            first_player: Player::P1,
+            resign_out_of_turn: false,
+            ai_strategy: Strategy::default(),
+        }
+    }
+}
+
+// Checks that a board of `size` x `size` squares is supported
+pub fn check_board_size(size: usize) -> Result<(), String> {
+    if !(MIN_BOARD_SIZE..=MAX_BOARD_SIZE).contains(&size) {
+        return Err(format!(
+            "Board size must be between {} and {}, got {}.",
+            MIN_BOARD_SIZE, MAX_BOARD_SIZE, size
+        ));
+    }
+    Ok(())
+}
+
+// The corner `player`'s base is in unless the config moves it: (0,0) for Player 1,
+// the opposite one for Player 2, then (0,last) for Player 3 and (last,0) for Player 4
+fn default_goal(player: Player, size: usize) -> Position {
+    let last = size - 1;
+    let corners = [(0, 0), (last, last), (0, last), (last, 0)];
+    let (row, col) = corners[player.index()];
+    Position { row, col }
+}
+
+impl GameConfig {
+    // The players of a game under this config, in turn order
+    pub fn players(&self) -> impl Iterator<Item = Player> {
+        Player::all(self.players)
+    }
+
+    // Squares `player`'s pieces start on, on a `size` x `size` board, in row-major order
+    pub fn start_squares(&self, player: Player, size: usize) -> Vec<Position> {
+        let custom = [
+            &self.p1_start,
+            &self.p2_start,
+            &self.p3_start,
+            &self.p4_start,
+        ];
+        if let Some(squares) = custom[player.index()] {
+            let mut squares = squares.clone();
+            squares.sort();
+            return squares;
+        }
+
+        // A diagonal line cutting off the player's corner
+        let corner = default_goal(player, size);
+        let away = |from: usize, steps: usize| if from == 0 { steps } else { from - steps };
+        let mut squares: Vec<Position> = (0..PIECES_PER_PLAYER)
+            .map(|i| Position {
+                row: away(corner.row, i),
+                col: away(corner.col, PIECES_PER_PLAYER - 1 - i),
+            })
+            .collect();
+        squares.sort();
+        squares
+    }
+
+    // Puts custom starting squares in row-major order, so configs listing the same
+    // squares in a different order build and serialize identical games
+    fn canonicalize(&mut self) {
+        for squares in [
+            &mut self.p1_start,
+            &mut self.p2_start,
+            &mut self.p3_start,
+            &mut self.p4_start,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            squares.sort();
+        }
+    }
+
+    // The base of `player` on a `size` x `size` board
+    pub fn goal(&self, player: Player, size: usize) -> Position {
+        let custom = [self.p1_goal, self.p2_goal, self.p3_goal, self.p4_goal];
+        custom[player.index()].unwrap_or_else(|| default_goal(player, size))
+    }
+
+    // Checks that the starting squares and goals fit a `size` x `size` board
+    // and that no two of them coincide
+    pub fn validate(&self, size: usize) -> Result<(), String> {
+        match self.errors(size).into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    // Everything `validate` objects to, rather than just the first problem;
+    // empty when the config is legal
+    pub fn errors(&self, size: usize) -> Vec<String> {
+        let on_board = |pos: &Position| pos.row < size && pos.col < size;
+        let mut errors = Vec::new();
+
+        if !(DEFAULT_PLAYERS..=MAX_PLAYERS).contains(&self.players) {
+            // Nothing else can be checked without knowing who plays
+            return vec![format!(
+                "A game is played by between {} and {} players, got {}.",
+                DEFAULT_PLAYERS, MAX_PLAYERS, self.players
+            )];
+        }
+        let extra_players = [
+            (
+                Player::P3,
+                self.p3_start.is_some() || self.p3_goal.is_some(),
+            ),
+            (
+                Player::P4,
+                self.p4_start.is_some() || self.p4_goal.is_some(),
+            ),
+        ];
+        for (player, configured) in extra_players {
+            if configured && player.index() >= usize::from(self.players) {
+                errors.push(format!(
+                    "{:?} has starting squares or a goal but doesn't play in a {}-player game.",
+                    player, self.players
+                ));
+            }
+        }
+        if self.first_player.index() >= usize::from(self.players) {
+            errors.push(format!(
+                "First player {:?} doesn't play in a {}-player game.",
+                self.first_player, self.players
+            ));
+        }
+        if self.players > DEFAULT_PLAYERS {
+            if self.clocks.is_some() {
+                errors.push("Only two-player games can be timed.".to_string());
+            }
+            if matches!(self.mode, Some(GameMode::VsAi { .. })) {
+                errors.push("The AI only plays two-player games.".to_string());
+            }
+        }
+
+        let goals: Vec<Position> = self
+            .players()
+            .map(|player| self.goal(player, size))
+            .collect();
+        if !goals.iter().all(on_board) {
+            errors.push("Goals must be on the board.".to_string());
+        }
+        let mut distinct = goals.clone();
+        distinct.sort();
+        distinct.dedup();
+        if distinct.len() < goals.len() {
+            errors.push(if goals.len() == 2 {
+                "The two goals must be different squares.".to_string()
+            } else {
+                "The goals must all be different squares.".to_string()
+            });
+        }
+
+        let mut occupied: Vec<Position> = Vec::new();
+        for player in self.players() {
+            let squares = self.start_squares(player, size);
+            if squares.is_empty() || squares.len() > PIECES_PER_PLAYER {
+                errors.push(format!(
+                    "{:?} must start with between 1 and {} pieces.",
+                    player, PIECES_PER_PLAYER
+                ));
+            }
+            for pos in squares {
+                if !on_board(&pos) {
+                    errors.push(format!(
+                        "Starting square ({},{}) is off the board.",
+                        pos.row, pos.col
+                    ));
+                } else if goals.contains(&pos) {
+                    errors.push(format!(
+                        "Starting square ({},{}) is a goal.",
+                        pos.row, pos.col
+                    ));
+                } else if occupied.contains(&pos) {
+                    errors.push(format!(
+                        "Starting square ({},{}) is used twice.",
+                        pos.row, pos.col
+                    ));
+                }
+                occupied.push(pos);
+            }
+        }
+        errors
+    }
+}
+
 // Main game structure
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(try_from = "RawGame")]
 pub struct Game {
-    pub board: [[Option<Player>; 7]; 7],
+    pub size: usize,
+    pub board: Board,
     pub current_player: Player,
     pub status: GameStatus,
+    // Number of half-moves played so far
+    pub ply: u32,
+    pub history: Vec<MoveRecord>,
+    // Hash of every position reached so far (board + player to move), oldest first
+    pub position_history: Vec<u64>,
+    pub config: GameConfig,
+    // Time left for each side, in timed games
+    pub clock: Option<ClockState>,
+    // How many pieces surround each square, row by row, kept up to date as pieces
+    // move so the move generator doesn't have to count them again and again
+    #[serde(skip)]
+    neighbor_counts: Vec<u8>,
+}
+
+// Unvalidated mirror of `Game`, used as the deserialization target before
+// the board is checked for consistency.
+#[derive(Deserialize)]
+struct RawGame {
+    #[serde(default)]
+    size: Option<usize>,
+    board: Board,
+    current_player: Player,
+    status: GameStatus,
+    #[serde(default)]
+    ply: u32,
+    #[serde(default)]
+    history: Vec<MoveRecord>,
+    #[serde(default)]
+    position_history: Vec<u64>,
+    #[serde(default)]
+    config: GameConfig,
+    #[serde(default)]
+    clock: Option<ClockState>,
+}
+
+impl TryFrom<RawGame> for Game {
+    type Error = String;
+
+    fn try_from(raw: RawGame) -> Result<Self, Self::Error> {
+        Game::validate_position(&raw.board, raw.current_player, raw.config.players)?;
+        let size = raw.board.len();
+        raw.config.validate(size)?;
+        if raw.size.is_some_and(|declared| declared != size) {
+            return Err(format!(
+                "Declared size doesn't match the {}x{} board.",
+                size, size
+            ));
+        }
+        check_move_counts(&raw)?;
+
+        let mut config = raw.config;
+        config.canonicalize();
+        let mut game = Game {
+            size,
+            board: raw.board,
+            current_player: raw.current_player,
+            status: raw.status,
+            ply: raw.ply,
+            history: raw.history,
+            position_history: raw.position_history,
+            config,
+            clock: raw.clock,
+            neighbor_counts: Vec::new(),
+        };
+        game.recount_neighbors();
+        Ok(game)
+    }
+}
+
+// Checks that the ply, the history and the position history of a deserialized game
+// agree, so that playing on or taking moves back can't overflow the ply. A game may
+// come without its history, but not with part of it.
+fn check_move_counts(raw: &RawGame) -> Result<(), String> {
+    let limit = raw.config.ply_limit;
+    if raw.ply > limit || (raw.ply == limit && raw.status == GameStatus::Ongoing) {
+        return Err(format!(
+            "Ply {} is past the ply limit of {} for an ongoing game.",
+            raw.ply, limit
+        ));
+    }
+    if raw.history.is_empty() {
+        return Ok(());
+    }
+    if raw.history.len() != raw.ply as usize {
+        return Err(format!(
+            "Ply {} doesn't match the {} moves in the history.",
+            raw.ply,
+            raw.history.len()
+        ));
+    }
+    // Every position is recorded, from the start on, except the one a move won the game in
+    let unrecorded = matches!(
+        raw.status,
+        GameStatus::Won(_, WinReason::ReachedGoal | WinReason::NoMovesLeft)
+    );
+    let expected = raw.history.len() + 1 - usize::from(unrecorded);
+    if raw.position_history.len() != expected {
+        return Err(format!(
+            "Position history has {} entries, {} expected after {} moves.",
+            raw.position_history.len(),
+            expected,
+            raw.history.len()
+        ));
+    }
+    Ok(())
 }
 
 // --- GAME LOGIC ---
 
+// Random keys for Zobrist hashing: one per (square, owner) pair, plus one per
+// player that is mixed in when they are to move (none for Player 1).
+struct ZobristKeys {
+    squares: Vec<[u64; MAX_PLAYERS as usize]>,
+    to_move: [u64; MAX_PLAYERS as usize],
+}
+
+// Zobrist hash of `board` with `current_player` to move; see `Game::position_key`.
+fn board_key(board: &Board, current_player: Player) -> u64 {
+    let keys = zobrist_keys();
+    let mut key = 0;
+    for (r, row) in board.iter().enumerate() {
+        for (c, square) in row.iter().enumerate() {
+            if let Some(owner) = square {
+                key ^= keys.squares[r * MAX_BOARD_SIZE + c][owner.index()];
+            }
+        }
+    }
+    key ^ keys.to_move[current_player.index()]
+}
+
+// The Zobrist keys, generated once from a fixed seed so hashes are reproducible.
+fn zobrist_keys() -> &'static ZobristKeys {
+    static KEYS: OnceLock<ZobristKeys> = OnceLock::new();
+    KEYS.get_or_init(|| {
+        // SplitMix64: tiny, well-distributed, and needs no dependency
+        let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+        let mut next = || {
+            state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        };
+        // Keys for Players 3 and 4 come after the two-player ones, which so stay
+        // the same as before there were more players, as saved position histories need
+        let mut squares: Vec<[u64; MAX_PLAYERS as usize]> = (0..MAX_BOARD_SIZE * MAX_BOARD_SIZE)
+            .map(|_| [next(), next(), 0, 0])
+            .collect();
+        let mut to_move = [0, next(), 0, 0];
+        for keys in &mut squares {
+            keys[2] = next();
+            keys[3] = next();
+        }
+        to_move[2] = next();
+        to_move[3] = next();
+        ZobristKeys { squares, to_move }
+    })
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Game {
-    // Creates a new game
+    // Creates a new game on the default board
     pub fn new() -> Self {
-        let mut board = [[None; 7]; 7];
+        Self::starting_position(DEFAULT_BOARD_SIZE, GameConfig::default())
+    }
 
-        // Player 1's starting positions (near corner A1 / 0,0)
-        board[0][3] = Some(Player::P1);
-        board[1][2] = Some(Player::P1);
-        board[2][1] = Some(Player::P1);
-        board[3][0] = Some(Player::P1);
+    // Creates a new game on a `size` x `size` board, set up and played as `config` says
+    pub fn new_with_config(size: usize, config: GameConfig) -> Result<Self, String> {
+        check_board_size(size)?;
+        config.validate(size)?;
+        Ok(Self::starting_position(size, config))
+    }
 
-        // Player 2's starting positions (near corner G7 / 6,6)
-        board[3][6] = Some(Player::P2);
-        board[4][5] = Some(Player::P2);
-        board[5][4] = Some(Player::P2);
-        board[6][3] = Some(Player::P2);
+    // A fresh game with the same board size and rules
+    pub fn restart(&self) -> Self {
+        Self::starting_position(self.size, self.config.clone())
+    }
+
+    // Builds the starting board from an already validated config
+    fn starting_position(size: usize, config: GameConfig) -> Self {
+        let mut board = vec![vec![None; size]; size];
+        for player in config.players() {
+            for pos in config.start_squares(player, size) {
+                board[pos.row][pos.col] = Some(player);
+            }
+        }
+        let first_player = config.first_player;
+        Self::with_config(board, first_player, config)
+    }
+
+    // Creates an ongoing game, with no history, on `board` and played under an
+    // already validated `config`. A timed game's clock starts now.
+    fn with_config(board: Board, current_player: Player, mut config: GameConfig) -> Self {
+        config.canonicalize();
+        let mut game = Self::from_position(board, current_player);
+        game.clock = config.clocks.map(|remaining| ClockState {
+            remaining,
+            turn_started_ms: now_ms(),
+        });
+        game.config = config;
+        game
+    }
 
-        Game {
+    // Creates an ongoing game, with no history, from an arbitrary (already validated) position
+    fn from_position(board: Board, current_player: Player) -> Self {
+        let mut game = Game {
+            size: board.len(),
             board,
-            current_player: Player::P1,
+            current_player,
             status: GameStatus::Ongoing,
+            ply: 0,
+            history: Vec::new(),
+            position_history: Vec::new(),
+            config: GameConfig::default(),
+            clock: None,
+            neighbor_counts: Vec::new(),
+        };
+        game.recount_neighbors();
+        game.position_history.push(game.position_key());
+        game
+    }
+
+    /// Builds a game from an arbitrary position, e.g. a puzzle. The board goes through
+    /// the same checks as a deserialized one, and a position that is already decided
+    /// gets the matching `Won` status.
+    pub fn from_setup(board: Board, current_player: Player) -> Result<Game, String> {
+        Self::validate_position(&board, current_player, DEFAULT_PLAYERS)?;
+        let mut game = Self::from_position(board, current_player);
+        game.settle_status();
+        Ok(game)
+    }
+
+    // Gives a position with no history the status its board already decides: won
+    // if a piece sits on the opposing base, and otherwise lost, or drawn, when the
+    // player to move is stuck
+    fn settle_status(&mut self) {
+        for goal in self
+            .config
+            .players()
+            .map(|player| self.get_goal_pos(player))
+        {
+            if let Some(player) = self.board[goal.row][goal.col] {
+                if self.goal_owner(goal) != Some(player) {
+                    self.status = GameStatus::Won(player, WinReason::ReachedGoal);
+                    return;
+                }
+            }
+        }
+        if !self.has_any_valid_moves(self.current_player) {
+            self.status = if self.is_gridlocked() {
+                GameStatus::Draw
+            } else {
+                GameStatus::Won(self.previous_player(), WinReason::NoMovesLeft)
+            };
         }
     }
 
-    // Returns the position of the base ("bottle") for a given player
-    pub fn get_goal_pos(player: Player) -> Position {
-        match player {
-            Player::P1 => Position { row: 0, col: 0 },
-            Player::P2 => Position { row: 6, col: 6 },
+    /// Checks that a position is consistent for a game of `players` players: the board
+    /// is square, of a supported size, and only has pieces of players in the game, none
+    /// with more than their allotted pieces, and the player to move is in the game.
+    pub fn validate_position(
+        board: &Board,
+        current_player: Player,
+        players: u8,
+    ) -> Result<(), String> {
+        let size = board.len();
+        check_board_size(size)?;
+        if board.iter().any(|row| row.len() != size) {
+            return Err("Board must be square.".to_string());
+        }
+        let in_game = |player: Player| player.index() < usize::from(players);
+        if !in_game(current_player) {
+            return Err(format!(
+                "{:?} is to move but doesn't play in a {}-player game.",
+                current_player, players
+            ));
+        }
+
+        for player in Player::all(MAX_PLAYERS) {
+            let count = board
+                .iter()
+                .flatten()
+                .filter(|&&square| square == Some(player))
+                .count();
+            if count > 0 && !in_game(player) {
+                return Err(format!(
+                    "{:?} has pieces on the board but doesn't play in a {}-player game.",
+                    player, players
+                ));
+            }
+            if count > PIECES_PER_PLAYER {
+                return Err(format!(
+                    "{:?} has {} pieces on the board, at most {} are allowed.",
+                    player, count, PIECES_PER_PLAYER
+                ));
+            }
         }
+        Ok(())
     }
 
-    /// Attempts to make a move. Updates the game state internally.
-    pub fn make_move(&mut self, from: Position, to: Position) -> Result<(), &'static str> {
-        // Validation 1: The starting square must contain a piece of the current player
-        match self.board[from.row][from.col] {
-            Some(p) if p == self.current_player => {}
-            _ => return Err("Invalid starting square or that's not your piece."),
+    /// Encodes the position as a compact string: one character per square, row by row
+    /// (`.` empty, `1` Player 1, `2` Player 2 and so on), followed by the player to move.
+    pub fn to_code(&self) -> String {
+        let digit = |player: Player| char::from(b'1' + player.0);
+        let squares = self
+            .board
+            .iter()
+            .flatten()
+            .map(|square| square.map_or('.', digit));
+        squares
+            .chain(std::iter::once(digit(self.current_player)))
+            .collect()
+    }
+
+    // Reads the board and the player to move out of a code, without checking them
+    fn parse_code(code: &str) -> Result<(Board, Player), String> {
+        let player = |ch: char| {
+            ch.to_digit(10)
+                .filter(|digit| (1..=u32::from(MAX_PLAYERS)).contains(digit))
+                .map(|digit| Player(digit as u8 - 1))
+        };
+        let chars: Vec<char> = code.chars().collect();
+        let Some(size) = (MIN_BOARD_SIZE..=MAX_BOARD_SIZE).find(|n| n * n + 1 == chars.len())
+        else {
+            return Err(format!(
+                "Code length {} doesn't match any supported board size.",
+                chars.len()
+            ));
+        };
+
+        let mut board = vec![vec![None; size]; size];
+        for (i, &ch) in chars[..size * size].iter().enumerate() {
+            board[i / size][i % size] = match (ch, player(ch)) {
+                ('.', _) => None,
+                (_, Some(owner)) => Some(owner),
+                _ => return Err(format!("Unknown square character '{}'.", ch)),
+            };
         }
 
-        // Validation 2: The move must be in the list of valid moves
-        let valid_moves = self.get_valid_moves_for_piece(from);
-        if !valid_moves.contains(&to) {
-            return Err("Illegal move.");
+        let to_move = chars[size * size];
+        let current_player =
+            player(to_move).ok_or_else(|| format!("Unknown player to move '{}'.", to_move))?;
+        Ok((board, current_player))
+    }
+
+    /// Parses a string produced by `to_code` back into a game with no history, played
+    /// under `config`, which is checked against the board size, and with a timed game's
+    /// clock started. The board size is inferred from the length of the code. The code
+    /// says who is to move, so that player becomes the config's `first_player`. A
+    /// position already decided under the config's goals gets the matching status, as
+    /// in `from_setup`.
+    pub fn from_code_with_config(code: &str, config: GameConfig) -> Result<Game, String> {
+        let (board, current_player) = Self::parse_code(code)?;
+        Self::from_setup_with_config(board, current_player, config)
+    }
+
+    /// Like `from_setup`, but played under `config`, which is checked against the board
+    /// size, and with a timed game's clock started. `current_player` becomes the
+    /// config's `first_player`, and whether the position is already decided is judged
+    /// against the config's goals.
+    pub fn from_setup_with_config(
+        board: Board,
+        current_player: Player,
+        mut config: GameConfig,
+    ) -> Result<Game, String> {
+        Self::validate_position(&board, current_player, config.players)?;
+        config.validate(board.len())?;
+        config.first_player = current_player;
+        let mut game = Self::with_config(board, current_player, config);
+        game.settle_status();
+        Ok(game)
+    }
+
+    /// Draws the board as text, one line per row starting from row 0, under a header
+    /// giving the player to move and the status. Pieces are 🔴/🔵, or `1`/`2` when
+    /// `emoji` is false; empty squares are `.`.
+    pub fn to_ascii(&self, emoji: bool) -> String {
+        let player_name = |player: Player| format!("Player {}", player.index() + 1);
+        let status = match self.status {
+            GameStatus::Ongoing => "Ongoing".to_string(),
+            GameStatus::Won(winner, WinReason::ReachedGoal) => {
+                format!("{} won by reaching the goal", player_name(winner))
+            }
+            GameStatus::Won(winner, WinReason::NoMovesLeft) => {
+                format!("{} won, opponent has no moves", player_name(winner))
+            }
+            GameStatus::Won(winner, WinReason::TimeForfeit) => {
+                format!("{} won on time", player_name(winner))
+            }
+            GameStatus::Won(winner, WinReason::Resignation) => {
+                format!("{} won by resignation", player_name(winner))
+            }
+            GameStatus::Draw => "Draw".to_string(),
+        };
+
+        let mut text = format!(
+            "Turn: {}\nStatus: {}\n",
+            player_name(self.current_player),
+            status
+        );
+        for row in &self.board {
+            let squares: Vec<String> = row
+                .iter()
+                .map(|square| match (square, emoji) {
+                    (None, _) => ".".to_string(),
+                    (Some(player), true) => player.emoji().to_string(),
+                    (Some(player), false) => (player.index() + 1).to_string(),
+                })
+                .collect();
+            text.push_str(&squares.join(" "));
+            text.push('\n');
         }
+        text
+    }
 
-        // The move is valid, execute it
-        self.board[to.row][to.col] = self.board[from.row][from.col].take();
+    /// Returns the starting position and the moves played so far.
+    pub fn transcript(&self) -> Transcript {
+        let mut start = self.clone();
+        while start.rewind() {}
+        Transcript {
+            initial_config: start.to_code(),
+            config: self.config.clone(),
+            moves: self
+                .history
+                .iter()
+                .map(|record| MoveRequest {
+                    from: record.from,
+                    to: record.to,
+                })
+                .collect(),
+        }
+    }
 
-        // Victory check 1: Reach the opponent's base
-        if to == Self::get_goal_pos(self.current_player.opponent()) {
-            self.status = GameStatus::Won(self.current_player);
-            return Ok(());
+    /// Every position of the game so far, from the start (index 0) to now (index `ply`).
+    pub fn replay(&self) -> Vec<Game> {
+        let mut snapshots = vec![self.clone()];
+        let mut game = self.clone();
+        while game.rewind() {
+            snapshots.push(game.clone());
         }
+        snapshots.reverse();
+        snapshots
+    }
 
-        // Pass to the next player
-        self.current_player = self.current_player.opponent();
+    /// Replays a transcript under its config, checking every move along the way.
+    pub fn from_transcript(transcript: &Transcript) -> Result<Game, TranscriptError> {
+        let mut game =
+            Self::from_code_with_config(&transcript.initial_config, transcript.config.clone())
+                .map_err(|error| TranscriptError {
+                    error,
+                    move_index: None,
+                })?;
 
-        // Victory check 2: The opponent has no more possible moves
-        if !self.has_any_valid_moves(self.current_player) {
-            self.status = GameStatus::Won(self.current_player.opponent());
+        for (index, mv) in transcript.moves.iter().enumerate() {
+            let result = if game.status == GameStatus::Ongoing {
+                game.make_move(mv.from, mv.to)
+            } else {
+                Err("Game is already over.")
+            };
+            result.map_err(|error| TranscriptError {
+                error: error.to_string(),
+                move_index: Some(index),
+            })?;
         }
+        Ok(game)
+    }
 
-        Ok(())
+    /// Zobrist hash of the board together with the player to move, identifying a
+    /// position for repetition checks and the AI's transposition table. Allocation-free,
+    /// and stable across runs since the keys come from a fixed seed.
+    pub fn position_key(&self) -> u64 {
+        board_key(&self.board, self.current_player)
     }
 
-    /// Calculates all valid moves for a piece at a given position.
-    pub fn get_valid_moves_for_piece(&self, pos: Position) -> Vec<Position> {
-        let mut moves = Vec::new();
-        let move_dist = self.count_neighbors(pos) as isize;
+    /// Like `position_key`, but the same for a position and its mirror image across
+    /// the main diagonal, which play alike since both bases lie on that diagonal and
+    /// so each player keeps their own. Of the two boards, the one that comes first
+    /// reading square by square (empty, then Player 1, then Player 2) is hashed.
+    /// When the config moves a base off the diagonal, the mirror image is a different
+    /// game, and this is just `position_key`.
+    pub fn canonical_key(&self) -> u64 {
+        let mirror = |pos: Position| Position {
+            row: pos.col,
+            col: pos.row,
+        };
+        let goals_on_diagonal = self.config.players().all(|player| {
+            let goal = self.get_goal_pos(player);
+            goal == mirror(goal)
+        });
+        if !goals_on_diagonal {
+            return self.position_key();
+        }
 
-        if move_dist == 0 {
-            return moves; // A piece with no neighbors cannot move
+        let mirrored: Board = (0..self.size)
+            .map(|r| (0..self.size).map(|c| self.board[c][r]).collect())
+            .collect();
+        let rank = |square: &Option<Player>| square.map_or(0, |player| player.index() + 1);
+        let squares = |board: &Board| board.iter().flatten().map(rank).collect::<Vec<_>>();
+        if squares(&mirrored) < squares(&self.board) {
+            board_key(&mirrored, self.current_player)
+        } else {
+            self.position_key()
         }
+    }
 
-        // Test the 8 possible directions
-        for &dir in &[
-            (-1, -1),
-            (-1, 0),
-            (-1, 1),
-            (0, -1),
-            (0, 1),
-            (1, -1),
+    // Returns the position of the base ("bottle") for a given player
+    pub fn get_goal_pos(&self, player: Player) -> Position {
+        self.config.goal(player, self.size)
+    }
+
+    /// The player whose base is on `pos`, if any.
+    pub fn goal_owner(&self, pos: Position) -> Option<Player> {
+        self.config
+            .players()
+            .find(|&player| self.get_goal_pos(player) == pos)
+    }
+
+    /// Checks whether `player` takes part in this game.
+    pub fn has_player(&self, player: Player) -> bool {
+        player.index() < usize::from(self.config.players)
+    }
+
+    /// Who plays after the player to move, with play rotating P1, P2, P3... in turn.
+    pub fn next_player(&self) -> Player {
+        self.current_player.next(self.config.players)
+    }
+
+    /// Who played before the player to move.
+    pub fn previous_player(&self) -> Player {
+        self.current_player.previous(self.config.players)
+    }
+
+    /// Checks whether no player at all has a legal move.
+    fn is_gridlocked(&self) -> bool {
+        !self
+            .config
+            .players()
+            .any(|player| self.has_any_valid_moves(player))
+    }
+
+    /// Attempts to make a move. Updates the game state internally.
+    pub fn make_move(&mut self, from: Position, to: Position) -> Result<(), &'static str> {
+        self.make_move_at(from, to, now_ms())
+    }
+
+    /// Same as `make_move`, played at `now_ms` (milliseconds since the Unix epoch)
+    /// as far as the clock is concerned. In a timed game, a player whose time has
+    /// run out loses on time instead of moving.
+    pub fn make_move_at(
+        &mut self,
+        from: Position,
+        to: Position,
+        now_ms: u64,
+    ) -> Result<(), &'static str> {
+        self.run_clock(now_ms);
+        if matches!(self.status, GameStatus::Won(_, WinReason::TimeForfeit)) {
+            return Err("Out of time.");
+        }
+        self.check_move(from, to)?;
+
+        // The move is valid, execute it
+        let token = self.apply_move(from, to);
+        self.history.push(MoveRecord {
+            from,
+            to,
+            player: token.player,
+            previous_status: token.previous_status,
+        });
+        Ok(())
+    }
+
+    /// Ends the game with `player` conceding to their opponent. Unless the rules allow
+    /// resigning out of turn, only the player to move may resign. Only two-player
+    /// games can be resigned, as there is no single opponent to win otherwise.
+    pub fn resign(&mut self, player: Player) -> Result<(), &'static str> {
+        if self.status != GameStatus::Ongoing {
+            return Err("Game is already over.");
+        }
+        if self.config.players != DEFAULT_PLAYERS {
+            return Err("Only two-player games can be resigned.");
+        }
+        if !self.has_player(player) {
+            return Err("That player doesn't play in this game.");
+        }
+        if player != self.current_player && !self.config.resign_out_of_turn {
+            return Err("Only the player to move may resign.");
+        }
+        self.status = GameStatus::Won(player.next(DEFAULT_PLAYERS), WinReason::Resignation);
+        Ok(())
+    }
+
+    /// Charges the time since the turn started, up to `now_ms`, to the player to move.
+    /// Their opponent wins on time if that uses up their clock. Does nothing in
+    /// untimed or finished games. Only two-player games have a clock.
+    pub fn run_clock(&mut self, now_ms: u64) {
+        let Some(clock) = &mut self.clock else {
+            return;
+        };
+        if self.status != GameStatus::Ongoing {
+            return;
+        }
+        let elapsed = now_ms.saturating_sub(clock.turn_started_ms);
+        let remaining = clock.remaining.get_mut(self.current_player);
+        *remaining = remaining.saturating_sub(elapsed);
+        clock.turn_started_ms = clock.turn_started_ms.max(now_ms);
+        if *remaining == 0 {
+            self.status = GameStatus::Won(self.next_player(), WinReason::TimeForfeit);
+        }
+    }
+
+    /// Checks whether the player to move may play `from` -> `to`, without playing it.
+    /// The error says why not, as `make_move` would report it.
+    pub fn check_move(&self, from: Position, to: Position) -> Result<(), &'static str> {
+        // A piece has to go somewhere; staying put isn't a move
+        if from == to {
+            return Err("Source and destination must differ.");
+        }
+
+        // Validation 0: Both squares must be on the board, otherwise indexing would panic
+        if !self.is_on_board(from.row as isize, from.col as isize)
+            || !self.is_on_board(to.row as isize, to.col as isize)
+        {
+            return Err("Position is off the board.");
+        }
+
+        // Validation 1: The starting square must contain a piece of the current player
+        match self.board[from.row][from.col] {
+            Some(p) if p == self.current_player => {}
+            _ => return Err("Invalid starting square or that's not your piece."),
+        }
+
+        // Validation 2: The move itself must follow the rules
+        if !self.is_move_valid(from, to) {
+            return Err(self.explain_illegal_move(from, to));
+        }
+        Ok(())
+    }
+
+    /// Plays a move that is already known to be legal, e.g. one from `all_valid_moves`,
+    /// without checking it or adding it to `history`. Meant for the AI search, which
+    /// explores many moves on one game and takes each back with `undo_with`.
+    pub fn apply_move(&mut self, from: Position, to: Position) -> UndoToken {
+        let token = UndoToken {
+            from,
+            to,
+            player: self.current_player,
+            previous_status: self.status,
+            previous_ply: self.ply,
+        };
+        let piece = self.board[from.row][from.col];
+        self.set_square(from, None);
+        self.set_square(to, piece);
+        self.ply += 1;
+
+        // Victory check 1: Reach an opponent's base
+        if self
+            .goal_owner(to)
+            .is_some_and(|owner| owner != self.current_player)
+        {
+            self.status = GameStatus::Won(self.current_player, WinReason::ReachedGoal);
+            return token;
+        }
+
+        // Pass to the next player
+        self.current_player = self.next_player();
+
+        // Victory check 2: The next player has no more possible moves, and the player
+        // who left them stuck wins. If nobody can move, the board is gridlocked and
+        // nobody wins.
+        if !self.has_any_valid_moves(self.current_player) {
+            match self.config.stalemate_rule {
+                StalemateRule::OpponentWins if !self.is_gridlocked() => {
+                    self.status = GameStatus::Won(token.player, WinReason::NoMovesLeft);
+                    return token;
+                }
+                // The position is still recorded below, so undo stays in step
+                _ => self.status = GameStatus::Draw,
+            }
+        }
+
+        // Draw check: The same position has now occurred too many times
+        let hash = self.position_key();
+        self.position_history.push(hash);
+        let occurrences = self.position_history.iter().filter(|&&h| h == hash).count();
+        if occurrences >= REPETITION_LIMIT {
+            self.status = GameStatus::Draw;
+        }
+
+        // Draw check: The game has gone on too long without a result
+        if self.ply >= self.config.ply_limit {
+            self.status = GameStatus::Draw;
+        }
+
+        token
+    }
+
+    /// Takes back the last move, restoring the board, the player to move and the status.
+    pub fn undo_move(&mut self) -> Result<(), &'static str> {
+        // The game ended between moves, so there is no move to take back
+        match self.status {
+            GameStatus::Won(_, WinReason::TimeForfeit) => {
+                return Err("A loss on time can't be undone.")
+            }
+            GameStatus::Won(_, WinReason::Resignation) => {
+                return Err("A resignation can't be undone.")
+            }
+            _ => {}
+        }
+        if !self.rewind() {
+            return Err("No moves to undo.");
+        }
+        Ok(())
+    }
+
+    /// Takes back the last move in `history`, however the game ended. Unlike
+    /// `undo_move`, this also steps back past a loss on time or a resignation,
+    /// as `transcript` and `replay` must to reach the start. Returns false when
+    /// there is no move left to take back.
+    fn rewind(&mut self) -> bool {
+        let Some(record) = self.history.pop() else {
+            return false;
+        };
+        // Such a game ended between moves, after the last position was recorded
+        if matches!(
+            self.status,
+            GameStatus::Won(_, WinReason::TimeForfeit | WinReason::Resignation)
+        ) {
+            self.status = GameStatus::Ongoing;
+        }
+
+        self.undo_with(UndoToken {
+            from: record.from,
+            to: record.to,
+            player: record.player,
+            previous_status: record.previous_status,
+            previous_ply: self.ply - 1,
+        });
+        true
+    }
+
+    /// Reverses the `apply_move` that returned `token`. Moves must be taken back in
+    /// the reverse order they were applied.
+    pub fn undo_with(&mut self, token: UndoToken) {
+        // A won game's final position was never recorded, so there is nothing to pop for it
+        if !matches!(self.status, GameStatus::Won(..)) {
+            self.position_history.pop();
+        }
+        let piece = self.board[token.to.row][token.to.col];
+        self.set_square(token.to, None);
+        self.set_square(token.from, piece);
+        self.current_player = token.player;
+        self.status = token.previous_status;
+        self.ply = token.previous_ply;
+    }
+
+    /// Calculates all valid moves for a piece at a given position.
+    pub fn get_valid_moves_for_piece(&self, pos: Position) -> Vec<Position> {
+        let mut moves = Vec::new();
+        let move_dist = self.move_distance(pos) as isize;
+
+        if move_dist == 0 {
+            return moves; // A piece with no neighbors cannot move
+        }
+
+        // Test the 8 possible directions
+        for &dir in &[
+            (-1, -1),
+            (-1, 0),
+            (-1, 1),
+            (0, -1),
+            (0, 1),
+            (1, -1),
             (1, 0),
             (1, 1),
         ] {
             let new_row_isize = pos.row as isize + dir.0 * move_dist;
             let new_col_isize = pos.col as isize + dir.1 * move_dist;
 
-            if Self::is_on_board(new_row_isize, new_col_isize) {
+            if self.is_on_board(new_row_isize, new_col_isize) {
                 let target_pos = Position {
                     row: new_row_isize as usize,
                     col: new_col_isize as usize,
                 };
-                if self.is_move_valid(pos, target_pos) {
+                if target_pos != pos && self.is_move_valid(pos, target_pos) {
                     moves.push(target_pos);
                 }
             }
@@ -151,154 +1416,928 @@ impl Game {
         moves
     }
 
-    /// Checks if a player has at least one valid move on the entire board.
-    pub fn has_any_valid_moves(&self, player: Player) -> bool {
-        for r in 0..7 {
-            for c in 0..7 {
-                if self.board[r][c] == Some(player)
-                    && !self
-                        .get_valid_moves_for_piece(Position { row: r, col: c })
-                        .is_empty()
-                {
-                    return true;
-                }
-            }
+    /// Collects every legal move for `player`'s pieces, in board order.
+    pub fn all_valid_moves(&self, player: Player) -> Vec<MoveRequest> {
+        let mut moves = Vec::new();
+        for r in 0..self.size {
+            for c in 0..self.size {
+                if self.board[r][c] == Some(player) {
+                    let from = Position { row: r, col: c };
+                    for to in self.get_valid_moves_for_piece(from) {
+                        moves.push(MoveRequest { from, to });
+                    }
+                }
+            }
+        }
+        moves
+    }
+
+    /// Every empty square one of `player`'s pieces could move to with a legal move,
+    /// whether or not it is their turn.
+    pub fn reachable_squares(&self, player: Player) -> HashSet<Position> {
+        self.all_valid_moves(player)
+            .into_iter()
+            .map(|mv| mv.to)
+            .collect()
+    }
+
+    /// The squares whose occupant differs from what it was after `ply` half-moves,
+    /// with their current occupant, in row-major order. None if `ply` hasn't been
+    /// played yet. When the history doesn't reach back that far, every square is listed.
+    pub fn changes_since(&self, ply: u32) -> Option<Vec<(Position, Option<Player>)>> {
+        let moves_back = self.ply.checked_sub(ply)? as usize;
+
+        let mut changed = Vec::new();
+        let Some(first) = self.history.len().checked_sub(moves_back) else {
+            for r in 0..self.size {
+                for c in 0..self.size {
+                    changed.push((Position { row: r, col: c }, self.board[r][c]));
+                }
+            }
+            return Some(changed);
+        };
+
+        // Walk the board back to how it was, then compare
+        let mut then = self.board.clone();
+        for record in self.history[first..].iter().rev() {
+            then[record.to.row][record.to.col] = None;
+            then[record.from.row][record.from.col] = Some(record.player);
+        }
+        for (r, (old_row, row)) in then.iter().zip(&self.board).enumerate() {
+            for (c, (old, &square)) in old_row.iter().zip(row).enumerate() {
+                if *old != square {
+                    changed.push((Position { row: r, col: c }, square));
+                }
+            }
+        }
+        Some(changed)
+    }
+
+    /// Lists the squares of `player`'s pieces that have at least one legal move.
+    /// The others are frozen, usually because they have no neighbors.
+    pub fn movable_pieces(&self, player: Player) -> Vec<Position> {
+        let mut pieces = Vec::new();
+        for r in 0..self.size {
+            for c in 0..self.size {
+                let pos = Position { row: r, col: c };
+                if self.board[r][c] == Some(player)
+                    && !self.get_valid_moves_for_piece(pos).is_empty()
+                {
+                    pieces.push(pos);
+                }
+            }
+        }
+        pieces
+    }
+
+    /// Checks if a player has at least one valid move on the entire board.
+    pub fn has_any_valid_moves(&self, player: Player) -> bool {
+        for r in 0..self.size {
+            for c in 0..self.size {
+                if self.board[r][c] == Some(player)
+                    && !self
+                        .get_valid_moves_for_piece(Position { row: r, col: c })
+                        .is_empty()
+                {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    // --- HELPER VALIDATION FUNCTIONS ---
+
+    /// Counts the number of adjacent pieces to a square. A square off the board has none.
+    pub fn count_neighbors(&self, pos: Position) -> u8 {
+        if !self.is_on_board(pos.row as isize, pos.col as isize) {
+            return 0;
+        }
+        self.neighbor_counts[pos.row * self.size + pos.col]
+    }
+
+    /// Puts `owner`'s piece on `pos`, or empties it when `owner` is None, keeping
+    /// the neighbor counts of the surrounding squares up to date. Squares must be
+    /// changed through this rather than by writing to `board` directly.
+    pub fn set_square(&mut self, pos: Position, owner: Option<Player>) {
+        let was_occupied = self.board[pos.row][pos.col].is_some();
+        self.board[pos.row][pos.col] = owner;
+        let change: i8 = match (was_occupied, owner.is_some()) {
+            (false, true) => 1,
+            (true, false) => -1,
+            _ => return,
+        };
+
+        for r_offset in -1..=1 {
+            for c_offset in -1..=1 {
+                let row = pos.row as isize + r_offset;
+                let col = pos.col as isize + c_offset;
+                if (r_offset, c_offset) != (0, 0) && self.is_on_board(row, col) {
+                    let count = &mut self.neighbor_counts[row as usize * self.size + col as usize];
+                    *count = count.wrapping_add_signed(change);
+                }
+            }
+        }
+    }
+
+    /// Replaces the whole board to set up a test position, and recounts neighbors.
+    #[cfg(test)]
+    pub fn set_board(&mut self, board: Board) {
+        self.size = board.len();
+        self.board = board;
+        self.recount_neighbors();
+    }
+
+    /// Fills `neighbor_counts` from scratch.
+    fn recount_neighbors(&mut self) {
+        self.neighbor_counts = (0..self.size * self.size)
+            .map(|i| {
+                self.count_neighbors_uncached(Position {
+                    row: i / self.size,
+                    col: i % self.size,
+                })
+            })
+            .collect();
+    }
+
+    /// Counts the pieces around `pos` by looking at every adjacent square.
+    fn count_neighbors_uncached(&self, pos: Position) -> u8 {
+        let mut count = 0;
+        for r_offset in -1..=1 {
+            for c_offset in -1..=1 {
+                if r_offset == 0 && c_offset == 0 {
+                    continue;
+                } // Ignore the square itself
+
+                let check_row = pos.row as isize + r_offset as isize;
+                let check_col = pos.col as isize + c_offset as isize;
+
+                if self.is_on_board(check_row, check_col)
+                    && self.board[check_row as usize][check_col as usize].is_some()
+                {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    /// How many squares the piece at `pos` moves: one per neighbor. A piece without
+    /// neighbors is stuck, unless the rules let it step one square.
+    pub fn move_distance(&self, pos: Position) -> u8 {
+        match self.count_neighbors(pos) {
+            0 if self.config.allow_single_step_when_isolated => 1,
+            neighbors => neighbors,
+        }
+    }
+
+    /// Picks the most specific reason why a move that isn't in the valid list was rejected.
+    fn explain_illegal_move(&self, from: Position, to: Position) -> &'static str {
+        if self.board[to.row][to.col].is_some() {
+            return "Destination square is occupied.";
+        }
+
+        let dr = to.row.abs_diff(from.row);
+        let dc = to.col.abs_diff(from.col);
+        if from.alignment(&to).is_some()
+            && dr.max(dc) == self.move_distance(from) as usize
+            && !self.is_path_clear(from, to)
+        {
+            return "Path is blocked.";
+        }
+
+        "Illegal move."
+    }
+
+    /// Checks if a move from `from` to `to` respects all rules.
+    fn is_move_valid(&self, from: Position, to: Position) -> bool {
+        // Both ends must be on the board
+        if !self.is_on_board(from.row as isize, from.col as isize)
+            || !self.is_on_board(to.row as isize, to.col as isize)
+        {
+            return false;
+        }
+        // The destination square must be empty
+        if self.board[to.row][to.col].is_some() {
+            return false;
+        }
+        // Must go in a straight line, exactly one square per neighbor
+        let dr = to.row.abs_diff(from.row);
+        let dc = to.col.abs_diff(from.col);
+        if from.alignment(&to).is_none() || dr.max(dc) != self.move_distance(from) as usize {
+            return false;
+        }
+        // Cannot move to its own base (judged by the piece's owner, so this
+        // also holds when looking at the moves of the player who isn't to move)
+        let owner = self.board[from.row][from.col].unwrap_or(self.current_player);
+        if to == self.get_goal_pos(owner) {
+            return false;
+        }
+        // Must have a clear path
+        if !self.is_path_clear(from, to) {
+            return false;
+        }
+
+        true
+    }
+
+    /// Checks that the path between two points is empty (no jumping).
+    /// Squares that don't share a line have no path between them.
+    fn is_path_clear(&self, from: Position, to: Position) -> bool {
+        let Some(direction) = from.alignment(&to) else {
+            return false;
+        };
+        // Every square up to, but not including, the destination
+        let distance = from.row.abs_diff(to.row).max(from.col.abs_diff(to.col));
+        self.is_ray_clear(from, direction, distance - 1)
+    }
+
+    /// Checks that the `steps` squares after `from`, going in direction `(dr, dc)`,
+    /// are all on the board and empty. `from` itself isn't checked.
+    pub fn is_ray_clear(&self, from: Position, (dr, dc): (isize, isize), steps: usize) -> bool {
+        (1..=steps as isize).all(|i| {
+            let row = from.row as isize + i * dr;
+            let col = from.col as isize + i * dc;
+            self.is_on_board(row, col) && self.board[row as usize][col as usize].is_none()
+        })
+    }
+
+    /// Checks if coordinates (as isize for calculations) are on the board.
+    pub fn is_on_board(&self, row: isize, col: isize) -> bool {
+        (0..self.size as isize).contains(&row) && (0..self.size as isize).contains(&col)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup_game() -> Game {
+        Game::new()
+    }
+
+    #[test]
+    fn test_initial_board_setup() {
+        let game = setup_game();
+        assert_eq!(game.board[0][3], Some(Player::P1));
+        assert_eq!(game.board[3][0], Some(Player::P1));
+        assert_eq!(game.board[3][6], Some(Player::P2));
+        assert_eq!(game.board[6][3], Some(Player::P2));
+        assert_eq!(game.board[0][0], None);
+    }
+
+    #[test]
+    fn test_custom_board_sizes() {
+        let small = Game::new_with_config(5, GameConfig::default()).unwrap();
+        assert_eq!(small.size, 5);
+        assert_eq!(small.board.len(), 5);
+        assert_eq!(small.board[0][3], Some(Player::P1));
+        assert_eq!(small.board[3][0], Some(Player::P1));
+        assert_eq!(small.board[1][4], Some(Player::P2));
+        assert_eq!(small.board[4][1], Some(Player::P2));
+        assert_eq!(small.get_goal_pos(Player::P2), Position { row: 4, col: 4 });
+
+        let large = Game::new_with_config(8, GameConfig::default()).unwrap();
+        assert_eq!(large.board[0][3], Some(Player::P1));
+        assert_eq!(large.board[4][7], Some(Player::P2));
+        assert_eq!(large.board[7][4], Some(Player::P2));
+        assert_eq!(large.get_goal_pos(Player::P2), Position { row: 7, col: 7 });
+        assert!(large.has_any_valid_moves(Player::P1));
+
+        // The off-board check follows the size
+        let mut large = large;
+        assert!(large
+            .make_move(Position { row: 0, col: 3 }, Position { row: 0, col: 2 })
+            .is_ok());
+        assert_eq!(
+            large.make_move(Position { row: 7, col: 4 }, Position { row: 8, col: 4 }),
+            Err("Position is off the board.")
+        );
+
+        assert!(Game::new_with_config(MIN_BOARD_SIZE - 1, GameConfig::default()).is_err());
+        assert!(Game::new_with_config(MAX_BOARD_SIZE + 1, GameConfig::default()).is_err());
+        assert_eq!(Game::new().size, DEFAULT_BOARD_SIZE);
+    }
+
+    #[test]
+    fn test_mirrored_setup_from_config() {
+        // Left-right mirror of the standard layout: bases in the other two corners
+        let last = DEFAULT_BOARD_SIZE - 1;
+        let config = GameConfig {
+            p1_start: Some(
+                (0..4)
+                    .map(|i| Position {
+                        row: i,
+                        col: last - 3 + i,
+                    })
+                    .collect(),
+            ),
+            p2_start: Some(
+                (0..4)
+                    .map(|i| Position {
+                        row: last - i,
+                        col: 3 - i,
+                    })
+                    .collect(),
+            ),
+            p1_goal: Some(Position { row: 0, col: last }),
+            p2_goal: Some(Position { row: last, col: 0 }),
+            ..GameConfig::default()
+        };
+        let game = Game::new_with_config(DEFAULT_BOARD_SIZE, config).unwrap();
+
+        for i in 0..4 {
+            assert_eq!(game.board[i][last - 3 + i], Some(Player::P1));
+            assert_eq!(game.board[last - i][3 - i], Some(Player::P2));
+        }
+        assert_eq!(game.board.iter().flatten().flatten().count(), 8);
+        assert_eq!(
+            game.get_goal_pos(Player::P1),
+            Position { row: 0, col: last }
+        );
+        assert_eq!(
+            game.get_goal_pos(Player::P2),
+            Position { row: last, col: 0 }
+        );
+    }
+
+    #[test]
+    fn test_games_from_the_same_setup_are_identical() {
+        let squares = |cells: &[(usize, usize)]| {
+            cells
+                .iter()
+                .map(|&(row, col)| Position { row, col })
+                .collect::<Vec<_>>()
+        };
+        let config = |p1: &[(usize, usize)], p2: &[(usize, usize)]| GameConfig {
+            p1_start: Some(squares(p1)),
+            p2_start: Some(squares(p2)),
+            ..GameConfig::default()
+        };
+        let first = Game::new_with_config(
+            DEFAULT_BOARD_SIZE,
+            config(&[(0, 3), (1, 2), (2, 1)], &[(6, 3), (5, 4), (4, 5)]),
+        )
+        .unwrap();
+        let second = Game::new_with_config(
+            DEFAULT_BOARD_SIZE,
+            config(&[(2, 1), (0, 3), (1, 2)], &[(4, 5), (6, 3), (5, 4)]),
+        )
+        .unwrap();
+
+        // Equality covers the neighbor cache too
+        assert_eq!(first, second);
+        let json = serde_json::to_string(&first).unwrap();
+        assert_eq!(json, serde_json::to_string(&second).unwrap());
+        assert_eq!(serde_json::from_str::<Game>(&json).unwrap(), first);
+        assert_eq!(
+            first.config.p2_start,
+            Some(squares(&[(4, 5), (5, 4), (6, 3)]))
+        );
+    }
+
+    #[test]
+    fn test_config_rejects_overlaps() {
+        let on_goal = GameConfig {
+            p1_start: Some(vec![Position { row: 6, col: 6 }]),
+            ..GameConfig::default()
+        };
+        assert!(Game::new_with_config(DEFAULT_BOARD_SIZE, on_goal).is_err());
+
+        let overlapping = GameConfig {
+            p2_start: Some(vec![Position { row: 0, col: 3 }]),
+            ..GameConfig::default()
+        };
+        assert!(Game::new_with_config(DEFAULT_BOARD_SIZE, overlapping).is_err());
+
+        let off_board = GameConfig {
+            p1_goal: Some(Position { row: 9, col: 0 }),
+            ..GameConfig::default()
+        };
+        assert!(Game::new_with_config(DEFAULT_BOARD_SIZE, off_board).is_err());
+    }
+
+    #[test]
+    fn test_initial_turn_is_p1() {
+        let game = setup_game();
+        assert_eq!(game.current_player, Player::P1);
+    }
+
+    #[test]
+    fn test_count_neighbors() {
+        let game = setup_game();
+
+        // Piece at (0,3) has 1 neighbor at (1,2)
+        let pos = Position { row: 0, col: 3 };
+        assert_eq!(game.count_neighbors(pos), 1);
+
+        // Piece at (3,0) has 1 neighbor at (2,1)
+        let pos = Position { row: 3, col: 0 };
+        assert_eq!(game.count_neighbors(pos), 1);
+
+        // Piece at (2,1) has 2 neighbors from the initial setup
+        let pos = Position { row: 2, col: 1 };
+        assert_eq!(game.count_neighbors(pos), 2);
+    }
+
+    #[test]
+    fn test_all_valid_moves_at_start() {
+        let game = setup_game();
+        // Each of the four pieces on the starting diagonal has four moves
+        assert_eq!(game.all_valid_moves(Player::P1).len(), 16);
+        assert_eq!(game.all_valid_moves(Player::P2).len(), 16);
+    }
+
+    #[test]
+    fn test_valid_move() {
+        let mut game = setup_game();
+        let from = Position { row: 0, col: 3 };
+        let to = Position { row: 0, col: 2 };
+
+        let result = game.make_move(from, to);
+        assert!(result.is_ok());
+        assert_eq!(game.board[from.row][from.col], None);
+        assert_eq!(game.board[to.row][to.col], Some(Player::P1));
+        assert_eq!(game.current_player, Player::P2);
+    }
+
+    #[test]
+    fn test_invalid_move_occupied_destination() {
+        let mut game = setup_game();
+        let from = Position { row: 0, col: 3 };
+        let to = Position { row: 1, col: 2 };
+        let result = game.make_move(from, to);
+        assert_eq!(result, Err("Destination square is occupied."));
+    }
+
+    #[test]
+    fn test_invalid_move_blocked_path() {
+        let mut game = setup_game();
+        // (2,1) has two neighbors, but sliding right to (2,3) crosses (2,2)
+        game.set_square(Position { row: 2, col: 2 }, Some(Player::P2));
+        game.set_square(Position { row: 3, col: 0 }, None);
+        let result = game.make_move(Position { row: 2, col: 1 }, Position { row: 2, col: 3 });
+        assert_eq!(result, Err("Path is blocked."));
+    }
+
+    #[test]
+    fn test_off_board_move_is_rejected() {
+        let mut game = setup_game();
+        let before = game.clone();
+
+        let result = game.make_move(Position { row: 99, col: 0 }, Position { row: 0, col: 2 });
+        assert_eq!(result, Err("Position is off the board."));
+
+        let result = game.make_move(Position { row: 0, col: 3 }, Position { row: 99, col: 0 });
+        assert_eq!(result, Err("Position is off the board."));
+
+        assert_eq!(game.board, before.board);
+        assert_eq!(game.current_player, before.current_player);
+        assert_eq!(game.status, before.status);
+    }
+
+    #[test]
+    fn test_notation_round_trips_corners() {
+        let last = DEFAULT_BOARD_SIZE - 1;
+        for (row, col) in [(0, 0), (0, last), (last, 0), (last, last)] {
+            let pos = Position { row, col };
+            assert_eq!(Position::from_notation(&pos.to_notation()), Ok(pos));
+        }
+        assert_eq!(Position { row: 0, col: 0 }.to_notation(), "a1");
+        assert_eq!(Position { row: 6, col: 6 }.to_notation(), "g7");
+        let far = Position {
+            row: usize::MAX,
+            col: 200,
+        };
+        assert_eq!(far.to_notation(), format!("({},200)", usize::MAX));
+
+        let mv = MoveRequest::from_notation("d4-c3").unwrap();
+        assert_eq!(mv.from, Position { row: 3, col: 3 });
+        assert_eq!(mv.to, Position { row: 2, col: 2 });
+        assert_eq!(mv.to_notation(), "d4-c3");
+    }
+
+    #[test]
+    fn test_notation_rejects_bad_squares() {
+        assert!(MoveRequest::from_notation("z9-a1").is_err());
+        assert!(MoveRequest::from_notation("a0-a1").is_err());
+        assert!(MoveRequest::from_notation("d4c3").is_err());
+        assert!(Position::from_notation("").is_err());
+    }
+
+    #[test]
+    fn test_move_request_accepts_notation_body() {
+        let mv: MoveRequest = serde_json::from_str(r#"{ "notation": "d1-c1" }"#).unwrap();
+        assert_eq!(mv.from, Position { row: 0, col: 3 });
+        assert_eq!(mv.to, Position { row: 0, col: 2 });
+
+        let mv: MoveRequest = serde_json::from_str(
+            r#"{ "from": { "row": 0, "col": 3 }, "to": { "row": 0, "col": 2 } }"#,
+        )
+        .unwrap();
+        assert_eq!(mv.to_notation(), "d1-c1");
+        assert!(serde_json::from_str::<MoveRequest>(r#"{ "notation": "z9-a1" }"#).is_err());
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let mut game = setup_game();
+        game.make_move(Position { row: 0, col: 3 }, Position { row: 0, col: 2 })
+            .unwrap();
+
+        let json = serde_json::to_string(&game).unwrap();
+        let loaded: Game = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, game);
+    }
+
+    #[test]
+    fn test_deserialize_rejects_too_many_pieces() {
+        let mut game = setup_game();
+        game.set_square(Position { row: 6, col: 0 }, Some(Player::P1));
+
+        let json = serde_json::to_string(&game).unwrap();
+        let err = serde_json::from_str::<Game>(&json).unwrap_err();
+        assert!(err.to_string().contains("P1 has 5 pieces"));
+    }
+
+    #[test]
+    fn test_deserialize_rejects_inconsistent_move_counts() {
+        let mut game = setup_game();
+        game.make_move(Position { row: 0, col: 3 }, Position { row: 0, col: 2 })
+            .unwrap();
+        let json = serde_json::to_value(&game).unwrap();
+        let load = |change: &dyn Fn(&mut serde_json::Value)| {
+            let mut json = json.clone();
+            change(&mut json);
+            serde_json::from_value::<Game>(json).map_err(|e| e.to_string())
+        };
+
+        let err = load(&|json| json["ply"] = u32::MAX.into()).unwrap_err();
+        assert!(err.contains("past the ply limit"));
+        let err = load(&|json| json["ply"] = 2.into()).unwrap_err();
+        assert!(err.contains("doesn't match the 1 moves"));
+        let err = load(&|json| json["position_history"] = vec![1u64].into()).unwrap_err();
+        assert!(err.contains("1 entries, 2 expected"));
+
+        // Without a history, the ply is taken as is and there is nothing to undo
+        let mut loaded = load(&|json| {
+            json["history"] = Vec::<u64>::new().into();
+            json["position_history"] = Vec::<u64>::new().into();
+            json["ply"] = 199.into();
+        })
+        .unwrap();
+        assert!(loaded.undo_move().is_err());
+        let mv = loaded.all_valid_moves(loaded.current_player)[0];
+        loaded.make_move(mv.from, mv.to).unwrap();
+        assert_eq!(loaded.status, GameStatus::Draw);
+    }
+
+    #[test]
+    fn test_undo_restores_initial_position() {
+        let mut game = setup_game();
+        game.make_move(Position { row: 0, col: 3 }, Position { row: 0, col: 2 })
+            .unwrap();
+        game.make_move(Position { row: 6, col: 3 }, Position { row: 6, col: 4 })
+            .unwrap();
+        assert_eq!(game.history.len(), 2);
+
+        assert!(game.undo_move().is_ok());
+        assert_eq!(game.current_player, Player::P2);
+        assert!(game.undo_move().is_ok());
+        assert_eq!(game, Game::new());
+
+        assert_eq!(game.undo_move(), Err("No moves to undo."));
+    }
+
+    #[test]
+    fn test_mirror_images_share_a_canonical_key() {
+        let mut game = setup_game();
+        game.make_move(Position { row: 0, col: 3 }, Position { row: 0, col: 2 })
+            .unwrap();
+        let mirrored: Board = (0..game.size)
+            .map(|r| (0..game.size).map(|c| game.board[c][r]).collect())
+            .collect();
+        let mirror = Game::from_setup(mirrored, game.current_player).unwrap();
+
+        assert_ne!(mirror.position_key(), game.position_key());
+        assert_eq!(mirror.canonical_key(), game.canonical_key());
+        // The side to move still matters
+        let other_side = Game::from_setup(game.board.clone(), Player::P1).unwrap();
+        assert_ne!(other_side.canonical_key(), game.canonical_key());
+        // The opening is its own mirror image
+        assert_eq!(Game::new().canonical_key(), Game::new().position_key());
+    }
+
+    #[test]
+    fn test_zero_length_move_is_rejected() {
+        let mut game = setup_game();
+        let square = Position { row: 0, col: 3 };
+        assert_eq!(
+            game.make_move(square, square),
+            Err("Source and destination must differ.")
+        );
+        assert_eq!(game, Game::new());
+
+        // Not even a move from an empty square, or one that is off the board, gets further
+        let off_board = Position { row: 9, col: 9 };
+        assert_eq!(
+            game.make_move(off_board, off_board),
+            Err("Source and destination must differ.")
+        );
+        for mv in game.all_valid_moves(Player::P1) {
+            assert_ne!(mv.from, mv.to);
         }
-        false
     }
 
-    // --- HELPER VALIDATION FUNCTIONS ---
+    #[test]
+    fn test_changes_since_skips_squares_that_were_restored() {
+        let mut game = Game::new();
+        let square = |row, col| Position { row, col };
+        game.make_move(square(0, 3), square(0, 2)).unwrap();
+        game.make_move(square(6, 3), square(6, 4)).unwrap();
+        game.make_move(square(0, 2), square(0, 3)).unwrap();
 
-    /// Counts the number of adjacent pieces to a square.
-    pub fn count_neighbors(&self, pos: Position) -> u8 {
-        let mut count = 0;
-        for r_offset in -1..=1 {
-            for c_offset in -1..=1 {
-                if r_offset == 0 && c_offset == 0 {
-                    continue;
-                } // Ignore the square itself
+        // P1's piece went out and came back, so only P2's move shows
+        assert_eq!(
+            game.changes_since(0),
+            Some(vec![(square(6, 3), None), (square(6, 4), Some(Player::P2))])
+        );
+        assert_eq!(game.changes_since(3), Some(vec![]));
+        assert_eq!(game.changes_since(4), None);
 
-                let check_row = pos.row as isize + r_offset as isize;
-                let check_col = pos.col as isize + c_offset as isize;
+        // A game restored without its history can only list everything
+        game.history.clear();
+        let all = game.changes_since(1).unwrap();
+        assert_eq!(all.len(), game.size * game.size);
+    }
 
-                if Self::is_on_board(check_row, check_col)
-                    && self.board[check_row as usize][check_col as usize].is_some()
-                {
-                    count += 1;
-                }
-            }
-        }
-        count
+    #[test]
+    fn test_ply_counts_half_moves() {
+        let mut game = setup_game();
+        assert_eq!(game.ply, 0);
+
+        game.make_move(Position { row: 0, col: 3 }, Position { row: 0, col: 2 })
+            .unwrap();
+        game.make_move(Position { row: 6, col: 3 }, Position { row: 6, col: 4 })
+            .unwrap();
+        // A rejected move doesn't count
+        assert!(game
+            .make_move(Position { row: 0, col: 2 }, Position { row: 1, col: 2 })
+            .is_err());
+        game.make_move(Position { row: 0, col: 2 }, Position { row: 0, col: 3 })
+            .unwrap();
+        assert_eq!(game.ply, 3);
+
+        game.undo_move().unwrap();
+        assert_eq!(game.ply, 2);
     }
 
-    /// Checks if a move from `from` to `to` respects all rules.
-    fn is_move_valid(&self, from: Position, to: Position) -> bool {
-        // Must be on the board
-        if !Self::is_on_board(to.row as isize, to.col as isize) {
-            return false;
-        }
-        // The destination square must be empty
-        if self.board[to.row][to.col].is_some() {
-            return false;
-        }
-        // Cannot move to its own base
-        if to == Self::get_goal_pos(self.current_player) {
-            return false;
+    #[test]
+    fn test_draw_by_threefold_repetition() {
+        let mut game = setup_game();
+        let shuffle = [
+            (Position { row: 0, col: 3 }, Position { row: 0, col: 2 }),
+            (Position { row: 6, col: 3 }, Position { row: 6, col: 4 }),
+            (Position { row: 0, col: 2 }, Position { row: 0, col: 3 }),
+            (Position { row: 6, col: 4 }, Position { row: 6, col: 3 }),
+        ];
+
+        // The starting position occurs for the second time
+        for (from, to) in shuffle {
+            game.make_move(from, to).unwrap();
         }
-        // Must have a clear path
-        if !self.is_path_clear(from, to) {
-            return false;
+        assert_eq!(game.status, GameStatus::Ongoing);
+
+        // ...and for the third time
+        for (from, to) in shuffle {
+            game.make_move(from, to).unwrap();
         }
+        assert_eq!(game.status, GameStatus::Draw);
 
-        true
+        // Taking back the repeating move lifts the draw
+        game.undo_move().unwrap();
+        assert_eq!(game.status, GameStatus::Ongoing);
     }
 
-    /// Checks that the path between two points is empty (no jumping).
-    fn is_path_clear(&self, from: Position, to: Position) -> bool {
-        let dr = (to.row as isize - from.row as isize).signum(); // Row direction: -1, 0, or 1
-        let dc = (to.col as isize - from.col as isize).signum(); // Column direction: -1, 0, or 1
-        let mut current_pos = from;
+    #[test]
+    fn test_three_player_game_rotates_turns_until_a_goal_is_reached() {
+        let config = GameConfig {
+            players: 3,
+            ..GameConfig::default()
+        };
+        // The default lines of neighbouring corners meet on the smaller boards
+        assert!(Game::new_with_config(DEFAULT_BOARD_SIZE, config.clone()).is_err());
 
-        // Move square by square until the second-to-last square of the path
-        while (current_pos.row as isize + dr) as usize != to.row
-            || (current_pos.col as isize + dc) as usize != to.col
-        {
-            current_pos.row = (current_pos.row as isize + dr) as usize;
-            current_pos.col = (current_pos.col as isize + dc) as usize;
-            if self.board[current_pos.row][current_pos.col].is_some() {
-                return false; // Obstacle found
-            }
+        let mut game = Game::new_with_config(8, config.clone()).unwrap();
+        assert_eq!(game.board[0][4], Some(Player::P3));
+        assert_eq!(game.get_goal_pos(Player::P3), Position { row: 0, col: 7 });
+        for (player, next) in [
+            (Player::P1, Player::P2),
+            (Player::P2, Player::P3),
+            (Player::P3, Player::P1),
+        ] {
+            assert_eq!(game.current_player, player);
+            let mv = game.all_valid_moves(player)[0];
+            game.make_move(mv.from, mv.to).unwrap();
+            assert_eq!(game.current_player, next);
         }
-        true
+        let code = game.to_code();
+        let loaded = Game::from_code_with_config(&code, config.clone()).unwrap();
+        assert_eq!(loaded.board, game.board);
+        assert!(Game::from_code_with_config(&code, GameConfig::default()).is_err());
+
+        // P3, with two neighbours, slides two squares onto Player 1's base and wins
+        let mut board = vec![vec![None; 8]; 8];
+        board[2][2] = Some(Player::P3);
+        board[3][3] = Some(Player::P1);
+        board[2][3] = Some(Player::P2);
+        let mut game = Game::from_setup_with_config(board, Player::P3, config).unwrap();
+        game.make_move(Position { row: 2, col: 2 }, Position { row: 0, col: 0 })
+            .unwrap();
+        assert_eq!(
+            game.status,
+            GameStatus::Won(Player::P3, WinReason::ReachedGoal)
+        );
+        assert!(game.undo_move().is_ok());
+        assert_eq!(game.current_player, Player::P3);
     }
 
-    /// Checks if coordinates (as i8 for calculations) are on the board.
-    fn is_on_board(row: isize, col: isize) -> bool {
-        (0..7).contains(&row) && (0..7).contains(&col)
+    #[test]
+    fn test_only_two_player_games_are_timed_or_resigned() {
+        let config = GameConfig {
+            players: 4,
+            clocks: Some(Clocks {
+                p1_ms: 1_000,
+                p2_ms: 1_000,
+            }),
+            ..GameConfig::default()
+        };
+        assert!(config
+            .errors(8)
+            .contains(&"Only two-player games can be timed.".to_string()));
+        assert!(GameConfig {
+            players: 5,
+            ..GameConfig::default()
+        }
+        .validate(8)
+        .is_err());
+        assert!(GameConfig {
+            p3_goal: Some(Position { row: 0, col: 6 }),
+            ..GameConfig::default()
+        }
+        .validate(7)
+        .is_err());
+
+        let mut game = Game::new_with_config(
+            8,
+            GameConfig {
+                clocks: None,
+                ..config
+            },
+        )
+        .unwrap();
+        assert_eq!(game.board[7][0], None);
+        assert_eq!(game.board[4][0], Some(Player::P4));
+        assert!(game.resign(Player::P1).is_err());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_code_round_trip() {
+        let mut game = setup_game();
+        assert_eq!(
+            Game::from_code_with_config(&game.to_code(), GameConfig::default()).unwrap(),
+            game
+        );
 
-    fn setup_game() -> Game {
-        Game::new()
+        game.make_move(Position { row: 0, col: 3 }, Position { row: 0, col: 2 })
+            .unwrap();
+        let code = game.to_code();
+        assert_eq!(code.len(), DEFAULT_BOARD_SIZE * DEFAULT_BOARD_SIZE + 1);
+        assert!(code.ends_with('2'));
+
+        let loaded = Game::from_code_with_config(&code, GameConfig::default()).unwrap();
+        assert_eq!(loaded.board, game.board);
+        assert_eq!(loaded.current_player, game.current_player);
+        assert_eq!(loaded.status, GameStatus::Ongoing);
     }
 
     #[test]
-    fn test_initial_board_setup() {
-        let game = setup_game();
-        assert_eq!(game.board[0][3], Some(Player::P1));
-        assert_eq!(game.board[3][0], Some(Player::P1));
-        assert_eq!(game.board[3][6], Some(Player::P2));
-        assert_eq!(game.board[6][3], Some(Player::P2));
-        assert_eq!(game.board[0][0], None);
+    fn test_transcript_round_trip() {
+        let mut game = setup_game();
+        let moves = [
+            (Position { row: 0, col: 3 }, Position { row: 0, col: 2 }),
+            (Position { row: 6, col: 3 }, Position { row: 6, col: 4 }),
+            (Position { row: 0, col: 2 }, Position { row: 0, col: 3 }),
+            (Position { row: 6, col: 4 }, Position { row: 6, col: 5 }),
+            (Position { row: 3, col: 0 }, Position { row: 4, col: 0 }),
+        ];
+        for (from, to) in moves {
+            game.make_move(from, to).unwrap();
+        }
+
+        let transcript = game.transcript();
+        assert_eq!(transcript.initial_config, Game::new().to_code());
+        assert_eq!(transcript.moves.len(), 5);
+        assert_eq!(Game::from_transcript(&transcript), Ok(game));
     }
 
     #[test]
-    fn test_initial_turn_is_p1() {
-        let game = setup_game();
-        assert_eq!(game.current_player, Player::P1);
+    fn test_replay_has_one_snapshot_per_ply() {
+        let mut game = setup_game();
+        game.make_move(Position { row: 0, col: 3 }, Position { row: 0, col: 2 })
+            .unwrap();
+        game.make_move(Position { row: 6, col: 3 }, Position { row: 6, col: 4 })
+            .unwrap();
+
+        let snapshots = game.replay();
+        assert_eq!(snapshots.len(), game.ply as usize + 1);
+        assert_eq!(snapshots[0], setup_game());
+        assert_eq!(snapshots[1].board[0][2], Some(Player::P1));
+        assert_eq!(snapshots[2], game);
     }
 
     #[test]
-    fn test_count_neighbors() {
-        let game = setup_game();
+    fn test_resigned_game_rewinds_to_the_start() {
+        let mut game = setup_game();
+        game.make_move(Position { row: 0, col: 3 }, Position { row: 0, col: 2 })
+            .unwrap();
+        game.make_move(Position { row: 6, col: 3 }, Position { row: 6, col: 4 })
+            .unwrap();
+        game.resign(Player::P1).unwrap();
+        assert!(game.undo_move().is_err());
 
-        // Piece at (0,3) has 1 neighbor at (1,2)
-        let pos = Position { row: 0, col: 3 };
-        assert_eq!(game.count_neighbors(pos), 1);
+        let transcript = game.transcript();
+        assert_eq!(transcript.initial_config, Game::new().to_code());
+        let replayed = Game::from_transcript(&transcript).unwrap();
+        assert_eq!(replayed.board, game.board);
+        assert_eq!(replayed.ply, 2);
 
-        // Piece at (3,0) has 1 neighbor at (2,1)
-        let pos = Position { row: 3, col: 0 };
-        assert_eq!(game.count_neighbors(pos), 1);
+        let snapshots = game.replay();
+        assert_eq!(snapshots.len(), game.ply as usize + 1);
+        assert_eq!(snapshots[0], setup_game());
+        assert_eq!(snapshots[2], game);
+    }
 
-        // Piece at (2,1) has 2 neighbors from the initial setup
-        let pos = Position { row: 2, col: 1 };
-        assert_eq!(game.count_neighbors(pos), 2);
+    #[test]
+    fn test_transcript_reports_first_illegal_move() {
+        let mut transcript = setup_game().transcript();
+        transcript.moves = vec![
+            MoveRequest {
+                from: Position { row: 0, col: 3 },
+                to: Position { row: 0, col: 2 },
+            },
+            // P2 to move, but this is a P1 piece
+            MoveRequest {
+                from: Position { row: 0, col: 2 },
+                to: Position { row: 0, col: 3 },
+            },
+        ];
+        let error = Game::from_transcript(&transcript).unwrap_err();
+        assert_eq!(error.move_index, Some(1));
+
+        transcript.initial_config = "nonsense".to_string();
+        let error = Game::from_transcript(&transcript).unwrap_err();
+        assert_eq!(error.move_index, None);
     }
 
     #[test]
-    fn test_valid_move() {
-        let mut game = setup_game();
-        let from = Position { row: 0, col: 3 };
-        let to = Position { row: 0, col: 2 };
+    fn test_code_rejects_malformed_input() {
+        let code = setup_game().to_code();
 
-        let result = game.make_move(from, to);
-        assert!(result.is_ok());
-        assert_eq!(game.board[from.row][from.col], None);
-        assert_eq!(game.board[to.row][to.col], Some(Player::P1));
-        assert_eq!(game.current_player, Player::P2);
+        // Wrong length
+        assert!(Game::from_code_with_config(&code[1..], GameConfig::default()).is_err());
+        assert!(Game::from_code_with_config("", GameConfig::default()).is_err());
+
+        // Unknown square character
+        let bad_square = format!("x{}", &code[1..]);
+        assert!(Game::from_code_with_config(&bad_square, GameConfig::default()).is_err());
+
+        // Unknown player to move
+        let bad_player = format!("{}3", &code[..code.len() - 1]);
+        assert!(Game::from_code_with_config(&bad_player, GameConfig::default()).is_err());
+
+        // Too many pieces for one side
+        let crowded = format!("1111{}", &code[4..]);
+        assert!(Game::from_code_with_config(&crowded, GameConfig::default()).is_err());
     }
 
     #[test]
-    fn test_invalid_move_occupied_destination() {
+    fn test_cannot_move_to_own_base_regardless_of_turn() {
         let mut game = setup_game();
-        let from = Position { row: 0, col: 3 };
-        let to = Position { row: 1, col: 2 };
-        let result = game.make_move(from, to);
-        assert!(result.is_err());
+        game.set_board(vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE]);
+        // P1 at (1,1) has one neighbor, so (0,0) is one step away but is its own base
+        game.set_square(Position { row: 1, col: 1 }, Some(Player::P1));
+        game.set_square(Position { row: 2, col: 2 }, Some(Player::P2));
+        let goal = Position { row: 0, col: 0 };
+
+        game.current_player = Player::P1;
+        assert!(!game
+            .get_valid_moves_for_piece(Position { row: 1, col: 1 })
+            .contains(&goal));
+        game.current_player = Player::P2;
+        assert!(!game
+            .get_valid_moves_for_piece(Position { row: 1, col: 1 })
+            .contains(&goal));
     }
 
     #[test]
@@ -307,14 +2346,14 @@ mod tests {
         game.current_player = Player::P1;
 
         // Manually set up the board for P1 to win in one move to the opponent's goal (6,6)
-        game.board = [[None; 7]; 7];
+        game.set_board(vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE]);
         let from = Position { row: 3, col: 3 };
-        game.board[from.row][from.col] = Some(Player::P1);
+        game.set_square(from, Some(Player::P1));
 
         // Add 3 neighbors to the piece at (3,3) so it can move 3 steps
-        game.board[2][2] = Some(Player::P2);
-        game.board[2][4] = Some(Player::P2);
-        game.board[4][2] = Some(Player::P2);
+        game.set_square(Position { row: 2, col: 2 }, Some(Player::P2));
+        game.set_square(Position { row: 2, col: 4 }, Some(Player::P2));
+        game.set_square(Position { row: 4, col: 2 }, Some(Player::P2));
 
         let to = Position { row: 6, col: 6 };
 
@@ -322,7 +2361,143 @@ mod tests {
 
         // The move is valid and results in a win
         assert!(result.is_ok());
-        assert_eq!(game.status, GameStatus::Won(Player::P1));
+        assert_eq!(
+            game.status,
+            GameStatus::Won(Player::P1, WinReason::ReachedGoal)
+        );
+    }
+
+    #[test]
+    fn test_setup_detects_decided_positions() {
+        let empty = || vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE];
+
+        // P1 already sits on P2's base
+        let mut board = empty();
+        board[6][6] = Some(Player::P1);
+        board[5][5] = Some(Player::P2);
+        let game = Game::from_setup(board, Player::P2).unwrap();
+        assert_eq!(
+            game.status,
+            GameStatus::Won(Player::P1, WinReason::ReachedGoal)
+        );
+
+        // P2 is to move but its lone piece has no neighbors, while P1 could move
+        let mut board = empty();
+        board[3][3] = Some(Player::P2);
+        board[0][5] = Some(Player::P1);
+        board[0][6] = Some(Player::P1);
+        let game = Game::from_setup(board, Player::P2).unwrap();
+        assert_eq!(
+            game.status,
+            GameStatus::Won(Player::P1, WinReason::NoMovesLeft)
+        );
+
+        // Too many pieces is rejected like on deserialization
+        let mut board = empty();
+        for square in &mut board[3][..=PIECES_PER_PLAYER] {
+            *square = Some(Player::P1);
+        }
+        assert!(Game::from_setup(board, Player::P1).is_err());
+    }
+
+    #[test]
+    fn test_draw_at_ply_limit() {
+        let mut game = setup_game();
+        game.config.ply_limit = 3;
+
+        game.make_move(Position { row: 0, col: 3 }, Position { row: 0, col: 2 })
+            .unwrap();
+        game.make_move(Position { row: 6, col: 3 }, Position { row: 6, col: 4 })
+            .unwrap();
+        assert_eq!(game.status, GameStatus::Ongoing);
+
+        // The limit-reaching move is played, then the game is drawn
+        game.make_move(Position { row: 3, col: 0 }, Position { row: 4, col: 0 })
+            .unwrap();
+        assert_eq!(game.ply, 3);
+        assert_eq!(game.status, GameStatus::Draw);
+
+        game.undo_move().unwrap();
+        assert_eq!(game.status, GameStatus::Ongoing);
+    }
+
+    // P1 to move; after (3,0) -> (4,0), P2's only piece is boxed into a corner
+    fn setup_stalemate(rule: StalemateRule) -> Game {
+        let mut game = setup_game();
+        game.config.stalemate_rule = rule;
+        game.set_board(vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE]);
+        game.set_square(Position { row: 0, col: 0 }, Some(Player::P2));
+        game.set_square(Position { row: 0, col: 1 }, Some(Player::P1));
+        game.set_square(Position { row: 1, col: 0 }, Some(Player::P1));
+        game.set_square(Position { row: 1, col: 1 }, Some(Player::P1));
+        game.set_square(Position { row: 3, col: 0 }, Some(Player::P1));
+        game.set_square(Position { row: 2, col: 1 }, Some(Player::P1));
+        game
+    }
+
+    #[test]
+    fn test_stalemate_rules() {
+        let from = Position { row: 3, col: 0 };
+        let to = Position { row: 4, col: 0 };
+
+        let mut game = setup_stalemate(StalemateRule::OpponentWins);
+        game.make_move(from, to).unwrap();
+        assert_eq!(
+            game.status,
+            GameStatus::Won(Player::P1, WinReason::NoMovesLeft)
+        );
+
+        let mut game = setup_stalemate(StalemateRule::Draw);
+        let before = game.clone();
+        game.make_move(from, to).unwrap();
+        assert_eq!(game.status, GameStatus::Draw);
+        game.undo_move().unwrap();
+        assert_eq!(game, before);
+    }
+
+    #[test]
+    fn test_gridlock_is_a_draw_not_a_win() {
+        // After (3,3) -> (3,2) every piece on the board is isolated, so neither side
+        // can move. The stuck player mustn't lose for it.
+        let mut board = vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE];
+        board[3][3] = Some(Player::P1);
+        board[3][4] = Some(Player::P1);
+        board[0][6] = Some(Player::P2);
+        let mut game = Game::from_setup(board, Player::P1).unwrap();
+        assert_eq!(game.config.stalemate_rule, StalemateRule::OpponentWins);
+        let before = game.clone();
+
+        game.make_move(Position { row: 3, col: 3 }, Position { row: 3, col: 2 })
+            .unwrap();
+        assert!(!game.has_any_valid_moves(Player::P1));
+        assert!(!game.has_any_valid_moves(Player::P2));
+        assert_eq!(game.status, GameStatus::Draw);
+
+        // Set up directly, the same position is drawn from the start
+        let gridlocked = Game::from_setup(game.board.clone(), Player::P2).unwrap();
+        assert_eq!(gridlocked.status, GameStatus::Draw);
+
+        game.undo_move().unwrap();
+        assert_eq!(game, before);
+    }
+
+    #[test]
+    fn test_ray_towards_a_square() {
+        let game = setup_game();
+        let corner = Position { row: 0, col: 0 };
+        assert_eq!(
+            corner.direction_to(&Position { row: 6, col: 2 }),
+            Some((1, 1))
+        );
+        assert_eq!(corner.direction_to(&corner), None);
+
+        // From (0,3) along row 0 towards (0,0): (0,2) and (0,1) are empty, (0,0)
+        // is a goal but also empty, and the board ends after it
+        let from = Position { row: 0, col: 3 };
+        assert!(game.is_ray_clear(from, (0, -1), 3));
+        assert!(!game.is_ray_clear(from, (0, -1), 4));
+        // (1,2) holds one of Player 1's pieces
+        assert!(!game.is_ray_clear(from, (1, -1), 1));
     }
 
     #[test]
@@ -332,15 +2507,15 @@ mod tests {
 
         // Set up a simple scenario where P2 has no valid moves.
         // P2's only piece is in a corner and surrounded.
-        game.board = [[None; 7]; 7];
-        game.board[0][0] = Some(Player::P2);
-        game.board[0][1] = Some(Player::P1);
-        game.board[1][0] = Some(Player::P1);
-        game.board[1][1] = Some(Player::P1);
+        game.set_board(vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE]);
+        game.set_square(Position { row: 0, col: 0 }, Some(Player::P2));
+        game.set_square(Position { row: 0, col: 1 }, Some(Player::P1));
+        game.set_square(Position { row: 1, col: 0 }, Some(Player::P1));
+        game.set_square(Position { row: 1, col: 1 }, Some(Player::P1));
 
         // P1 makes a valid move. P1 piece at (3,0) moves to (4,0).
-        game.board[3][0] = Some(Player::P1);
-        game.board[2][1] = Some(Player::P1);
+        game.set_square(Position { row: 3, col: 0 }, Some(Player::P1));
+        game.set_square(Position { row: 2, col: 1 }, Some(Player::P1));
         let from = Position { row: 3, col: 0 };
         let to = Position { row: 4, col: 0 };
 
@@ -349,6 +2524,441 @@ mod tests {
         // The move is valid. The win condition should now be triggered
         // because P2 has no moves left.
         assert!(result.is_ok());
-        assert_eq!(game.status, GameStatus::Won(Player::P1));
+        assert_eq!(
+            game.status,
+            GameStatus::Won(Player::P1, WinReason::NoMovesLeft)
+        );
+    }
+
+    #[test]
+    fn test_apply_then_undo_restores_game_exactly() {
+        let start = Game::new();
+        let mut game = start.clone();
+
+        // Play a long line, always taking the first legal move, then take it all back
+        let mut tokens = Vec::new();
+        while game.status == GameStatus::Ongoing && tokens.len() < 40 {
+            let mv = game.all_valid_moves(game.current_player)[0];
+            tokens.push(game.apply_move(mv.from, mv.to));
+        }
+        assert!(tokens.len() > 10);
+        while let Some(token) = tokens.pop() {
+            game.undo_with(token);
+        }
+
+        assert_eq!(game, start);
+        assert_eq!(
+            serde_json::to_string(&game).unwrap(),
+            serde_json::to_string(&start).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_position_new_checks_board_bounds() {
+        assert_eq!(Position::new(5, 5, 6), Some(Position { row: 5, col: 5 }));
+        assert_eq!(Position::new(6, 0, 6), None);
+        assert_eq!(Position::new(0, 6, 6), None);
+        assert!(Position::new(6, 0, DEFAULT_BOARD_SIZE).is_some());
+    }
+
+    #[test]
+    fn test_neighbor_counts_stay_in_step_with_the_board() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let assert_counts_fresh = |game: &Game| {
+            for row in 0..game.size {
+                for col in 0..game.size {
+                    let pos = Position { row, col };
+                    assert_eq!(
+                        game.count_neighbors(pos),
+                        game.count_neighbors_uncached(pos),
+                        "({},{}) after {} plies",
+                        row,
+                        col,
+                        game.ply
+                    );
+                }
+            }
+        };
+
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..20 {
+            let mut game = Game::new();
+            let mut tokens = Vec::new();
+            while game.status == GameStatus::Ongoing {
+                let moves = game.all_valid_moves(game.current_player);
+                let mv = moves[rng.random_range(0..moves.len())];
+                tokens.push(game.apply_move(mv.from, mv.to));
+                assert_counts_fresh(&game);
+            }
+            while let Some(token) = tokens.pop() {
+                game.undo_with(token);
+                assert_counts_fresh(&game);
+            }
+            assert_eq!(game, Game::new());
+        }
+    }
+
+    #[test]
+    fn test_alignment_gives_unit_direction() {
+        let center = Position { row: 3, col: 3 };
+        let cases = [
+            ((3, 6), Some((0, 1))),   // Same row, to the right
+            ((3, 0), Some((0, -1))),  // Same row, to the left
+            ((0, 3), Some((-1, 0))),  // Same column, above
+            ((5, 3), Some((1, 0))),   // Same column, below
+            ((6, 6), Some((1, 1))),   // Main diagonal
+            ((1, 1), Some((-1, -1))), // Main diagonal, the other way
+            ((1, 5), Some((-1, 1))),  // Anti-diagonal
+            ((5, 1), Some((1, -1))),  // Anti-diagonal, the other way
+            ((4, 5), None),           // A knight's move away
+            ((3, 3), None),           // The square itself
+        ];
+        for ((row, col), expected) in cases {
+            let other = Position { row, col };
+            assert_eq!(center.alignment(&other), expected, "({},{})", row, col);
+        }
+    }
+
+    #[test]
+    fn test_player_accepts_friendly_spellings() {
+        for spelling in [r#""P1""#, r#""p1""#, r#""red""#, r#""RED""#, r#""1""#, "1"] {
+            let player: Player = serde_json::from_str(spelling).unwrap();
+            assert_eq!(player, Player::P1, "{}", spelling);
+        }
+        for spelling in [
+            r#""P2""#,
+            r#""p2""#,
+            r#""blue""#,
+            r#""Blue""#,
+            r#""2""#,
+            "2",
+        ] {
+            let player: Player = serde_json::from_str(spelling).unwrap();
+            assert_eq!(player, Player::P2, "{}", spelling);
+        }
+        assert_eq!(
+            serde_json::from_str::<Player>(r#""Green""#).unwrap(),
+            Player::P3
+        );
+        assert_eq!(serde_json::from_str::<Player>("4").unwrap(), Player::P4);
+        assert!(serde_json::from_str::<Player>(r#""purple""#).is_err());
+        assert!(serde_json::from_str::<Player>("5").is_err());
+        assert!(serde_json::from_str::<Player>("0").is_err());
+
+        // Serialization keeps the canonical names
+        assert_eq!(serde_json::to_string(&Player::P2).unwrap(), r#""P2""#);
+
+        let status: GameStatus = serde_json::from_str(r#"{"Won":["blue","ReachedGoal"]}"#).unwrap();
+        assert_eq!(status, GameStatus::Won(Player::P2, WinReason::ReachedGoal));
+        let status: GameStatus = serde_json::from_str(r#"{"Won":[1,"NoMovesLeft"]}"#).unwrap();
+        assert_eq!(status, GameStatus::Won(Player::P1, WinReason::NoMovesLeft));
+    }
+
+    #[test]
+    fn test_movable_pieces_skip_frozen_ones() {
+        // At the start every piece touches another one on its diagonal
+        let game = Game::new();
+        for player in [Player::P1, Player::P2] {
+            let movable = game.movable_pieces(player);
+            let start = game.config.start_squares(player, game.size);
+            assert_eq!(movable.len(), start.len());
+            assert!(start.iter().all(|pos| movable.contains(pos)));
+            assert!(movable.iter().all(|&pos| game.count_neighbors(pos) > 0));
+        }
+
+        // A piece on its own has no neighbors, so it can't move
+        let mut board = vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE];
+        board[0][3] = Some(Player::P1);
+        board[1][2] = Some(Player::P1);
+        board[5][0] = Some(Player::P1);
+        board[6][3] = Some(Player::P2);
+        board[5][4] = Some(Player::P2);
+        let game = Game::from_setup(board, Player::P1).unwrap();
+        assert_eq!(
+            game.movable_pieces(Player::P1),
+            vec![Position { row: 0, col: 3 }, Position { row: 1, col: 2 }]
+        );
+    }
+
+    #[test]
+    fn test_move_distance_is_enforced_on_every_path() {
+        let mut game = Game::new();
+        // (2,1) has two neighbors, so a one-step move to the empty (2,2) is illegal
+        let from = Position { row: 2, col: 1 };
+        let to = Position { row: 2, col: 2 };
+        assert_eq!(game.count_neighbors(from), 2);
+        assert!(!game.is_move_valid(from, to));
+        assert!(!game.is_move_valid(from, Position { row: 3, col: 3 }));
+        assert_eq!(game.check_move(from, to), Err("Illegal move."));
+        assert!(game.make_move(from, to).is_err());
+
+        // Two squares along the same line is fine
+        let two_steps = Position { row: 2, col: 3 };
+        assert!(game.is_move_valid(from, two_steps));
+        assert!(game.make_move(from, two_steps).is_ok());
+    }
+
+    #[test]
+    fn test_resigning_hands_the_win_to_the_opponent() {
+        let mut game = Game::new();
+        assert_eq!(
+            game.resign(Player::P2),
+            Err("Only the player to move may resign.")
+        );
+        game.resign(Player::P1).unwrap();
+        assert_eq!(
+            game.status,
+            GameStatus::Won(Player::P2, WinReason::Resignation)
+        );
+        assert_eq!(game.resign(Player::P1), Err("Game is already over."));
+        assert!(game.undo_move().is_err());
+
+        let mut game = Game::new_with_config(
+            DEFAULT_BOARD_SIZE,
+            GameConfig {
+                resign_out_of_turn: true,
+                ..GameConfig::default()
+            },
+        )
+        .unwrap();
+        game.resign(Player::P2).unwrap();
+        assert_eq!(
+            game.status,
+            GameStatus::Won(Player::P1, WinReason::Resignation)
+        );
+    }
+
+    #[test]
+    fn test_running_out_of_time_forfeits() {
+        let config = GameConfig {
+            clocks: Some(Clocks {
+                p1_ms: 1000,
+                p2_ms: 1000,
+            }),
+            ..GameConfig::default()
+        };
+        let mut game = Game::new_with_config(DEFAULT_BOARD_SIZE, config).unwrap();
+        game.clock.as_mut().unwrap().turn_started_ms = 0;
+
+        // Player 1 thinks for 400ms, then Player 2 for 500ms
+        game.make_move_at(
+            Position { row: 0, col: 3 },
+            Position { row: 0, col: 2 },
+            400,
+        )
+        .unwrap();
+        game.make_move_at(
+            Position { row: 6, col: 3 },
+            Position { row: 6, col: 4 },
+            900,
+        )
+        .unwrap();
+        let clock = game.clock.unwrap();
+        assert_eq!(
+            clock.remaining,
+            Clocks {
+                p1_ms: 600,
+                p2_ms: 500
+            }
+        );
+        assert_eq!(clock.turn_started_ms, 900);
+
+        // Player 1 then takes 700ms, more than they have left
+        assert_eq!(
+            game.make_move_at(
+                Position { row: 1, col: 2 },
+                Position { row: 1, col: 3 },
+                1600
+            ),
+            Err("Out of time.")
+        );
+        assert_eq!(
+            game.status,
+            GameStatus::Won(Player::P2, WinReason::TimeForfeit)
+        );
+        assert_eq!(game.clock.unwrap().remaining.p1_ms, 0);
+        assert_eq!(game.ply, 2);
+        assert!(game.undo_move().is_err());
+
+        // The game can still be exported and replayed from the start
+        let replayed = Game::from_transcript(&game.transcript()).unwrap();
+        assert_eq!(replayed.board, game.board);
+        assert_eq!(replayed.ply, 2);
+        let snapshots = game.replay();
+        assert_eq!(snapshots.len(), 3);
+        assert_eq!(snapshots[0].board, Game::new().board);
+        assert_eq!(snapshots[2], game);
+
+        // Untimed games never run out
+        let mut game = Game::new();
+        game.run_clock(u64::MAX);
+        assert_eq!(game.status, GameStatus::Ongoing);
+    }
+
+    #[test]
+    fn test_isolated_piece_steps_only_when_allowed() {
+        let mut board = vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE];
+        board[3][3] = Some(Player::P1);
+        board[6][0] = Some(Player::P2);
+        board[5][1] = Some(Player::P2);
+        let mut game = Game::from_setup(board, Player::P1).unwrap();
+        let pos = Position { row: 3, col: 3 };
+        assert!(game.get_valid_moves_for_piece(pos).is_empty());
+        assert!(!game.has_any_valid_moves(Player::P1));
+
+        game.config.allow_single_step_when_isolated = true;
+        let moves = game.get_valid_moves_for_piece(pos);
+        assert_eq!(moves.len(), 8);
+        assert!(moves
+            .iter()
+            .all(|to| to.row.abs_diff(3) <= 1 && to.col.abs_diff(3) <= 1));
+        assert!(game.make_move(pos, Position { row: 2, col: 2 }).is_ok());
+
+        // Pieces with neighbors still move one square per neighbor
+        assert_eq!(game.move_distance(Position { row: 5, col: 1 }), 1);
+    }
+
+    #[test]
+    fn test_reachable_squares_union_every_move() {
+        // Two touching pieces step one square; the lone one is frozen
+        let mut board = vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE];
+        board[3][3] = Some(Player::P1);
+        board[3][4] = Some(Player::P1);
+        board[0][0] = Some(Player::P1);
+        board[6][0] = Some(Player::P2);
+        let game = Game::from_setup(board, Player::P1).unwrap();
+
+        let expected: HashSet<Position> = [
+            (2, 2),
+            (2, 3),
+            (2, 4),
+            (2, 5),
+            (3, 2),
+            (3, 5),
+            (4, 2),
+            (4, 3),
+            (4, 4),
+            (4, 5),
+        ]
+        .into_iter()
+        .map(|(row, col)| Position { row, col })
+        .collect();
+        assert_eq!(game.reachable_squares(Player::P1), expected);
+        assert!(game.reachable_squares(Player::P2).is_empty());
+    }
+
+    #[test]
+    fn test_position_key_identifies_positions() {
+        let mut game = Game::new();
+        let start = game.position_key();
+        assert_eq!(Game::new().position_key(), start);
+
+        game.make_move(Position { row: 0, col: 3 }, Position { row: 0, col: 2 })
+            .unwrap();
+        let after = game.position_key();
+        assert_ne!(after, start);
+
+        // Same board, other side to move
+        game.current_player = Player::P1;
+        assert_ne!(game.position_key(), after);
+
+        game.current_player = Player::P2;
+        game.undo_move().unwrap();
+        assert_eq!(game.position_key(), start);
+
+        // Boards one change away from the start all get different keys
+        let mut keys = vec![start];
+        for r in 0..DEFAULT_BOARD_SIZE {
+            for c in 0..DEFAULT_BOARD_SIZE {
+                for owner in [None, Some(Player::P1), Some(Player::P2)] {
+                    let mut near = Game::new();
+                    if near.board[r][c] == owner {
+                        continue;
+                    }
+                    near.set_square(Position { row: r, col: c }, owner);
+                    keys.push(near.position_key());
+                }
+            }
+        }
+        // Swapping a piece's colour is a change too
+        let mut swapped = Game::new();
+        swapped.set_square(Position { row: 0, col: 3 }, Some(Player::P2));
+        swapped.set_square(Position { row: 6, col: 3 }, Some(Player::P1));
+        keys.push(swapped.position_key());
+
+        let count = keys.len();
+        keys.sort_unstable();
+        keys.dedup();
+        assert_eq!(keys.len(), count);
+    }
+
+    use proptest::prelude::*;
+    // Not the AI's `Strategy`, which the glob import from `super` also brings in
+    use proptest::strategy::Strategy;
+
+    // Any square, on the board or a little past its edge
+    fn any_position() -> impl Strategy<Value = Position> {
+        (0..MAX_BOARD_SIZE + 3, 0..MAX_BOARD_SIZE + 3).prop_map(|(row, col)| Position { row, col })
+    }
+
+    // A board of any supported size with pieces scattered at random. It isn't
+    // necessarily a reachable position, which is the point: nothing a client
+    // sends should be able to crash the server.
+    fn any_game() -> impl Strategy<Value = Game> {
+        (MIN_BOARD_SIZE..=MAX_BOARD_SIZE)
+            .prop_flat_map(|size| {
+                let square = prop_oneof![
+                    4 => Just(None),
+                    1 => Just(Some(Player::P1)),
+                    1 => Just(Some(Player::P2)),
+                ];
+                (
+                    proptest::collection::vec(proptest::collection::vec(square, size), size),
+                    any::<bool>(),
+                    any::<bool>(),
+                )
+            })
+            .prop_map(|(board, p2_to_move, single_step)| {
+                let mut game = Game::new_with_config(
+                    board.len(),
+                    GameConfig {
+                        allow_single_step_when_isolated: single_step,
+                        ..GameConfig::default()
+                    },
+                )
+                .unwrap();
+                game.set_board(board);
+                if p2_to_move {
+                    game.current_player = Player::P2;
+                }
+                game
+            })
+    }
+
+    proptest! {
+        // Off-board squares are turned away by `check_move` before anything indexes
+        // the board, and `from == to` has no alignment, so `is_path_clear` never
+        // walks a zero-length step. The panics found were `count_neighbors` and
+        // `is_move_valid` indexing with an off-board origin.
+        #[test]
+        fn fuzz_make_move_never_panics(
+            mut game in any_game(),
+            moves in proptest::collection::vec((any_position(), any_position()), 1..8),
+        ) {
+            for (from, to) in moves {
+                // Off-board squares used to index past the neighbor counts, or
+                // wrap around to the next row
+                if !game.is_on_board(from.row as isize, from.col as isize) {
+                    prop_assert_eq!(game.count_neighbors(from), 0);
+                    prop_assert!(game.get_valid_moves_for_piece(from).is_empty());
+                }
+                let before = game.clone();
+                match game.make_move(from, to) {
+                    Ok(()) => prop_assert!(before.get_valid_moves_for_piece(from).contains(&to)),
+                    Err(_) => prop_assert_eq!(&game, &before),
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,126 @@
+use crate::ai::{self, Difficulty, OpeningBook, SearchLimit};
+use crate::game::{Game, GameStatus, MoveRequest, Player};
+use std::io::{self, BufRead, Write};
+use std::time::Duration;
+
+/// Plays a game in the terminal: draws the board to `output` and reads moves in
+/// notation (e.g. `d1-c1`) from `input`, one per line. The AI moves for
+/// `ai_player`, if given. Also understands `undo` and `quit`.
+/// Returns the game as it stands when it ends, or when `input` runs out.
+pub fn play<R: BufRead, W: Write>(
+    input: R,
+    mut output: W,
+    ai_player: Option<Player>,
+    book: Option<&OpeningBook>,
+) -> io::Result<Game> {
+    let mut game = Game::new();
+    let mut lines = input.lines();
+    writeln!(
+        output,
+        "Moves are written as from-to, e.g. d1-c1: the column letter, then the row counted from 1 at the top."
+    )?;
+
+    while game.status == GameStatus::Ongoing {
+        write!(output, "\n{}", game.to_ascii(true))?;
+
+        if Some(game.current_player) == ai_player {
+            let settings = Difficulty::default().settings();
+            let limit = SearchLimit::Time {
+                budget: Duration::from_millis(settings.time_ms),
+                max_depth: settings.depth,
+                aspiration_window: ai::DEFAULT_ASPIRATION_WINDOW,
+            };
+            let (best_move, _) = ai::find_best_move_seeded(
+                &game,
+                game.current_player,
+                limit,
+                ai::DistanceMetric::default(),
+                settings.weights,
+                rand::random(),
+                book,
+            );
+            let Some((from, to, _)) = best_move else {
+                break;
+            };
+            let mv = MoveRequest { from, to };
+            writeln!(output, "AI plays {}", mv.to_notation())?;
+            if let Err(e) = game.make_move(from, to) {
+                writeln!(output, "AI made an invalid move: {}", e)?;
+                break;
+            }
+            continue;
+        }
+
+        write!(output, "> ")?;
+        output.flush()?;
+        let Some(line) = lines.next().transpose()? else {
+            break;
+        };
+        match line.trim() {
+            "" => {}
+            "quit" => break,
+            "undo" => {
+                // Against the AI, take back its reply as well
+                let plies = if ai_player.is_some() { 2 } else { 1 };
+                for _ in 0..plies {
+                    if let Err(e) = game.undo_move() {
+                        writeln!(output, "{}", e)?;
+                        break;
+                    }
+                }
+            }
+            notation => {
+                let result = MoveRequest::from_notation(notation)
+                    .and_then(|mv| game.make_move(mv.from, mv.to).map_err(str::to_string));
+                if let Err(e) = result {
+                    writeln!(output, "{}", e)?;
+                }
+            }
+        }
+    }
+
+    write!(output, "\n{}", game.to_ascii(true))?;
+    Ok(game)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scripted_game_plays_to_the_end() {
+        // Script a whole game by always taking the first legal move
+        let mut expected = Game::new();
+        let mut script = String::from("z9-a1\n\n");
+        while expected.status == GameStatus::Ongoing {
+            let mv = expected.all_valid_moves(expected.current_player)[0];
+            expected.make_move(mv.from, mv.to).unwrap();
+            script.push_str(&mv.to_notation());
+            script.push('\n');
+        }
+
+        let mut output = Vec::new();
+        let game = play(script.as_bytes(), &mut output, None, None).unwrap();
+        assert_eq!(game, expected);
+        assert_ne!(game.status, GameStatus::Ongoing);
+
+        // The bad first line was reported, not played
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Invalid square 'z9'."));
+    }
+
+    #[test]
+    fn test_quit_and_undo() {
+        let mut output = Vec::new();
+        let game = play(
+            "d1-c1\nd7-e7\nundo\nquit\nd1-c1\n".as_bytes(),
+            &mut output,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(game.ply, 1);
+        assert_eq!(game.current_player, Player::P2);
+        assert_eq!(game.status, GameStatus::Ongoing);
+    }
+}
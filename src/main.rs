@@ -1,147 +1,5099 @@
 use axum::{
-    extract::State,
-    http::StatusCode,
-    response::Json,
+    extract::{FromRequest, MatchedPath, Path, Query, RawPathParams, Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
     routing::{get, post},
     Router,
 };
-use std::sync::{Arc, Mutex};
+use clap::{Parser, ValueEnum};
+use futures_util::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
 use tower_http::{
-    cors::{Any, CorsLayer},
+    cors::{AllowOrigin, Any, CorsLayer},
     services::{ServeDir, ServeFile},
 };
-use tracing::{error, info};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use tracing::{error, info, warn, Instrument};
+use tracing_subscriber::{
+    fmt::MakeWriter, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer,
+};
+use uuid::Uuid;
 
-// Declare the game and AI modules
+// Declare the game, AI, benchmark, persistence and API documentation modules
 mod ai;
+mod bench;
 mod game;
+mod local;
+mod openapi;
+mod store;
 
-use game::{Game, GameStatus, MoveRequest, Player};
+use game::{
+    Board, Game, GameConfig, GameMode, GameStatus, MoveRequest, NeighborsResponse, Player,
+    Position, StatusResponse, Strategy, Transcript, TranscriptError, ValidMovesQuery,
+    ValidMovesResponse, DEFAULT_BOARD_SIZE, DEFAULT_PLAYERS,
+};
+use store::Store;
 
 // --- AXUM ROUTES & HANDLERS ---
 
-type AppState = Arc<Mutex<Game>>;
+// Shared by every handler: the running game sessions, keyed by the id handed
+// out by POST /games, and the database they are saved to when --db is given.
+#[derive(Debug, Clone, Default)]
+struct AppState {
+    games: Arc<Mutex<HashMap<Uuid, Game>>>,
+    store: Option<Store>,
+    store_writes: Arc<tokio::sync::Mutex<()>>, // Makes writes to `store` take turns
+    scoreboard: Arc<Mutex<Scoreboard>>,
+    book: Option<Arc<ai::OpeningBook>>, // Moves the AI plays without searching, from --book
+    rate_limiter: Option<Arc<RateLimiter>>, // Throttles the move endpoints, from --rate-limit
+    cors_origins: Arc<[HeaderValue]>, // Browser origins allowed to call the API, from --cors-origin; any if empty
+    events: GameEvents,               // Pushes every change of a game to its GET /events streams
+    seeds: Arc<Mutex<HashMap<Uuid, u64>>>, // AI seeds set with POST /seed, by session
+    session_limits: Option<Arc<SessionLimits>>, // Drops idle sessions, from --max-sessions and --session-ttl
+}
 
-async fn index() -> impl axum::response::IntoResponse {
-    info!("GET / requested.");
-    "Visit /board to see the game state."
+// How many updates a slow /events stream may fall behind before it skips some;
+// each update carries the whole game, so skipping loses nothing.
+const EVENT_BUFFER: usize = 64;
+
+// Broadcasts the new state of a game whenever it changes. A single channel serves
+// every session; each subscriber picks out the game it follows.
+#[derive(Debug, Clone)]
+struct GameEvents(broadcast::Sender<(Uuid, Arc<Game>)>);
+
+impl Default for GameEvents {
+    fn default() -> Self {
+        GameEvents(broadcast::channel(EVENT_BUFFER).0)
+    }
 }
 
-// Handles GET /board request. Returns the current game state as JSON.
-async fn get_board(State(state): State<AppState>) -> Json<Game> {
-    info!("GET /board requested.");
-    let game = state.lock().unwrap();
-    Json((*game).clone())
+// Token buckets limiting how often each session may call the move endpoints, so
+// one client can't keep the CPU busy with AI searches. Each session may make
+// `per_second` requests a second on average, in bursts of up to as many.
+// A bucket left alone for a second is full again, the same as a new one, so such
+// buckets are swept out, at most once a second.
+#[derive(Debug)]
+struct RateLimiter {
+    per_second: f64,
+    buckets: Mutex<Buckets>,
 }
 
-// Handles POST /move request. Attempts to make a move.
-async fn make_move(
-    State(state): State<AppState>,
-    Json(payload): Json<MoveRequest>,
-) -> (StatusCode, String) {
-    info!(
-        "POST /move requested: from ({},{}), to ({},{})",
-        payload.from.row, payload.from.col, payload.to.row, payload.to.col
-    );
-    let mut game = state.lock().unwrap();
+#[derive(Debug)]
+struct Buckets {
+    by_session: HashMap<Uuid, TokenBucket>,
+    swept: Instant, // When idle buckets were last removed
+}
 
-    if let GameStatus::Won(_) = game.status {
-        error!("Move failed: Game is already over.");
-        return (StatusCode::BAD_REQUEST, "Game is already over.".to_string());
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+// How long an unused bucket takes to fill up from empty
+const BUCKET_REFILL_TIME: Duration = Duration::from_secs(1);
+
+impl RateLimiter {
+    fn new(per_second: u32) -> Self {
+        RateLimiter {
+            per_second: f64::from(per_second),
+            buckets: Mutex::new(Buckets {
+                by_session: HashMap::new(),
+                swept: Instant::now(),
+            }),
+        }
+    }
+
+    fn buckets(&self) -> MutexGuard<'_, Buckets> {
+        self.buckets
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Drops the bucket of a session that no longer exists
+    fn forget(&self, game_id: Uuid) {
+        self.buckets().by_session.remove(&game_id);
     }
 
-    match game.make_move(payload.from, payload.to) {
-        Ok(_) => {
-            info!("Move successful.");
-            (StatusCode::OK, "Move accepted.".to_string())
+    // Takes a token from the session's bucket, or says how long until one is available
+    fn acquire(&self, game_id: Uuid, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets();
+        if now.saturating_duration_since(buckets.swept) >= BUCKET_REFILL_TIME {
+            buckets.by_session.retain(|_, bucket| {
+                now.saturating_duration_since(bucket.refilled) < BUCKET_REFILL_TIME
+            });
+            buckets.swept = now;
         }
-        Err(e) => {
-            error!("Move failed: {}", e);
-            (StatusCode::BAD_REQUEST, e.to_string())
+        let bucket = buckets.by_session.entry(game_id).or_insert(TokenBucket {
+            tokens: self.per_second,
+            refilled: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.refilled).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.per_second).min(self.per_second);
+        bucket.refilled = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.per_second,
+            ))
         }
     }
 }
 
-// Handles POST /ai-move request. Triggers the AI to make its move.
-async fn make_ai_move(State(state): State<AppState>) -> (StatusCode, String) {
-    info!("POST /ai-move requested.");
-    let mut game = state.lock().unwrap();
+// Most dropped session ids remembered for answering 410 Gone. Past that, the oldest
+// are forgotten and their clients get 404 like for any unknown id, so creating
+// sessions in a loop can't grow memory without bound.
+const MAX_TOMBSTONES: usize = 100_000;
+
+// The ids of the most recently dropped sessions, oldest first, up to `capacity`.
+#[derive(Debug, Default)]
+struct Tombstones {
+    capacity: usize,
+    order: VecDeque<Uuid>,
+    ids: HashSet<Uuid>,
+}
 
-    if let GameStatus::Won(_) = game.status {
-        error!("AI move failed: Game is already over.");
-        return (StatusCode::BAD_REQUEST, "Game is already over.".to_string());
+impl Tombstones {
+    fn new(capacity: usize) -> Self {
+        Tombstones {
+            capacity,
+            ..Tombstones::default()
+        }
     }
 
-    // The AI is always Player 2.
-    if game.current_player != Player::P2 {
-        error!("AI move failed: It's not the AI's turn.");
-        return (
-            StatusCode::BAD_REQUEST,
-            "It's not the AI's turn.".to_string(),
-        );
+    fn insert(&mut self, game_id: Uuid) {
+        if !self.ids.insert(game_id) {
+            return;
+        }
+        self.order.push_back(game_id);
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
     }
 
-    // Call the AI logic from the separate module
-    if let Some((from, to)) = ai::find_best_move(&game) {
-        match game.make_move(from, to) {
-            Ok(_) => {
-                info!("AI move successful.");
-                (StatusCode::OK, "AI move accepted.".to_string())
+    fn contains(&self, game_id: &Uuid) -> bool {
+        self.ids.contains(game_id)
+    }
+}
+
+// Keeps the number of sessions in check: a session left alone for longer than `ttl`
+// is dropped, and once `max_sessions` are open, creating another drops the one
+// idle the longest. Recently dropped ids are remembered so their clients get
+// 410 Gone rather than 404; an id takes far less room than a game.
+#[derive(Debug)]
+struct SessionLimits {
+    max_sessions: usize, // 0 for no cap
+    ttl: Option<Duration>,
+    last_access: Mutex<HashMap<Uuid, Instant>>,
+    evicted: Mutex<Tombstones>,
+}
+
+impl SessionLimits {
+    fn new(max_sessions: usize, ttl: Option<Duration>) -> Self {
+        SessionLimits {
+            max_sessions,
+            ttl,
+            last_access: Mutex::new(HashMap::new()),
+            evicted: Mutex::new(Tombstones::new(MAX_TOMBSTONES)),
+        }
+    }
+
+    fn last_access(&self) -> MutexGuard<'_, HashMap<Uuid, Instant>> {
+        self.last_access
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn evicted(&self) -> MutexGuard<'_, Tombstones> {
+        self.evicted
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn is_expired(&self, last_access: Instant, now: Instant) -> bool {
+        self.ttl
+            .is_some_and(|ttl| now.saturating_duration_since(last_access) > ttl)
+    }
+
+    // Removes a session from `games` and remembers that it was dropped
+    fn evict(&self, games: &mut HashMap<Uuid, Game>, game_id: Uuid) {
+        games.remove(&game_id);
+        self.last_access().remove(&game_id);
+        self.evicted().insert(game_id);
+    }
+
+    // Drops every expired session, then the longest idle ones until there is room
+    // for one more. Returns the ids dropped.
+    fn make_room(&self, games: &mut HashMap<Uuid, Game>, now: Instant) -> Vec<Uuid> {
+        let mut idle: Vec<(Instant, Uuid)> = {
+            let last_access = self.last_access();
+            games
+                .keys()
+                .map(|id| (last_access.get(id).copied().unwrap_or(now), *id))
+                .collect()
+        };
+        idle.sort();
+
+        let mut dropped = Vec::new();
+        for (last_access, id) in idle {
+            let over_capacity = self.max_sessions > 0 && games.len() >= self.max_sessions;
+            if !over_capacity && !self.is_expired(last_access, now) {
+                break;
             }
-            Err(e) => {
-                error!("AI move failed during execution: {}", e);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "AI made an invalid move.".to_string(),
-                )
+            self.evict(games, id);
+            dropped.push(id);
+        }
+        dropped
+    }
+}
+
+// Results of every game finished on this server since it started, returned by GET /stats.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+struct Scoreboard {
+    p1_wins: u32,
+    p2_wins: u32,
+    p3_wins: u32,
+    p4_wins: u32,
+    draws: u32,
+}
+
+impl Scoreboard {
+    // The counter for games ending with `status`, if it is a result
+    fn counter(&mut self, status: GameStatus) -> Option<&mut u32> {
+        match status {
+            GameStatus::Ongoing => None,
+            GameStatus::Won(Player::P1, _) => Some(&mut self.p1_wins),
+            GameStatus::Won(Player::P2, _) => Some(&mut self.p2_wins),
+            GameStatus::Won(Player::P3, _) => Some(&mut self.p3_wins),
+            GameStatus::Won(_, _) => Some(&mut self.p4_wins),
+            GameStatus::Draw => Some(&mut self.draws),
+        }
+    }
+
+    // Counts a game whose status went from `before` to `after`. Only the move that
+    // ends a game counts it, and undoing that move takes it back off.
+    fn record(&mut self, before: GameStatus, after: GameStatus) {
+        if before == after {
+            return;
+        }
+        if let Some(count) = self.counter(before) {
+            *count = count.saturating_sub(1);
+        }
+        if let Some(count) = self.counter(after) {
+            *count += 1;
+        }
+    }
+}
+
+impl AppState {
+    // Locks the game sessions. If a handler panicked while holding the lock, the
+    // sessions are still taken over rather than failing every later request; the
+    // game that was being changed may be left half-updated, which is logged.
+    fn games(&self) -> MutexGuard<'_, HashMap<Uuid, Game>> {
+        self.games.lock().unwrap_or_else(|poisoned| {
+            warn!("Game state lock was poisoned by a panic; recovering it.");
+            self.games.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+
+    // The seed the AI uses for the move at `ply` of a session whose seed was set with
+    // POST /seed. Each ply gets its own seed, so successive moves break ties
+    // differently, yet the whole game can be replayed from the session seed.
+    fn move_seed(&self, game_id: Uuid, ply: u32) -> Option<u64> {
+        let seeds = self
+            .seeds
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let seed = *seeds.get(&game_id)?;
+        Some(seed ^ u64::from(ply).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+    }
+
+    // Adds a new session, first dropping idle ones if the session limits call for it
+    async fn open_session(&self, game_id: Uuid, game: Game) {
+        let mut dropped = Vec::new();
+        {
+            let mut games = self.games();
+            if let Some(limits) = &self.session_limits {
+                let now = Instant::now();
+                dropped = limits.make_room(&mut games, now);
+                limits.last_access().insert(game_id, now);
             }
+            games.insert(game_id, game);
+        }
+        for id in dropped {
+            self.forget_session(id).await;
+        }
+    }
+
+    // Tidies up after a session was dropped for being idle
+    async fn forget_session(&self, game_id: Uuid) {
+        info!("Game {} was idle and has been closed.", game_id);
+        self.seeds
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(&game_id);
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.forget(game_id);
+        }
+        self.persist(game_id).await;
+    }
+
+    // Updates the scoreboard for a game whose status went from `before` to `after`
+    fn record_result(&self, before: GameStatus, after: GameStatus) {
+        self.scoreboard
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .record(before, after);
+    }
+
+    // Hands out the new state of a game: saves it to the database, if there is one,
+    // and pushes it to the game's /events streams. A failed write is logged but
+    // doesn't fail the request, since the in-memory copy is the one being played.
+    async fn publish(&self, game_id: Uuid, game: &Game) {
+        self.persist(game_id).await;
+        // Sending only fails when nobody is listening
+        let _ = self.events.0.send((game_id, Arc::new(game.clone())));
+    }
+
+    // Saves the session's game as it is now, not as it was when the request that
+    // changed it finished, or deletes it once the session is closed. Writes take
+    // turns, so one held up behind another can't land after it with an older copy
+    // of the game, nor bring back a closed one.
+    async fn persist(&self, game_id: Uuid) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        let _turn = self.store_writes.lock().await;
+        let current = self.games().get(&game_id).cloned();
+        let result = match current {
+            Some(game) => store.save(game_id, &game).await,
+            None => store.delete(game_id).await,
+        };
+        if let Err(e) = result {
+            error!("Persisting game {} failed: {}", game_id, e);
         }
-    } else {
-        error!("AI move failed: No valid moves found.");
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "AI could not find a move.".to_string(),
-        )
     }
 }
 
-// Handles POST /reset request. Resets the game to its initial state.
-async fn reset_game(State(state): State<AppState>) -> (StatusCode, String) {
-    info!("POST /reset requested.");
-    let mut game = state.lock().unwrap();
-    *game = Game::new();
-    info!("Game reset successfully.");
-    (StatusCode::OK, "Game reset.".to_string())
+// Command-line options.
+#[derive(Debug, Parser)]
+struct Args {
+    /// Address and port the server listens on
+    #[arg(long, env = "TOPCAP_ADDR", default_value = "0.0.0.0:3000")]
+    addr: SocketAddr,
+    /// SQLite file to save games to and reload them from; games live only in memory without it
+    #[arg(long, value_name = "PATH")]
+    db: Option<String>,
+    /// JSON opening book mapping position codes (see GET /code) to the move the AI should play
+    #[arg(long, value_name = "PATH")]
+    book: Option<String>,
+    /// Play in the terminal instead of starting the server
+    #[arg(long)]
+    local: bool,
+    /// Requests per second each session may make to /move and /ai-move; 0 turns the limit off
+    #[arg(long, value_name = "N", default_value_t = 10)]
+    rate_limit: u32,
+    /// With --local, let the AI play Player 2
+    #[arg(long, requires = "local")]
+    versus_ai: bool,
+    /// Browser origin allowed to call the API, e.g. https://example.com; repeat for
+    /// several. Any origin is allowed when none is given
+    #[arg(long = "cors-origin", value_name = "ORIGIN", value_parser = parse_origin)]
+    cors_origins: Vec<HeaderValue>,
+    /// Time the AI on a fixed set of positions at depths 1 to 4 instead of starting the server
+    #[arg(long)]
+    bench_ai: bool,
+    /// Most sessions kept at once; creating another closes the one idle the longest. 0 for no cap
+    #[arg(long, value_name = "N", default_value_t = 10_000)]
+    max_sessions: usize,
+    /// Seconds a session may sit idle before it is closed; 0 keeps idle sessions forever
+    #[arg(long, value_name = "SECS", default_value_t = 86_400)]
+    session_ttl: u64,
+    /// How log lines are written; which ones are written is set with RUST_LOG
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
 }
 
-#[tokio::main]
-async fn main() {
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stdout))
-        .init();
+// Shape of the server's log lines, chosen with --log-format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum LogFormat {
+    Text, // Human-readable lines
+    Json, // One JSON object per line, for log aggregators
+}
 
-    info!("Starting server...");
+// Level logged when RUST_LOG isn't set.
+const DEFAULT_LOG_FILTER: &str = "info";
 
-    let shared_state = AppState::new(Mutex::new(Game::new()));
+// The layer writing log lines in `format` to `writer`.
+fn log_layer<S, W>(format: LogFormat, writer: W) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer);
+    match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    }
+}
 
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_headers(Any)
-        .allow_methods(Any);
+// Checks that `origin` is a bare origin as browsers send it: an http(s) scheme
+// and a host, with an optional port but no path.
+fn parse_origin(origin: &str) -> Result<HeaderValue, String> {
+    let host = origin
+        .strip_prefix("http://")
+        .or_else(|| origin.strip_prefix("https://"))
+        .ok_or_else(|| format!("'{}' must start with http:// or https://", origin))?;
+    if host.is_empty() || host.contains('/') {
+        return Err(format!(
+            "'{}' must be a scheme and host only, like https://example.com",
+            origin
+        ));
+    }
+    HeaderValue::from_str(origin).map_err(|e| format!("'{}' is not a valid origin: {}", origin, e))
+}
 
-    let serve_dir = ServeDir::new("assets").not_found_service(ServeFile::new("assets/index.html"));
-    let app = Router::new()
-        .route("/", get(index))
-        .route("/board", get(get_board))
-        .route("/move", post(make_move))
-        .route("/ai-move", post(make_ai_move))
-        .route("/reset", post(reset_game))
-        .fallback_service(serve_dir)
-        .with_state(shared_state)
-        .layer(cors);
+// Query parameters accepted by the AI move and hint endpoints.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+struct AiMoveQuery {
+    #[serde(default)]
+    difficulty: ai::Difficulty, // Preset search parameters; `depth`, `ms` and weights override them
+    depth: Option<u8>, // Fixed search depth; takes precedence over `ms`
+    ms: Option<u64>,   // Time budget for iterative deepening
+    #[serde(default)]
+    stats: bool, // Include search statistics in the response
+    #[serde(default)]
+    distance: ai::DistanceMetric, // How the evaluation measures distance to the goal
+    seed: Option<u64>, // Breaks ties between equally good moves reproducibly; the session's seed, or random, if absent
+    apply: Option<bool>, // Play the move (the default); false only reports it
+    #[serde(default)]
+    pv: bool, // Include the line of play the AI expects in the response
+}
+
+// Query parameters accepted by POST /autoplay.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+struct AutoplayQuery {
+    depth: Option<u8>,      // Search depth for both sides
+    max_plies: Option<u32>, // Stop after this many half-moves even if the game goes on
+    seed: Option<u64>, // Makes the whole game reproducible; the session's seed, or random, if absent
+}
+
+// Deepest search --bench-ai times.
+const BENCH_MAX_DEPTH: u8 = 4;
+
+// Search depth and half-move cap POST /autoplay uses when the client doesn't pick them.
+const DEFAULT_AUTOPLAY_DEPTH: u8 = 2;
+const DEFAULT_AUTOPLAY_PLIES: u32 = 200;
+// Most half-moves a single POST /autoplay may play.
+const MAX_AUTOPLAY_PLIES: u32 = 1000;
+
+// Query parameters accepted by POST /analyze.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+struct AnalyzeQuery {
+    depth: Option<u8>,  // Search depth for every move
+    top: Option<usize>, // Return only this many of the best moves; all of them if absent
+}
+
+// Search depth POST /analyze uses when the client doesn't pick one.
+const DEFAULT_ANALYZE_DEPTH: u8 = 3;
+
+// One entry of the list returned by POST /analyze.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+struct ScoredMove {
+    from: Position,
+    to: Position,
+    score: i32,
+}
+
+// Query parameters accepted by POST /evaluate.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+struct EvaluateQuery {
+    depth: Option<u8>, // Also search this many plies ahead for the best move
+}
+
+// Returned by POST /evaluate: the score for the player to move and, after a
+// search, the move it found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+struct EvaluateResponse {
+    score: i32,
+    best_move: Option<MoveRequest>,
+}
+
+// Optional JSON body accepted by the AI move and hint endpoints.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+struct AiMoveRequest {
+    weights: Option<ai::EvalWeights>, // Replaces the difficulty's; missing ones keep their defaults
+}
+
+// Returned by the AI move and hint endpoints: the chosen move and how good the AI thinks it is.
+#[derive(Debug, Clone, Serialize)]
+struct AiMoveResponse {
+    from: Position,
+    to: Position,
+    score: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stats: Option<ai::SearchStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pv: Option<Vec<MoveRequest>>, // Expected line, starting with this move
+}
+
+// Query parameters accepted by POST /games.
+#[derive(Debug, Default, Deserialize)]
+struct CreateGameQuery {
+    code: Option<String>,
+}
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    info!("Listening on http://0.0.0.0:3000");
-    axum::serve(listener, app).await.unwrap();
+// Optional JSON body accepted by POST /games.
+#[derive(Debug, Default, Deserialize)]
+struct CreateGameRequest {
+    size: Option<usize>,
+    #[serde(default)]
+    config: GameConfig, // Rule variants, e.g. `{ "stalemate_rule": "Draw" }`
+}
+
+// Returned by POST /validate-config.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct ConfigValidation {
+    valid: bool,
+    errors: Vec<String>, // Every problem found; empty when `valid`
+}
+
+// JSON body accepted by POST /setup: an arbitrary position to play from.
+#[derive(Debug, Deserialize)]
+struct SetupRequest {
+    board: Board,
+    current_player: Player,
+    #[serde(default)]
+    allow_won: bool, // Accept a position that is already decided
+}
+
+// JSON body accepted by POST /canonical: the position to identify.
+#[derive(Debug, Deserialize)]
+struct CanonicalRequest {
+    board: Board,
+    current_player: Player,
+}
+
+// Returned by POST /canonical.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct CanonicalResponse {
+    key: String, // `Game::canonical_key` in hex, since JavaScript numbers can't hold every u64
+}
+
+// Returned by POST /autoplay: the game once it ended or hit the cap, and how many
+// half-moves were played to get there.
+#[derive(Debug, Serialize)]
+struct AutoplayResponse {
+    game: Game,
+    plies: u32,
+}
+
+// One entry of the move list returned by GET /moves.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct NotatedMove {
+    ply: u32, // 1 for the first move of the game
+    player: Player,
+    notation: String, // e.g. "d1-c1"
+}
+
+// JSON body accepted by POST /moves.
+#[derive(Debug, Clone, Deserialize)]
+struct MoveBatchRequest {
+    moves: Vec<MoveRequest>, // Played in order
+}
+
+// Returned by POST /moves. When a move couldn't be played, `error` says why, and it
+// is the move at index `applied`; nothing after it was tried.
+#[derive(Debug, Serialize)]
+struct MoveBatchResponse {
+    applied: usize,    // How many moves were played
+    final_board: Game, // The game once they were
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ErrorResponse>,
+}
+
+// JSON body accepted by POST /surrender.
+#[derive(Debug, Clone, Copy, Deserialize)]
+struct SurrenderRequest {
+    player: Player, // The side giving up
+}
+
+// JSON body accepted by POST /seed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct SeedRequest {
+    seed: u64,
+}
+
+// Query parameters accepted by POST /undo.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+struct UndoQuery {
+    count: Option<usize>, // Half-moves to take back, 1 if absent; stops early at the start of the game
+}
+
+// Returned by POST /undo: the game after taking moves back, and how many were.
+#[derive(Debug, Serialize)]
+struct UndoResponse {
+    game: Game,
+    undone: usize,
+}
+
+// Returned by GET /config: where the goals and the starting pieces are, so a
+// frontend can draw the board without knowing the default layout, and what each
+// AI difficulty searches with. Players 3 and 4 only appear in games they play in.
+#[derive(Debug, Serialize)]
+struct ConfigResponse {
+    board_size: usize,
+    players: u8,
+    goal_p1: Position, // Player 1's base, which the other players try to reach
+    goal_p2: Position, // Player 2's base, which the other players try to reach
+    #[serde(skip_serializing_if = "Option::is_none")]
+    goal_p3: Option<Position>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    goal_p4: Option<Position>,
+    p1_start: Vec<Position>,
+    p2_start: Vec<Position>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    p3_start: Option<Vec<Position>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    p4_start: Option<Vec<Position>>,
+    difficulties: HashMap<ai::Difficulty, ai::DifficultySettings>,
+}
+
+impl ConfigResponse {
+    fn new(size: usize, config: &GameConfig) -> Self {
+        let in_game = |player: Player| config.players().any(|p| p == player);
+        let goal = |player| in_game(player).then(|| config.goal(player, size));
+        let start = |player| in_game(player).then(|| config.start_squares(player, size));
+        ConfigResponse {
+            board_size: size,
+            players: config.players,
+            goal_p1: config.goal(Player::P1, size),
+            goal_p2: config.goal(Player::P2, size),
+            goal_p3: goal(Player::P3),
+            goal_p4: goal(Player::P4),
+            p1_start: config.start_squares(Player::P1, size),
+            p2_start: config.start_squares(Player::P2, size),
+            p3_start: start(Player::P3),
+            p4_start: start(Player::P4),
+            difficulties: ai::Difficulty::ALL
+                .into_iter()
+                .map(|difficulty| (difficulty, difficulty.settings()))
+                .collect(),
+        }
+    }
+}
+
+// Returned by GET /healthz and GET /readyz.
+#[derive(Debug, Serialize)]
+struct HealthResponse {
+    status: &'static str,
+}
+
+// Returned by GET /version: which build is running. Filled in at compile time;
+// see build.rs.
+#[derive(Debug, Serialize, Deserialize)]
+struct VersionResponse {
+    version: String,    // Crate version from Cargo.toml
+    git_sha: String,    // Commit the binary was built from, or "unknown"
+    build_time: String, // When it was built, in RFC 3339 form (UTC)
+}
+
+// Returned by GET /status-only: just enough for a client polling a game to tell
+// whether it has moved on or ended, without the board.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct StatusOnlyResponse {
+    status: GameStatus, // Includes the winner and why once the game is won
+    current_player: Player,
+    ply: u32,
+}
+
+// Returned by GET /code: the position in `Game::to_code` form.
+#[derive(Debug, Serialize, Deserialize)]
+struct CodeResponse {
+    code: String,
+}
+
+// Query parameters accepted by GET /board/{id}/ascii.
+#[derive(Debug, Deserialize)]
+struct AsciiQuery {
+    #[serde(default = "default_emoji")]
+    emoji: bool, // Draw pieces as 🔴/🔵 rather than 1/2
+}
+
+fn default_emoji() -> bool {
+    true
+}
+
+// Query parameters accepted by GET /games.
+#[derive(Debug, Default, Deserialize)]
+struct ListGamesQuery {
+    limit: Option<usize>, // Return at most this many sessions
+    #[serde(default)]
+    offset: usize, // Skip this many sessions first
+}
+
+// One entry of GET /games: a session without its board.
+#[derive(Debug, Serialize)]
+struct GameSummary {
+    id: Uuid,
+    current_player: Player,
+    status: GameStatus,
+    ply: u32,
+}
+
+// Returned by GET /piece: everything about one square.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct PieceResponse {
+    occupant: Option<Player>,
+    neighbors: u8,              // Also how far a piece standing there moves
+    valid_moves: Vec<Position>, // Where the piece on the square can go; empty for an empty square
+    is_goal_for: Vec<Player>,   // Players who win by reaching this square
+}
+
+// Query parameters accepted by GET /board/{id}/since.
+#[derive(Debug, Clone, Copy, Deserialize)]
+struct SinceQuery {
+    ply: u32, // The ply the client's copy of the board is from
+}
+
+// One square of a board diff.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+struct SquareChange {
+    row: usize,
+    col: usize,
+    occupant: Option<Player>,
+}
+
+// Returned by GET /board/{id}/since: what to change to bring an old copy of the
+// board up to date.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct BoardDiff {
+    ply: u32, // The ply the board is at now
+    changes: Vec<SquareChange>,
+}
+
+// Query parameters accepted by GET /heatmap.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+struct HeatmapQuery {
+    #[serde(default)]
+    distance: ai::DistanceMetric, // How distance is measured, as for POST /ai-move
+}
+
+// One piece in the response of GET /heatmap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+struct PieceDistance {
+    row: usize,
+    col: usize,
+    owner: Player,
+    distance: i32, // How far the piece is from the goal it is racing to
+}
+
+// Query parameters accepted by GET /threats.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+struct ThreatsQuery {
+    player: Option<Player>, // Whose reach to show; whoever plays next if absent
+}
+
+// Query parameters accepted by GET /can-move.
+#[derive(Debug, Clone, Copy, Deserialize)]
+struct CanMoveQuery {
+    player: Player,
+}
+
+// Returned by GET /can-move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct CanMoveResponse {
+    can_move: bool,
+}
+
+// Query parameters accepted by GET /legal: the move to check, as from/to row and column.
+#[derive(Debug, Clone, Copy, Deserialize)]
+struct LegalQuery {
+    fr: usize,
+    fc: usize,
+    tr: usize,
+    tc: usize,
+}
+
+// Returned by GET /legal.
+#[derive(Debug, Serialize)]
+struct LegalResponse {
+    legal: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>, // Why the move would be rejected
+}
+
+// Query parameters accepted by GET /replay.
+#[derive(Debug, Default, Deserialize)]
+struct ReplayQuery {
+    step: Option<usize>, // Return only the position after this many half-moves
+}
+
+// Returned by POST /import: the new session and the position the moves led to.
+#[derive(Debug, Serialize)]
+struct ImportResponse {
+    id: Uuid,
+    game: Game,
+}
+
+// Returned by POST /games so the client knows which session to address.
+#[derive(Debug, Serialize, Deserialize)]
+struct CreateGameResponse {
+    id: Uuid,
+}
+
+// JSON body returned when a move is rejected, so clients can branch on `code`
+// instead of parsing the message.
+#[derive(Debug, Serialize, Deserialize)]
+struct ErrorResponse {
+    error: String,
+    code: String,
+}
+
+impl ErrorResponse {
+    fn new(error: &str, code: &str) -> Self {
+        ErrorResponse {
+            error: error.to_string(),
+            code: code.to_string(),
+        }
+    }
+
+    // Maps an error message from `Game::make_move` to its machine-readable code.
+    fn from_move_error(error: &'static str) -> Self {
+        let code = match error {
+            "Position is off the board." => "off_board",
+            "Invalid starting square or that's not your piece." => "wrong_piece",
+            "Destination square is occupied." => "occupied_destination",
+            "Path is blocked." => "blocked_path",
+            "Out of time." => "out_of_time",
+            "Source and destination must differ." => "same_square",
+            _ => "illegal_move",
+        };
+        ErrorResponse::new(error, code)
+    }
+}
+
+// What a move body should look like, returned when it can't be parsed.
+const MOVE_BODY_HINT: &str = "expected {from:{row,col},to:{row,col}} or {notation:\"d4-c3\"}";
+
+// The body of POST /move. Parsed like `Json<MoveRequest>`, but a malformed body
+// gets the same JSON error shape as an illegal move instead of axum's plain-text 422.
+struct MoveBody(MoveRequest);
+
+impl<S: Send + Sync> FromRequest<S> for MoveBody {
+    type Rejection = (StatusCode, Json<ErrorResponse>);
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        match Json::<MoveRequest>::from_request(req, state).await {
+            Ok(Json(payload)) => Ok(MoveBody(payload)),
+            Err(rejection) => {
+                error!("Move failed: {}", rejection.body_text());
+                Err((
+                    StatusCode::BAD_REQUEST,
+                    Json(ErrorResponse::new(MOVE_BODY_HINT, "invalid_body")),
+                ))
+            }
+        }
+    }
+}
+
+// Error returned by every session-scoped handler when the id is unknown.
+fn game_not_found(game_id: Uuid) -> (StatusCode, String) {
+    error!("Game {} not found.", game_id);
+    (StatusCode::NOT_FOUND, "Game not found.".to_string())
+}
+
+async fn index() -> impl axum::response::IntoResponse {
+    info!("GET / requested.");
+    "Create a game with POST /games, then visit /board/{game_id} to see its state."
+}
+
+// Handles GET /openapi.json request. Describes the main endpoints for client generators.
+async fn get_openapi() -> Json<serde_json::Value> {
+    Json(openapi::document())
+}
+
+// Handles GET /stats request. Reports how many games each player has won.
+async fn get_stats(State(state): State<AppState>) -> Json<Scoreboard> {
+    info!("GET /stats requested.");
+    let scoreboard = *state
+        .scoreboard
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    Json(scoreboard)
+}
+
+// Handles GET /healthz request. Liveness probe: answers as long as the server runs,
+// without touching the game state, so a long-running request can't hold it up.
+async fn healthz() -> Json<HealthResponse> {
+    Json(HealthResponse { status: "ok" })
+}
+
+// Handles GET /version request. Tells operators which build is live.
+async fn get_version() -> Json<VersionResponse> {
+    Json(VersionResponse {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        git_sha: env!("TOP_CAP_GIT_SHA").to_string(),
+        build_time: env!("TOP_CAP_BUILD_TIME").to_string(),
+    })
+}
+
+// Handles GET /readyz request. Readiness probe: also checks that the game state is
+// usable and, when --db is given, that the database answers.
+async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<HealthResponse>) {
+    let unavailable = (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(HealthResponse {
+            status: "unavailable",
+        }),
+    );
+    if state.games.is_poisoned() {
+        error!("Not ready: Game state lock is poisoned.");
+        return unavailable;
+    }
+    if let Some(store) = &state.store {
+        if let Err(e) = store.ping().await {
+            error!("Not ready: {}", e);
+            return unavailable;
+        }
+    }
+    (StatusCode::OK, Json(HealthResponse { status: "ok" }))
+}
+
+// Handles GET /games request. Lists the active sessions, sorted by id so that
+// `offset` and `limit` page through them consistently.
+async fn list_games(
+    State(state): State<AppState>,
+    Query(query): Query<ListGamesQuery>,
+) -> Json<Vec<GameSummary>> {
+    info!("GET /games requested.");
+    let games = state.games();
+    let mut summaries: Vec<GameSummary> = games
+        .iter()
+        .map(|(&id, game)| GameSummary {
+            id,
+            current_player: game.current_player,
+            status: game.status,
+            ply: game.ply,
+        })
+        .collect();
+    summaries.sort_by_key(|summary| summary.id);
+
+    let page = summaries
+        .into_iter()
+        .skip(query.offset)
+        .take(query.limit.unwrap_or(usize::MAX))
+        .collect();
+    Json(page)
+}
+
+// Handles POST /games request. Starts a new game session and returns its id.
+// The body may pick a board `size` and a `config` (rules, starting squares, goals);
+// a `code` from GET /code starts the session from that position instead.
+async fn create_game(
+    State(state): State<AppState>,
+    Query(query): Query<CreateGameQuery>,
+    body: Option<Json<CreateGameRequest>>,
+) -> Result<Json<CreateGameResponse>, (StatusCode, String)> {
+    info!("POST /games requested.");
+    let Json(request) = body.unwrap_or_default();
+    let game = match (query.code, request.size) {
+        (Some(_), Some(_)) => Err("A code already determines the board size.".to_string()),
+        (Some(code), None) => Game::from_code_with_config(&code, request.config),
+        (None, size) => Game::new_with_config(size.unwrap_or(DEFAULT_BOARD_SIZE), request.config),
+    }
+    .map_err(|e| {
+        error!("Game creation failed: {}", e);
+        (StatusCode::BAD_REQUEST, e)
+    })?;
+
+    let id = Uuid::new_v4();
+    state.open_session(id, game.clone()).await;
+    state.publish(id, &game).await;
+    info!("Game {} created.", id);
+    Ok(Json(CreateGameResponse { id }))
+}
+
+// Handles POST /validate-config request. Checks a game setup without creating a
+// game. Takes the same body as POST /games, so a tool can check exactly what it is
+// about to send, and lists every problem rather than only the first.
+async fn validate_config(Json(request): Json<CreateGameRequest>) -> Json<ConfigValidation> {
+    info!("POST /validate-config requested.");
+    let size = request.size.unwrap_or(DEFAULT_BOARD_SIZE);
+    // Whether squares are on the board means nothing for an impossible board
+    let errors = match game::check_board_size(size) {
+        Err(e) => vec![e],
+        Ok(()) => request.config.errors(size),
+    };
+    Json(ConfigValidation {
+        valid: errors.is_empty(),
+        errors,
+    })
+}
+
+// Handles GET /code request. Returns the position as a compact, URL-friendly string.
+async fn get_code(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+) -> Result<Json<CodeResponse>, (StatusCode, String)> {
+    info!("GET /code/{} requested.", game_id);
+    let games = state.games();
+    let Some(game) = games.get(&game_id) else {
+        return Err(game_not_found(game_id));
+    };
+    Ok(Json(CodeResponse {
+        code: game.to_code(),
+    }))
+}
+
+// Handles GET /export request. Returns the starting position and every move played.
+async fn export_game(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+) -> Result<Json<Transcript>, (StatusCode, String)> {
+    info!("GET /export/{} requested.", game_id);
+    let games = state.games();
+    let Some(game) = games.get(&game_id) else {
+        return Err(game_not_found(game_id));
+    };
+    Ok(Json(game.transcript()))
+}
+
+// Handles GET /replay request. Returns every position of the game, one per ply,
+// starting with the initial one; `step=N` returns only the position after N plies.
+async fn replay_game(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+    Query(query): Query<ReplayQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    info!("GET /replay/{} requested.", game_id);
+    let mut snapshots = {
+        let games = state.games();
+        let Some(game) = games.get(&game_id) else {
+            return Err(game_not_found(game_id));
+        };
+        game.replay()
+    };
+
+    let Some(step) = query.step else {
+        return Ok(Json(snapshots).into_response());
+    };
+    if step >= snapshots.len() {
+        error!("Replay failed: Step {} is out of range.", step);
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Step must be between 0 and {}.", snapshots.len() - 1),
+        ));
+    }
+    Ok(Json(snapshots.swap_remove(step)).into_response())
+}
+
+// Handles POST /import request. Replays a transcript from GET /export into a new session.
+// If a move is illegal, nothing is created and the error names its index.
+async fn import_game(
+    State(state): State<AppState>,
+    Json(transcript): Json<Transcript>,
+) -> Result<Json<ImportResponse>, (StatusCode, Json<TranscriptError>)> {
+    info!(
+        "POST /import requested: {} move(s).",
+        transcript.moves.len()
+    );
+    let game = Game::from_transcript(&transcript).map_err(|e| {
+        error!("Import failed: {} (move {:?})", e.error, e.move_index);
+        (StatusCode::BAD_REQUEST, Json(e))
+    })?;
+
+    let id = Uuid::new_v4();
+    state.open_session(id, game.clone()).await;
+    state.publish(id, &game).await;
+    info!("Game {} imported.", id);
+    Ok(Json(ImportResponse { id, game }))
+}
+
+// Handles GET /board request. Returns the current game state as JSON.
+async fn get_board(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+) -> Result<Json<Game>, (StatusCode, String)> {
+    info!("GET /board/{} requested.", game_id);
+    let games = state.games();
+    let Some(game) = games.get(&game_id) else {
+        return Err(game_not_found(game_id));
+    };
+    // Show the clock as it stands now, not as of the last move
+    let mut game = game.clone();
+    game.run_clock(game::now_ms());
+    Ok(Json(game))
+}
+
+// Handles GET /board/{id}/since request. Lists only the squares that changed
+// since the given ply, for clients that can't afford to fetch the whole board.
+async fn get_board_since(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+    Query(query): Query<SinceQuery>,
+) -> Result<Json<BoardDiff>, (StatusCode, String)> {
+    info!("GET /board/{}/since?ply={} requested.", game_id, query.ply);
+    let games = state.games();
+    let Some(game) = games.get(&game_id) else {
+        return Err(game_not_found(game_id));
+    };
+    let Some(changes) = game.changes_since(query.ply) else {
+        return Err((
+            StatusCode::CONFLICT,
+            format!(
+                "Ply {} hasn't been played yet; the game is at ply {}.",
+                query.ply, game.ply
+            ),
+        ));
+    };
+    let changes = changes
+        .into_iter()
+        .map(|(pos, occupant)| SquareChange {
+            row: pos.row,
+            col: pos.col,
+            occupant,
+        })
+        .collect();
+    Ok(Json(BoardDiff {
+        ply: game.ply,
+        changes,
+    }))
+}
+
+// Handles GET /moves request. Lists the moves played so far in notation, oldest
+// first. Moves that were taken back aren't listed.
+async fn get_move_list(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+) -> Result<Json<Vec<NotatedMove>>, (StatusCode, String)> {
+    info!("GET /moves/{} requested.", game_id);
+    let games = state.games();
+    let Some(game) = games.get(&game_id) else {
+        return Err(game_not_found(game_id));
+    };
+    let moves = game
+        .history
+        .iter()
+        .zip(1..)
+        .map(|(record, ply)| NotatedMove {
+            ply,
+            player: record.player,
+            notation: MoveRequest {
+                from: record.from,
+                to: record.to,
+            }
+            .to_notation(),
+        })
+        .collect();
+    Ok(Json(moves))
+}
+
+// Handles GET /board/{id}/ascii request. Returns the board as plain text for
+// terminals and logs.
+async fn get_board_ascii(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+    Query(query): Query<AsciiQuery>,
+) -> Result<String, (StatusCode, String)> {
+    info!("GET /board/{}/ascii requested.", game_id);
+    let games = state.games();
+    let Some(game) = games.get(&game_id) else {
+        return Err(game_not_found(game_id));
+    };
+    Ok(game.to_ascii(query.emoji))
+}
+
+// Handles GET /config request. Describes the layout a new default game gets.
+async fn get_default_config() -> Json<ConfigResponse> {
+    info!("GET /config requested.");
+    Json(ConfigResponse::new(
+        DEFAULT_BOARD_SIZE,
+        &GameConfig::default(),
+    ))
+}
+
+// Handles GET /config/{id} request. Describes the layout of an existing game.
+async fn get_config(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+) -> Result<Json<ConfigResponse>, (StatusCode, String)> {
+    info!("GET /config/{} requested.", game_id);
+    let games = state.games();
+    let Some(game) = games.get(&game_id) else {
+        return Err(game_not_found(game_id));
+    };
+    Ok(Json(ConfigResponse::new(game.size, &game.config)))
+}
+
+// Handles GET /events request. A Server-Sent Events stream for clients that can't
+// use WebSockets: sends the game as it stands, then the whole game again as a
+// `game` event every time it changes.
+async fn game_events(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, (StatusCode, String)> {
+    info!("GET /events/{} requested.", game_id);
+    // Subscribe before reading the game, so no change can slip in between
+    let receiver = state.events.0.subscribe();
+    let current = {
+        let games = state.games();
+        let Some(game) = games.get(&game_id) else {
+            return Err(game_not_found(game_id));
+        };
+        Arc::new(game.clone())
+    };
+
+    let updates = stream::unfold(receiver, move |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok((id, game)) if id == game_id => return Some((game, receiver)),
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    let stream = stream::once(async { current })
+        .chain(updates)
+        .map(|game| Event::default().event("game").json_data(&*game));
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+// Handles GET /status request. Reports whether the game is over, the winner and why.
+async fn get_status(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+) -> Result<Json<StatusResponse>, (StatusCode, String)> {
+    info!("GET /status/{} requested.", game_id);
+    let games = state.games();
+    let Some(game) = games.get(&game_id) else {
+        return Err(game_not_found(game_id));
+    };
+    Ok(Json(StatusResponse::from(game.status)))
+}
+
+// Handles GET /status-only request. A much smaller payload than GET /board, for
+// clients that poll until the game changes or ends.
+async fn get_status_only(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+) -> Result<Json<StatusOnlyResponse>, (StatusCode, String)> {
+    info!("GET /status-only/{} requested.", game_id);
+    let games = state.games();
+    let Some(game) = games.get(&game_id) else {
+        return Err(game_not_found(game_id));
+    };
+    Ok(Json(StatusOnlyResponse {
+        status: game.status,
+        current_player: game.current_player,
+        ply: game.ply,
+    }))
+}
+
+// Handles GET /valid-moves request. Returns the legal destinations for the piece
+// on the given square. Squares that are empty, off the board, or not owned by
+// the player to move simply have no moves.
+async fn get_valid_moves(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+    Query(query): Query<ValidMovesQuery>,
+) -> Result<Json<ValidMovesResponse>, (StatusCode, String)> {
+    info!(
+        "GET /valid-moves/{} requested: ({},{})",
+        game_id, query.row, query.col
+    );
+    let games = state.games();
+    let Some(game) = games.get(&game_id) else {
+        return Err(game_not_found(game_id));
+    };
+    let Some(pos) = Position::new(query.row, query.col, game.size)
+        .filter(|pos| game.board[pos.row][pos.col] == Some(game.current_player))
+    else {
+        return Ok(Json(ValidMovesResponse {
+            move_dist: 0,
+            moves: Vec::new(),
+        }));
+    };
+
+    Ok(Json(ValidMovesResponse {
+        move_dist: game.move_distance(pos),
+        moves: game.get_valid_moves_for_piece(pos),
+    }))
+}
+
+// Handles GET /neighbors request. Counts the pieces around any square, occupied or
+// not, i.e. how far a piece standing there would move.
+async fn get_neighbors(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+    Query(query): Query<Position>,
+) -> Result<Json<NeighborsResponse>, (StatusCode, String)> {
+    info!(
+        "GET /neighbors/{} requested: ({},{})",
+        game_id, query.row, query.col
+    );
+    let games = state.games();
+    let Some(game) = games.get(&game_id) else {
+        return Err(game_not_found(game_id));
+    };
+    let Some(pos) = Position::new(query.row, query.col, game.size) else {
+        error!(
+            "Neighbor count failed: ({},{}) is off the board.",
+            query.row, query.col
+        );
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Position is off the board.".to_string(),
+        ));
+    };
+    Ok(Json(NeighborsResponse {
+        count: game.count_neighbors(pos),
+    }))
+}
+
+// Handles GET /piece request. Describes a square, e.g. the one a player clicked:
+// who is on it, its neighbor count, where its piece can go and whose goal it is.
+async fn get_piece(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+    Query(query): Query<Position>,
+) -> Result<Json<PieceResponse>, (StatusCode, String)> {
+    info!(
+        "GET /piece/{} requested: ({},{})",
+        game_id, query.row, query.col
+    );
+    let games = state.games();
+    let Some(game) = games.get(&game_id) else {
+        return Err(game_not_found(game_id));
+    };
+    let Some(pos) = Position::new(query.row, query.col, game.size) else {
+        error!(
+            "Piece lookup failed: ({},{}) is off the board.",
+            query.row, query.col
+        );
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Position is off the board.".to_string(),
+        ));
+    };
+
+    let occupant = game.board[pos.row][pos.col];
+    Ok(Json(PieceResponse {
+        occupant,
+        neighbors: game.count_neighbors(pos),
+        valid_moves: match occupant {
+            Some(_) => game.get_valid_moves_for_piece(pos),
+            None => Vec::new(),
+        },
+        is_goal_for: game
+            .config
+            .players()
+            .filter(|&player| game.goal_owner(pos).is_some_and(|owner| owner != player))
+            .collect(),
+    }))
+}
+
+// Handles GET /all-moves request. Lists every legal move for the player to move.
+// The list is empty when that player is stuck or the game is over.
+async fn get_all_moves(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+) -> Result<Json<Vec<MoveRequest>>, (StatusCode, String)> {
+    info!("GET /all-moves/{} requested.", game_id);
+    let games = state.games();
+    let Some(game) = games.get(&game_id) else {
+        return Err(game_not_found(game_id));
+    };
+    if game.status != GameStatus::Ongoing {
+        return Ok(Json(Vec::new()));
+    }
+    Ok(Json(game.all_valid_moves(game.current_player)))
+}
+
+// Handles GET /movable request. Lists the pieces of the player to move that have a
+// legal move, so a UI can grey out the frozen ones. Empty once the game is over.
+async fn get_movable_pieces(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+) -> Result<Json<Vec<Position>>, (StatusCode, String)> {
+    info!("GET /movable/{} requested.", game_id);
+    let games = state.games();
+    let Some(game) = games.get(&game_id) else {
+        return Err(game_not_found(game_id));
+    };
+    if game.status != GameStatus::Ongoing {
+        return Ok(Json(Vec::new()));
+    }
+    Ok(Json(game.movable_pieces(game.current_player)))
+}
+
+// Handles GET /threats request. Lists the squares a player could move into on
+// their next turn, sorted by row then column, so a UI can shade them.
+async fn get_threats(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+    Query(query): Query<ThreatsQuery>,
+) -> Result<Json<Vec<Position>>, (StatusCode, String)> {
+    info!("GET /threats/{} requested.", game_id);
+    let games = state.games();
+    let Some(game) = games.get(&game_id) else {
+        return Err(game_not_found(game_id));
+    };
+    let player = query.player.unwrap_or_else(|| game.next_player());
+    let mut squares: Vec<Position> = game.reachable_squares(player).into_iter().collect();
+    squares.sort_by_key(|pos| (pos.row, pos.col));
+    Ok(Json(squares))
+}
+
+// Handles GET /heatmap request. Lists every piece, row by row, with its distance
+// to the goal it is racing to: the main term of the AI's evaluation.
+async fn get_heatmap(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+    Query(query): Query<HeatmapQuery>,
+) -> Result<Json<Vec<PieceDistance>>, (StatusCode, String)> {
+    info!("GET /heatmap/{} requested.", game_id);
+    let games = state.games();
+    let Some(game) = games.get(&game_id) else {
+        return Err(game_not_found(game_id));
+    };
+    let mut pieces = Vec::new();
+    for (row, squares) in game.board.iter().enumerate() {
+        for (col, square) in squares.iter().enumerate() {
+            if let Some(owner) = *square {
+                let pos = Position { row, col };
+                pieces.push(PieceDistance {
+                    row,
+                    col,
+                    owner,
+                    distance: ai::goal_distance(game, pos, owner, query.distance),
+                });
+            }
+        }
+    }
+    Ok(Json(pieces))
+}
+
+// Handles GET /can-move request. Says whether a player has any legal move, so a
+// frontend can warn them before being stuck loses them the game.
+async fn can_move(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+    Query(query): Query<CanMoveQuery>,
+) -> Result<Json<CanMoveResponse>, (StatusCode, String)> {
+    info!(
+        "GET /can-move/{}?player={:?} requested.",
+        game_id, query.player
+    );
+    let games = state.games();
+    let Some(game) = games.get(&game_id) else {
+        return Err(game_not_found(game_id));
+    };
+    if !game.has_player(query.player) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("{:?} doesn't play in this game.", query.player),
+        ));
+    }
+    Ok(Json(CanMoveResponse {
+        can_move: game.has_any_valid_moves(query.player),
+    }))
+}
+
+// Handles GET /legal request. Says whether POST /move would accept a move, and why
+// not, without playing it.
+async fn check_legal(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+    Query(query): Query<LegalQuery>,
+) -> Result<Json<LegalResponse>, (StatusCode, String)> {
+    let from = Position {
+        row: query.fr,
+        col: query.fc,
+    };
+    let to = Position {
+        row: query.tr,
+        col: query.tc,
+    };
+    info!(
+        "GET /legal/{} requested: ({},{}) -> ({},{})",
+        game_id, from.row, from.col, to.row, to.col
+    );
+    let games = state.games();
+    let Some(game) = games.get(&game_id) else {
+        return Err(game_not_found(game_id));
+    };
+
+    let verdict = if game.status != GameStatus::Ongoing {
+        Err("Game is already over.")
+    } else {
+        game.check_move(from, to)
+    };
+    Ok(Json(LegalResponse {
+        legal: verdict.is_ok(),
+        reason: verdict.err().map(str::to_string),
+    }))
+}
+
+// Handles POST /move request. Attempts to make a move.
+async fn make_move(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+    MoveBody(payload): MoveBody,
+) -> Result<(StatusCode, String), (StatusCode, Json<ErrorResponse>)> {
+    // Logged as coordinates: the move hasn't been checked to be on the board yet
+    info!(
+        "POST /move/{} requested: ({},{})-({},{})",
+        game_id, payload.from.row, payload.from.col, payload.to.row, payload.to.col
+    );
+    let (result, updated) = {
+        let mut games = state.games();
+        let Some(game) = games.get_mut(&game_id) else {
+            let (status, message) = game_not_found(game_id);
+            return Err((status, Json(ErrorResponse::new(&message, "game_not_found"))));
+        };
+
+        if let Some(refusal) = refuse_player_move(game) {
+            error!("Move failed: {}", refusal.error);
+            return Err((StatusCode::BAD_REQUEST, Json(refusal)));
+        }
+
+        let result = game.make_move(payload.from, payload.to);
+        // Running out of time ends the game even though the move isn't played
+        state.record_result(GameStatus::Ongoing, game.status);
+        (result, game.clone())
+    };
+    if let Err(e) = result {
+        error!("Move failed: {}", e);
+        if updated.status != GameStatus::Ongoing {
+            state.publish(game_id, &updated).await;
+        }
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::from_move_error(e)),
+        ));
+    }
+
+    info!("Move successful.");
+    state.publish(game_id, &updated).await;
+    Ok((StatusCode::OK, "Move accepted.".to_string()))
+}
+
+// Why a player can't send a move in `game` right now, if they can't: the game is
+// over, or the side to move is played by the AI.
+fn refuse_player_move(game: &Game) -> Option<ErrorResponse> {
+    if game.status != GameStatus::Ongoing {
+        return Some(ErrorResponse::new("Game is already over.", "game_over"));
+    }
+    match game.config.mode {
+        Some(GameMode::VsAi { ai_player }) if game.current_player == ai_player => Some(
+            ErrorResponse::new("The AI plays this side; use /ai-move.", "ai_turn"),
+        ),
+        _ => None,
+    }
+}
+
+// Handles POST /moves/{id} request. Plays a list of moves in order, each as
+// POST /move would. This is best-effort, not atomic: it stops at the first move
+// that can't be played, and the moves before it stay played.
+async fn make_moves(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+    Json(request): Json<MoveBatchRequest>,
+) -> Result<Json<MoveBatchResponse>, (StatusCode, Json<ErrorResponse>)> {
+    info!(
+        "POST /moves/{} requested: {} moves",
+        game_id,
+        request.moves.len()
+    );
+    let (applied, error, before, updated) = {
+        let mut games = state.games();
+        let Some(game) = games.get_mut(&game_id) else {
+            let (status, message) = game_not_found(game_id);
+            return Err((status, Json(ErrorResponse::new(&message, "game_not_found"))));
+        };
+
+        let before = game.status;
+        let mut applied = 0;
+        let mut error = None;
+        for payload in &request.moves {
+            let result = match refuse_player_move(game) {
+                Some(refusal) => Err(refusal),
+                None => game
+                    .make_move(payload.from, payload.to)
+                    .map_err(ErrorResponse::from_move_error),
+            };
+            if let Err(e) = result {
+                error!(
+                    "Move ({},{})-({},{}) failed: {}",
+                    payload.from.row, payload.from.col, payload.to.row, payload.to.col, e.error
+                );
+                error = Some(e);
+                break;
+            }
+            applied += 1;
+        }
+        state.record_result(before, game.status);
+        (applied, error, before, game.clone())
+    };
+
+    info!("{} of {} moves played.", applied, request.moves.len());
+    // Running out of time changes the game even if no move was played
+    if applied > 0 || updated.status != before {
+        state.publish(game_id, &updated).await;
+    }
+    Ok(Json(MoveBatchResponse {
+        applied,
+        final_board: updated,
+        error,
+    }))
+}
+
+// Runs the AI search for the player to move in a game, as configured by `query`.
+// Returns the snapshot that was searched together with the chosen move, so the
+// caller can check the game hasn't changed before acting on it. With `as_opponent`
+// the AI plays the way the session's `ai_strategy` says, rather than its best.
+async fn search_current_position(
+    state: &AppState,
+    game_id: Uuid,
+    query: AiMoveQuery,
+    weights: Option<ai::EvalWeights>,
+    as_opponent: bool,
+) -> Result<(Game, AiMoveResponse), (StatusCode, String)> {
+    let preset = query.difficulty.settings();
+    let weights = weights.unwrap_or(preset.weights);
+    if let Some(depth) = query.depth {
+        if !(1..=ai::MAX_SEARCH_DEPTH).contains(&depth) {
+            error!("AI search failed: Invalid depth {}.", depth);
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Depth must be between 1 and {}.", ai::MAX_SEARCH_DEPTH),
+            ));
+        }
+    }
+    let ms = query.ms.unwrap_or(preset.time_ms);
+    if !(1..=ai::MAX_THINK_TIME_MS).contains(&ms) {
+        error!("AI search failed: Invalid time budget {}ms.", ms);
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Time budget must be between 1 and {} ms.",
+                ai::MAX_THINK_TIME_MS
+            ),
+        ));
+    }
+
+    // Take a snapshot so other requests aren't blocked while the AI thinks
+    let snapshot = {
+        let games = state.games();
+        let Some(game) = games.get(&game_id) else {
+            return Err(game_not_found(game_id));
+        };
+        game.clone()
+    };
+
+    if snapshot.status != GameStatus::Ongoing {
+        error!("AI search failed: Game is already over.");
+        return Err((StatusCode::BAD_REQUEST, "Game is already over.".to_string()));
+    }
+    check_ai_players(&snapshot, "AI search")?;
+
+    // The AI plays whichever side is to move.
+    // Call the AI logic from the separate module
+    let search_game = snapshot.clone();
+    let seed = query
+        .seed
+        .or_else(|| state.move_seed(game_id, snapshot.ply))
+        .unwrap_or_else(rand::random);
+    let book = state.book.clone();
+    let strategy = if as_opponent {
+        snapshot.config.ai_strategy
+    } else {
+        Strategy::Minimax
+    };
+    let (best_move, stats, pv) = tokio::task::spawn_blocking(move || {
+        let limit = match query.depth {
+            Some(depth) => ai::SearchLimit::Depth(depth),
+            None => ai::SearchLimit::Time {
+                budget: Duration::from_millis(ms),
+                max_depth: preset.depth,
+                aspiration_window: ai::DEFAULT_ASPIRATION_WINDOW,
+            },
+        };
+        let player = search_game.current_player;
+        let book = book.as_deref();
+        let (best_move, stats, pv) = if strategy != Strategy::Minimax {
+            let (best_move, stats) = ai::find_move_by_strategy(
+                &search_game,
+                player,
+                strategy,
+                query.distance,
+                weights,
+                seed,
+            );
+            // A deliberately weak move isn't the start of any line the AI expects
+            let pv = best_move.map(|(from, to, _)| vec![MoveRequest { from, to }]);
+            (best_move, stats, pv.filter(|_| query.pv))
+        } else if query.pv {
+            // Working out the rest of the line costs extra searches, so only on request
+            let (best_move, stats, pv) = ai::find_best_move_with_pv(
+                &search_game,
+                player,
+                limit,
+                query.distance,
+                weights,
+                seed,
+                book,
+            );
+            (best_move, stats, Some(pv))
+        } else {
+            let (best_move, stats) = ai::find_best_move_seeded(
+                &search_game,
+                player,
+                limit,
+                query.distance,
+                weights,
+                seed,
+                book,
+            );
+            (best_move, stats, None)
+        };
+        (best_move, query.stats.then_some(stats), pv)
+    })
+    .await
+    .map_err(|e| {
+        error!("AI search failed: Search task panicked: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "AI search failed.".to_string(),
+        )
+    })?;
+
+    let Some((from, to, score)) = best_move else {
+        error!("AI search failed: No valid moves found.");
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "AI could not find a move.".to_string(),
+        ));
+    };
+    if let Some(stats) = stats {
+        info!(
+            "AI search finished: depth {}, {} nodes in {:?}.",
+            stats.depth, stats.nodes, stats.elapsed
+        );
+    }
+
+    let response = AiMoveResponse {
+        from,
+        to,
+        score,
+        stats,
+        pv,
+    };
+    Ok((snapshot, response))
+}
+
+// Handles POST /ai-move request. Triggers the AI to make its move.
+// By default the AI thinks for a fixed time budget, adjustable with `ms`;
+// passing `depth` instead searches exactly that many plies ahead.
+// With `stats=true` the response also reports how much work the search did, and an
+// optional `{ "weights": {...} }` body changes how the AI judges positions.
+async fn make_ai_move(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+    Query(query): Query<AiMoveQuery>,
+    body: Option<Json<AiMoveRequest>>,
+) -> Result<Json<AiMoveResponse>, (StatusCode, String)> {
+    info!("POST /ai-move/{} requested.", game_id);
+    // A dry run only reports the move, like a hint, so it doesn't play for anyone
+    let apply = query.apply.unwrap_or(true);
+    if apply {
+        let games = state.games();
+        let Some(game) = games.get(&game_id) else {
+            return Err(game_not_found(game_id));
+        };
+        if let Err(e) = ai_may_move(game) {
+            error!("AI move failed: {}", e);
+            return Err((StatusCode::BAD_REQUEST, e.to_string()));
+        }
+    }
+    let Json(request) = body.unwrap_or_default();
+    let (snapshot, response) =
+        search_current_position(&state, game_id, query, request.weights, true).await?;
+    if !apply {
+        info!(
+            "AI move computed but not played (score {}).",
+            response.score
+        );
+        return Ok(Json(response));
+    }
+
+    let (result, updated) = {
+        let mut games = state.games();
+        let Some(game) = games.get_mut(&game_id) else {
+            return Err(game_not_found(game_id));
+        };
+        if *game != snapshot {
+            error!("AI move failed: Game changed while the AI was thinking.");
+            return Err((
+                StatusCode::CONFLICT,
+                "Game changed while the AI was thinking.".to_string(),
+            ));
+        }
+
+        let result = game.make_move(response.from, response.to);
+        state.record_result(snapshot.status, game.status);
+        (result, game.clone())
+    };
+    match result {
+        Ok(()) => {}
+        Err(e @ "Out of time.") => {
+            error!("AI move failed: {}", e);
+            state.publish(game_id, &updated).await;
+            return Err((StatusCode::BAD_REQUEST, e.to_string()));
+        }
+        Err(e) => {
+            error!("AI move failed during execution: {}", e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "AI made an invalid move.".to_string(),
+            ));
+        }
+    }
+
+    info!("AI move successful (score {}).", response.score);
+    state.publish(game_id, &updated).await;
+    Ok(Json(response))
+}
+
+// The AI searches one side against the other, so it only plays two-player games.
+fn check_ai_players(game: &Game, action: &str) -> Result<(), (StatusCode, String)> {
+    if game.config.players != DEFAULT_PLAYERS {
+        error!(
+            "{} failed: The game has {} players.",
+            action, game.config.players
+        );
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "The AI only plays two-player games.".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+// Whether the session's mode lets the AI play the player to move.
+fn ai_may_move(game: &Game) -> Result<(), &'static str> {
+    match game.config.mode {
+        None => Ok(()),
+        Some(GameMode::Human) => Err("AI disabled for this game"),
+        Some(GameMode::VsAi { ai_player }) if ai_player != game.current_player => {
+            Err("It's not the AI's turn.")
+        }
+        Some(GameMode::VsAi { .. }) => Ok(()),
+    }
+}
+
+// Handles POST /autoplay request. Lets the AI play both sides from the current
+// position until the game ends or `max_plies` half-moves have been played.
+async fn autoplay(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+    Query(query): Query<AutoplayQuery>,
+) -> Result<Json<AutoplayResponse>, (StatusCode, String)> {
+    info!("POST /autoplay/{} requested.", game_id);
+    let depth = query.depth.unwrap_or(DEFAULT_AUTOPLAY_DEPTH);
+    if !(1..=ai::MAX_SEARCH_DEPTH).contains(&depth) {
+        error!("Autoplay failed: Invalid depth {}.", depth);
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Depth must be between 1 and {}.", ai::MAX_SEARCH_DEPTH),
+        ));
+    }
+    let max_plies = query.max_plies.unwrap_or(DEFAULT_AUTOPLAY_PLIES);
+    if !(1..=MAX_AUTOPLAY_PLIES).contains(&max_plies) {
+        error!("Autoplay failed: Invalid ply cap {}.", max_plies);
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("max_plies must be between 1 and {}.", MAX_AUTOPLAY_PLIES),
+        ));
+    }
+
+    let snapshot = {
+        let games = state.games();
+        let Some(game) = games.get(&game_id) else {
+            return Err(game_not_found(game_id));
+        };
+        game.clone()
+    };
+    if snapshot.status != GameStatus::Ongoing {
+        error!("Autoplay failed: Game is already over.");
+        return Err((StatusCode::BAD_REQUEST, "Game is already over.".to_string()));
+    }
+    check_ai_players(&snapshot, "Autoplay")?;
+    // Autoplay moves both sides, so it would play for a person in either mode
+    if snapshot.config.mode.is_some() {
+        error!("Autoplay failed: The game has human players.");
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Autoplay is only available in games without a mode.".to_string(),
+        ));
+    }
+
+    let seed = query
+        .seed
+        .or_else(|| state.move_seed(game_id, snapshot.ply))
+        .unwrap_or_else(rand::random);
+    let book = state.book.clone();
+    let mut played = snapshot.clone();
+    let (played, plies) = tokio::task::spawn_blocking(move || {
+        let mut plies = 0;
+        while played.status == GameStatus::Ongoing && plies < max_plies {
+            let (best_move, _) = ai::find_best_move_seeded(
+                &played,
+                played.current_player,
+                ai::SearchLimit::Depth(depth),
+                ai::DistanceMetric::default(),
+                ai::EvalWeights::default(),
+                seed.wrapping_add(plies as u64),
+                book.as_deref(),
+            );
+            // A side without moves has already lost, so there is always one here
+            let Some((from, to, _)) = best_move else {
+                break;
+            };
+            if played.make_move(from, to).is_err() {
+                break;
+            }
+            plies += 1;
+        }
+        (played, plies)
+    })
+    .await
+    .map_err(|e| {
+        error!("Autoplay failed: Search task panicked: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Autoplay failed.".to_string(),
+        )
+    })?;
+
+    {
+        let mut games = state.games();
+        let Some(game) = games.get_mut(&game_id) else {
+            return Err(game_not_found(game_id));
+        };
+        if *game != snapshot {
+            error!("Autoplay failed: Game changed while the AI was playing.");
+            return Err((
+                StatusCode::CONFLICT,
+                "Game changed while the AI was playing.".to_string(),
+            ));
+        }
+        *game = played.clone();
+        state.record_result(snapshot.status, played.status);
+    }
+
+    info!(
+        "Autoplay finished after {} plies: {:?}.",
+        plies, played.status
+    );
+    state.publish(game_id, &played).await;
+    Ok(Json(AutoplayResponse {
+        game: played,
+        plies,
+    }))
+}
+
+// Handles POST /hint request. Suggests a move for the player to move without playing it.
+// Accepts the same parameters and body as POST /ai-move.
+async fn get_hint(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+    Query(query): Query<AiMoveQuery>,
+    body: Option<Json<AiMoveRequest>>,
+) -> Result<Json<AiMoveResponse>, (StatusCode, String)> {
+    info!("POST /hint/{} requested.", game_id);
+    let Json(request) = body.unwrap_or_default();
+    let (_, response) =
+        search_current_position(&state, game_id, query, request.weights, false).await?;
+    info!("Hint suggested (score {}).", response.score);
+    Ok(Json(response))
+}
+
+// Handles POST /analyze request. Scores every move the player to move could make,
+// as the AI sees them, and returns the `top` best, best first.
+async fn analyze(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+    Query(query): Query<AnalyzeQuery>,
+) -> Result<Json<Vec<ScoredMove>>, (StatusCode, String)> {
+    info!("POST /analyze/{} requested.", game_id);
+    let depth = query.depth.unwrap_or(DEFAULT_ANALYZE_DEPTH);
+    if !(1..=ai::MAX_SEARCH_DEPTH).contains(&depth) {
+        error!("Analysis failed: Invalid depth {}.", depth);
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Depth must be between 1 and {}.", ai::MAX_SEARCH_DEPTH),
+        ));
+    }
+
+    let snapshot = {
+        let games = state.games();
+        let Some(game) = games.get(&game_id) else {
+            return Err(game_not_found(game_id));
+        };
+        game.clone()
+    };
+    if snapshot.status != GameStatus::Ongoing {
+        error!("Analysis failed: Game is already over.");
+        return Err((StatusCode::BAD_REQUEST, "Game is already over.".to_string()));
+    }
+    check_ai_players(&snapshot, "Analysis")?;
+
+    let scored = tokio::task::spawn_blocking(move || {
+        ai::score_root_moves(
+            &snapshot,
+            snapshot.current_player,
+            depth,
+            ai::DistanceMetric::default(),
+            ai::EvalWeights::default(),
+        )
+    })
+    .await
+    .map_err(|e| {
+        error!("Analysis failed: Search task panicked: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Analysis failed.".to_string(),
+        )
+    })?;
+
+    let top = query.top.unwrap_or(scored.len());
+    Ok(Json(
+        scored
+            .into_iter()
+            .take(top)
+            .map(|(from, to, score)| ScoredMove { from, to, score })
+            .collect(),
+    ))
+}
+
+// Handles POST /canonical request. Identifies a position up to its mirror image
+// across the main diagonal, so puzzle tools can spot the same position twice.
+// No session is created or touched.
+async fn canonical_key(
+    Json(request): Json<CanonicalRequest>,
+) -> Result<Json<CanonicalResponse>, (StatusCode, String)> {
+    info!("POST /canonical requested.");
+    let game = Game::from_setup(request.board, request.current_player).map_err(|e| {
+        error!("Canonical key failed: {}", e);
+        (StatusCode::BAD_REQUEST, e)
+    })?;
+    Ok(Json(CanonicalResponse {
+        key: format!("{:016x}", game.canonical_key()),
+    }))
+}
+
+// Handles POST /evaluate request. Scores a position posted as a full game, the same
+// JSON GET /board returns, from the point of view of the player to move. Without
+// `depth` this is the AI's static evaluation; with it, the score of a search that
+// deep, along with the best move. No session is created or touched.
+async fn evaluate_position(
+    Query(query): Query<EvaluateQuery>,
+    Json(game): Json<Game>,
+) -> Result<Json<EvaluateResponse>, (StatusCode, String)> {
+    info!("POST /evaluate requested.");
+    check_ai_players(&game, "Evaluation")?;
+    let player = game.current_player;
+    let Some(depth) = query.depth else {
+        let score = ai::evaluate(
+            &game,
+            player,
+            ai::DistanceMetric::default(),
+            ai::EvalWeights::default(),
+        );
+        return Ok(Json(EvaluateResponse {
+            score,
+            best_move: None,
+        }));
+    };
+
+    if !(1..=ai::MAX_SEARCH_DEPTH).contains(&depth) {
+        error!("Evaluation failed: Invalid depth {}.", depth);
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Depth must be between 1 and {}.", ai::MAX_SEARCH_DEPTH),
+        ));
+    }
+    if game.status != GameStatus::Ongoing {
+        error!("Evaluation failed: Game is already over.");
+        return Err((StatusCode::BAD_REQUEST, "Game is already over.".to_string()));
+    }
+
+    let best_move = tokio::task::spawn_blocking(move || {
+        ai::find_best_move(
+            &game,
+            player,
+            depth,
+            ai::DistanceMetric::default(),
+            ai::EvalWeights::default(),
+        )
+    })
+    .await
+    .map_err(|e| {
+        error!("Evaluation failed: Search task panicked: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Evaluation failed.".to_string(),
+        )
+    })?;
+    let Some((from, to, score)) = best_move else {
+        error!("Evaluation failed: No valid moves found.");
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "The player to move has no legal moves.".to_string(),
+        ));
+    };
+    Ok(Json(EvaluateResponse {
+        score,
+        best_move: Some(MoveRequest { from, to }),
+    }))
+}
+
+// Handles POST /undo request. Takes back the last `count` moves played in the game,
+// or as many as there are, and returns the game as it then stands.
+async fn undo_move(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+    Query(query): Query<UndoQuery>,
+) -> Result<Json<UndoResponse>, (StatusCode, String)> {
+    info!("POST /undo/{} requested.", game_id);
+    let count = query.count.unwrap_or(1);
+    if count == 0 {
+        error!("Undo failed: Invalid count 0.");
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "count must be at least 1.".to_string(),
+        ));
+    }
+
+    let (updated, undone) = {
+        let mut games = state.games();
+        let Some(game) = games.get_mut(&game_id) else {
+            return Err(game_not_found(game_id));
+        };
+
+        let before = game.status;
+        if let Err(e) = game.undo_move() {
+            error!("Undo failed: {}", e);
+            return Err((StatusCode::BAD_REQUEST, e.to_string()));
+        }
+        let mut undone = 1;
+        while undone < count && game.undo_move().is_ok() {
+            undone += 1;
+        }
+        state.record_result(before, game.status);
+        (game.clone(), undone)
+    };
+
+    info!("Undid {} move(s).", undone);
+    state.publish(game_id, &updated).await;
+    Ok(Json(UndoResponse {
+        game: updated,
+        undone,
+    }))
+}
+
+// Handles POST /surrender request. Ends the game with the given player conceding,
+// and returns the finished game.
+async fn surrender(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+    Json(request): Json<SurrenderRequest>,
+) -> Result<Json<Game>, (StatusCode, String)> {
+    info!(
+        "POST /surrender/{} requested by {:?}.",
+        game_id, request.player
+    );
+    let updated = {
+        let mut games = state.games();
+        let Some(game) = games.get_mut(&game_id) else {
+            return Err(game_not_found(game_id));
+        };
+        if let Err(e) = game.resign(request.player) {
+            error!("Surrender failed: {}", e);
+            return Err((StatusCode::BAD_REQUEST, e.to_string()));
+        }
+        state.record_result(GameStatus::Ongoing, game.status);
+        game.clone()
+    };
+
+    info!("{:?} resigned.", request.player);
+    state.publish(game_id, &updated).await;
+    Ok(Json(updated))
+}
+
+// Handles POST /seed request. Fixes the seed the AI breaks ties with in this
+// session, so its games can be replayed exactly.
+async fn set_seed(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+    Json(request): Json<SeedRequest>,
+) -> Result<Json<SeedRequest>, (StatusCode, String)> {
+    info!("POST /seed/{} requested.", game_id);
+    if !state.games().contains_key(&game_id) {
+        return Err(game_not_found(game_id));
+    }
+    state
+        .seeds
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .insert(game_id, request.seed);
+    info!("AI seed of game {} set to {}.", game_id, request.seed);
+    Ok(Json(request))
+}
+
+// Handles POST /reset request. Resets the game to its initial state, keeping its
+// board size and rules.
+async fn reset_game(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+) -> (StatusCode, String) {
+    info!("POST /reset/{} requested.", game_id);
+    let updated = {
+        let mut games = state.games();
+        let Some(game) = games.get_mut(&game_id) else {
+            return game_not_found(game_id);
+        };
+        *game = game.restart();
+        game.clone()
+    };
+    info!("Game reset successfully.");
+    state.publish(game_id, &updated).await;
+    (StatusCode::OK, "Game reset.".to_string())
+}
+
+// Handles POST /setup request. Replaces the game with a custom position, e.g. a puzzle,
+// played under the session's config. Positions that are already won under the
+// session's goals are rejected unless `allow_won` is set.
+async fn setup_game(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+    Json(payload): Json<SetupRequest>,
+) -> (StatusCode, String) {
+    info!("POST /setup/{} requested.", game_id);
+    let game = {
+        let mut games = state.games();
+        let Some(current) = games.get_mut(&game_id) else {
+            return game_not_found(game_id);
+        };
+        let game = match Game::from_setup_with_config(
+            payload.board,
+            payload.current_player,
+            current.config.clone(),
+        ) {
+            Ok(game) => game,
+            Err(e) => {
+                error!("Setup failed: {}", e);
+                return (StatusCode::BAD_REQUEST, e);
+            }
+        };
+        if matches!(game.status, GameStatus::Won(..)) && !payload.allow_won {
+            error!("Setup failed: Position is already won.");
+            return (
+                StatusCode::BAD_REQUEST,
+                "Position is already won.".to_string(),
+            );
+        }
+        *current = game.clone();
+        game
+    };
+    info!("Game set up successfully.");
+    state.publish(game_id, &game).await;
+    (StatusCode::OK, "Position set up.".to_string())
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    if args.bench_ai {
+        bench::run(std::io::stdout(), BENCH_MAX_DEPTH).unwrap();
+        return;
+    }
+    if args.local {
+        let book = args
+            .book
+            .as_deref()
+            .map(|path| ai::OpeningBook::load(path).unwrap());
+        let ai_player = args.versus_ai.then_some(Player::P2);
+        local::play(
+            std::io::stdin().lock(),
+            std::io::stdout(),
+            ai_player,
+            book.as_ref(),
+        )
+        .unwrap();
+        return;
+    }
+
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER));
+    tracing_subscriber::registry()
+        .with(log_layer(args.log_format, std::io::stdout))
+        .with(filter)
+        .init();
+    info!("Starting server...");
+
+    let mut shared_state = AppState::default();
+    if let Some(path) = &args.db {
+        let store = Store::open(path).await.unwrap();
+        let games = store.load_all().await.unwrap();
+        info!("Loaded {} game(s) from {}.", games.len(), path);
+        shared_state = AppState {
+            games: Arc::new(Mutex::new(games)),
+            store: Some(store),
+            ..AppState::default()
+        };
+    }
+    if let Some(path) = &args.book {
+        let book = ai::OpeningBook::load(path).unwrap();
+        info!("Loaded opening book from {}.", path);
+        shared_state.book = Some(Arc::new(book));
+    }
+    if !args.cors_origins.is_empty() {
+        info!("Allowing CORS requests from {:?}.", args.cors_origins);
+        shared_state.cors_origins = args.cors_origins.into();
+    }
+    if args.rate_limit > 0 {
+        shared_state.rate_limiter = Some(Arc::new(RateLimiter::new(args.rate_limit)));
+    }
+    if args.max_sessions > 0 || args.session_ttl > 0 {
+        let ttl = (args.session_ttl > 0).then(|| Duration::from_secs(args.session_ttl));
+        let limits = SessionLimits::new(args.max_sessions, ttl);
+        // Games reloaded from the database count as used just now
+        let now = Instant::now();
+        limits
+            .last_access()
+            .extend(shared_state.games().keys().map(|&id| (id, now)));
+        shared_state.session_limits = Some(Arc::new(limits));
+    }
+
+    let app = build_router(shared_state.clone());
+
+    let listener = match tokio::net::TcpListener::bind(args.addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Could not listen on {}: {}", args.addr, e);
+            std::process::exit(1);
+        }
+    };
+    info!("Listening on http://{}", args.addr);
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+        .unwrap();
+
+    // In-flight requests have finished; make sure their writes reach the disk
+    if let Some(store) = &shared_state.store {
+        store.close().await;
+    }
+    info!("Server stopped.");
+}
+
+// Builds the application: every route, the static assets and CORS.
+fn build_router(state: AppState) -> Router {
+    let allowed_origins = if state.cors_origins.is_empty() {
+        AllowOrigin::from(Any)
+    } else {
+        AllowOrigin::list(state.cors_origins.iter().cloned())
+    };
+    let cors = CorsLayer::new()
+        .allow_origin(allowed_origins)
+        .allow_headers(Any)
+        .allow_methods(Any);
+
+    let serve_dir = ServeDir::new("assets").not_found_service(ServeFile::new("assets/index.html"));
+    Router::new()
+        .route("/", get(index))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/version", get(get_version))
+        .route("/stats", get(get_stats))
+        .route("/openapi.json", get(get_openapi))
+        .route("/games", get(list_games).post(create_game))
+        .route("/board/{game_id}", get(get_board))
+        .route("/board/{game_id}/ascii", get(get_board_ascii))
+        .route("/board/{game_id}/since", get(get_board_since))
+        .route(
+            "/moves/{game_id}",
+            get(get_move_list).merge(
+                post(make_moves)
+                    .route_layer(middleware::from_fn_with_state(state.clone(), rate_limit)),
+            ),
+        )
+        .route("/config", get(get_default_config))
+        .route("/config/{game_id}", get(get_config))
+        .route("/validate-config", post(validate_config))
+        .route("/code/{game_id}", get(get_code))
+        .route("/status/{game_id}", get(get_status))
+        .route("/status-only/{game_id}", get(get_status_only))
+        .route("/events/{game_id}", get(game_events))
+        .route("/valid-moves/{game_id}", get(get_valid_moves))
+        .route("/all-moves/{game_id}", get(get_all_moves))
+        .route("/movable/{game_id}", get(get_movable_pieces))
+        .route("/neighbors/{game_id}", get(get_neighbors))
+        .route("/piece/{game_id}", get(get_piece))
+        .route(
+            "/move/{game_id}",
+            post(make_move).route_layer(middleware::from_fn_with_state(state.clone(), rate_limit)),
+        )
+        .route("/legal/{game_id}", get(check_legal))
+        .route("/threats/{game_id}", get(get_threats))
+        .route("/can-move/{game_id}", get(can_move))
+        .route("/heatmap/{game_id}", get(get_heatmap))
+        .route(
+            "/ai-move/{game_id}",
+            post(make_ai_move)
+                .route_layer(middleware::from_fn_with_state(state.clone(), rate_limit)),
+        )
+        .route("/autoplay/{game_id}", post(autoplay))
+        .route("/hint/{game_id}", post(get_hint))
+        .route("/analyze/{game_id}", post(analyze))
+        .route("/evaluate", post(evaluate_position))
+        .route("/canonical", post(canonical_key))
+        .route("/undo/{game_id}", post(undo_move))
+        .route("/surrender/{game_id}", post(surrender))
+        .route("/seed/{game_id}", post(set_seed))
+        .route("/reset/{game_id}", post(reset_game))
+        .route("/setup/{game_id}", post(setup_game))
+        .route("/export/{game_id}", get(export_game))
+        .route("/import", post(import_game))
+        .route("/replay/{game_id}", get(replay_game))
+        .route_layer(middleware::from_fn_with_state(state.clone(), track_session))
+        .route_layer(middleware::from_fn(trace_request))
+        .fallback_service(serve_dir)
+        .with_state(state)
+        .layer(cors)
+}
+
+// Middleware in front of the move endpoints: answers 429 Too Many Requests, with a
+// Retry-After header, once a session has used up its rate limit. Unknown sessions
+// are left for the handler to answer 404, without getting a bucket.
+async fn rate_limit(
+    State(state): State<AppState>,
+    Path(game_id): Path<Uuid>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Some(limiter) = &state.rate_limiter {
+        let exists = state.games().contains_key(&game_id);
+        let acquired = if exists {
+            limiter.acquire(game_id, Instant::now())
+        } else {
+            Ok(())
+        };
+        if let Err(wait) = acquired {
+            error!("Rate limit exceeded for game {}.", game_id);
+            let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, retry_after.to_string())],
+                "Too many requests, slow down.",
+            )
+                .into_response();
+        }
+    }
+    next.run(request).await
+}
+
+// Middleware in front of every API route: marks the session named in the path as
+// used, or answers 410 Gone if it was closed for being idle, or is about to be.
+async fn track_session(
+    State(state): State<AppState>,
+    params: RawPathParams,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(limits) = &state.session_limits else {
+        return next.run(request).await;
+    };
+    let Some(game_id) = params
+        .iter()
+        .find(|&(name, _)| name == "game_id")
+        .and_then(|(_, value)| Uuid::parse_str(value).ok())
+    else {
+        return next.run(request).await;
+    };
+
+    let now = Instant::now();
+    let expired = {
+        let mut games = state.games();
+        let expired = limits
+            .last_access()
+            .get(&game_id)
+            .is_some_and(|&last_access| limits.is_expired(last_access, now));
+        if expired {
+            limits.evict(&mut games, game_id);
+        } else if let Some(last_access) = limits.last_access().get_mut(&game_id) {
+            *last_access = now;
+        }
+        expired
+    };
+    if expired {
+        state.forget_session(game_id).await;
+    }
+    if limits.evicted().contains(&game_id) {
+        return (
+            StatusCode::GONE,
+            format!("Game {} was closed after sitting idle.", game_id),
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+// Header carrying the id `trace_request` gave a request, so a client can quote it
+// when reporting a problem.
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+// Middleware in front of every API route: runs the request inside a span holding a
+// fresh request id, the route and the game id, if any, so every log line the
+// request produces can be told apart from those of concurrent requests.
+async fn trace_request(
+    matched: MatchedPath,
+    params: RawPathParams,
+    request: Request,
+    next: Next,
+) -> Response {
+    let request_id = Uuid::new_v4();
+    let span = tracing::info_span!(
+        "request",
+        id = %request_id,
+        method = %request.method(),
+        route = matched.as_str(),
+        game_id = tracing::field::Empty,
+    );
+    if let Some((_, game_id)) = params.iter().find(|&(name, _)| name == "game_id") {
+        span.record("game_id", game_id);
+    }
+
+    let mut response = next.run(request).instrument(span).await;
+    if let Ok(value) = HeaderValue::from_str(&request_id.to_string()) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+// Resolves when the process is asked to stop: Ctrl+C, or SIGTERM on Unix
+// (what `docker stop` sends).
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to listen for Ctrl+C");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    info!("Shutdown signal received, finishing in-flight requests...");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{StalemateRule, WinReason, MAX_BOARD_SIZE, MIN_BOARD_SIZE};
+
+    fn setup_state() -> AppState {
+        AppState::default()
+    }
+
+    async fn new_session(state: &AppState) -> Uuid {
+        let Json(created) = create_game(
+            State(state.clone()),
+            Query(CreateGameQuery::default()),
+            None,
+        )
+        .await
+        .unwrap();
+        created.id
+    }
+
+    #[tokio::test]
+    async fn test_moves_are_persisted_to_the_store() {
+        let path = std::env::temp_dir().join(format!("top-cap-{}.db", Uuid::new_v4()));
+        let path = path.to_string_lossy().into_owned();
+        let state = AppState {
+            store: Some(Store::open(&path).await.unwrap()),
+            ..AppState::default()
+        };
+        let id = new_session(&state).await;
+        let payload = MoveRequest {
+            from: Position { row: 0, col: 3 },
+            to: Position { row: 0, col: 2 },
+        };
+        make_move(State(state.clone()), Path(id), MoveBody(payload))
+            .await
+            .unwrap();
+        let expected = state.games()[&id].clone();
+        drop(state);
+
+        let reloaded = Store::open(&path).await.unwrap().load_all().await.unwrap();
+        assert_eq!(reloaded[&id], expected);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_a_late_save_keeps_the_newest_game() {
+        let path = std::env::temp_dir().join(format!("top-cap-{}.db", Uuid::new_v4()));
+        let path = path.to_string_lossy().into_owned();
+        let state = AppState {
+            store: Some(Store::open(&path).await.unwrap()),
+            ..AppState::default()
+        };
+        let id = new_session(&state).await;
+        let stale = state.games()[&id].clone();
+        let payload = MoveRequest {
+            from: Position { row: 0, col: 3 },
+            to: Position { row: 0, col: 2 },
+        };
+        make_move(State(state.clone()), Path(id), MoveBody(payload))
+            .await
+            .unwrap();
+
+        // A request that finished before the move but saves after it
+        state.publish(id, &stale).await;
+        let expected = state.games()[&id].clone();
+        drop(state);
+
+        let reloaded = Store::open(&path).await.unwrap().load_all().await.unwrap();
+        assert_eq!(reloaded[&id], expected);
+        assert_ne!(reloaded[&id], stale);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_a_late_save_does_not_bring_back_a_closed_session() {
+        let path = std::env::temp_dir().join(format!("top-cap-{}.db", Uuid::new_v4()));
+        let path = path.to_string_lossy().into_owned();
+        let state = AppState {
+            store: Some(Store::open(&path).await.unwrap()),
+            session_limits: Some(Arc::new(SessionLimits::new(1, None))),
+            ..AppState::default()
+        };
+        let closed = new_session(&state).await;
+        let stale = state.games()[&closed].clone();
+
+        // A save of the first session is still under way when opening another
+        // closes it
+        let turn = state.store_writes.lock().await;
+        let opening = tokio::spawn({
+            let state = state.clone();
+            async move { new_session(&state).await }
+        });
+        while state.games().contains_key(&closed) {
+            tokio::task::yield_now().await;
+        }
+        let store = state.store.clone().unwrap();
+        store.save(closed, &stale).await.unwrap();
+        drop(turn);
+        let open = opening.await.unwrap();
+
+        // And a request on it only gets to save once it is closed
+        state.publish(closed, &stale).await;
+        drop(state);
+
+        let reloaded = Store::open(&path).await.unwrap().load_all().await.unwrap();
+        assert!(!reloaded.contains_key(&closed));
+        assert!(reloaded.contains_key(&open));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_idle_sessions_are_closed() {
+        use tower::ServiceExt;
+
+        let state = AppState {
+            session_limits: Some(Arc::new(SessionLimits::new(2, None))),
+            ..AppState::default()
+        };
+        let app = build_router(state.clone());
+        let get_board = |id: Uuid| {
+            let request = Request::get(format!("/board/{}", id))
+                .body(axum::body::Body::empty())
+                .unwrap();
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+
+        let first = new_session(&state).await;
+        let second = new_session(&state).await;
+        // Looking at the first game makes the second the one idle the longest
+        assert_eq!(get_board(first).await, StatusCode::OK);
+        let third = new_session(&state).await;
+
+        assert_eq!(state.games().len(), 2);
+        assert_eq!(get_board(second).await, StatusCode::GONE);
+        assert_eq!(get_board(first).await, StatusCode::OK);
+        assert_eq!(get_board(third).await, StatusCode::OK);
+        assert_eq!(get_board(Uuid::new_v4()).await, StatusCode::NOT_FOUND);
+
+        // With a time limit, a game nobody looks at expires on its own
+        let state = AppState {
+            session_limits: Some(Arc::new(SessionLimits::new(
+                0,
+                Some(Duration::from_millis(20)),
+            ))),
+            ..AppState::default()
+        };
+        let id = new_session(&state).await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let request = Request::get(format!("/board/{}", id))
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = build_router(state.clone()).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::GONE);
+        assert!(state.games().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_only_recent_tombstones_are_kept() {
+        let limits = SessionLimits::new(1, None);
+        *limits.evicted() = Tombstones::new(3);
+        let state = AppState {
+            session_limits: Some(Arc::new(limits)),
+            ..AppState::default()
+        };
+
+        let mut ids = Vec::new();
+        for _ in 0..10 {
+            ids.push(new_session(&state).await);
+        }
+        // Every session but the last was dropped, yet only three are remembered
+        let limits = state.session_limits.as_ref().unwrap();
+        let evicted = limits.evicted();
+        assert_eq!(evicted.ids.len(), 3);
+        assert_eq!(evicted.order.len(), 3);
+        assert!(ids[6..9].iter().all(|id| evicted.contains(id)));
+        assert!(!evicted.contains(&ids[5]));
+        assert!(!evicted.contains(&ids[9]));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_rejects_bursts() {
+        use tower::ServiceExt;
+
+        let state = AppState {
+            rate_limiter: Some(Arc::new(RateLimiter::new(2))),
+            ..AppState::default()
+        };
+        let id = new_session(&state).await;
+        let app = build_router(state);
+
+        let mut statuses = Vec::new();
+        let mut retry_after = None;
+        for _ in 0..4 {
+            // Invalid moves still count against the limit
+            let request = Request::post(format!("/move/{}", id))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(axum::body::Body::from(r#"{"notation":"a1-a2"}"#))
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            statuses.push(response.status());
+            if response.status() == StatusCode::TOO_MANY_REQUESTS {
+                retry_after = response.headers().get(header::RETRY_AFTER).cloned();
+            }
+        }
+
+        assert_eq!(statuses[..2], [StatusCode::BAD_REQUEST; 2]);
+        assert_eq!(statuses[2..], [StatusCode::TOO_MANY_REQUESTS; 2]);
+        assert_eq!(retry_after.unwrap(), "1");
+    }
+
+    #[tokio::test]
+    async fn test_cors_allows_only_listed_origins() {
+        use tower::ServiceExt;
+
+        let args =
+            Args::try_parse_from(["top-cap", "--cors-origin", "https://allowed.example"]).unwrap();
+        let state = AppState {
+            cors_origins: args.cors_origins.into(),
+            ..AppState::default()
+        };
+        let app = build_router(state);
+        let allowed_origin = |origin: &'static str| {
+            let request = Request::get("/healthz")
+                .header(header::ORIGIN, origin)
+                .body(axum::body::Body::empty())
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                response
+                    .headers()
+                    .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                    .cloned()
+            }
+        };
+
+        assert_eq!(
+            allowed_origin("https://allowed.example").await.unwrap(),
+            "https://allowed.example"
+        );
+        assert_eq!(allowed_origin("https://evil.example").await, None);
+
+        // Without the flag any origin is allowed, as before
+        let request = Request::get("/healthz")
+            .header(header::ORIGIN, "https://evil.example")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = build_router(setup_state()).oneshot(request).await.unwrap();
+        assert_eq!(response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+    }
+
+    #[test]
+    fn test_cors_origins_are_validated() {
+        assert!(parse_origin("http://localhost:3000").is_ok());
+        assert!(parse_origin("https://example.com").is_ok());
+        for bad in [
+            "example.com",
+            "https://",
+            "https://example.com/app",
+            "ftp://example.com",
+        ] {
+            assert!(parse_origin(bad).is_err(), "{}", bad);
+        }
+        assert!(Args::try_parse_from(["top-cap", "--cors-origin", "example.com"]).is_err());
+    }
+
+    #[test]
+    fn test_token_bucket_refills_over_time() {
+        let limiter = RateLimiter::new(2);
+        let id = Uuid::new_v4();
+        let start = Instant::now();
+        assert!(limiter.acquire(id, start).is_ok());
+        assert!(limiter.acquire(id, start).is_ok());
+        let wait = limiter.acquire(id, start).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(500));
+
+        // Other sessions have their own bucket
+        assert!(limiter.acquire(Uuid::new_v4(), start).is_ok());
+
+        assert!(limiter.acquire(id, start + wait).is_ok());
+        assert!(limiter.acquire(id, start + wait).is_err());
+
+        // A second later every bucket is full again, so they are all swept out
+        // except the one being used
+        let later = start + wait + BUCKET_REFILL_TIME;
+        assert!(limiter.acquire(id, later).is_ok());
+        assert_eq!(limiter.buckets().by_session.len(), 1);
+        limiter.forget(id);
+        assert!(limiter.buckets().by_session.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_move_far_off_the_board_is_rejected() {
+        use tower::ServiceExt;
+
+        let state = setup_state();
+        let id = new_session(&state).await;
+        let app = build_router(state.clone());
+        let post = |path: String, body: String| {
+            let request = Request::post(path)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(axum::body::Body::from(body))
+                .unwrap();
+            app.clone().oneshot(request)
+        };
+        let far = format!(
+            r#"{{"from":{{"row":{},"col":200}},"to":{{"row":0,"col":0}}}}"#,
+            usize::MAX
+        );
+
+        let response = post(format!("/move/{}", id), far.clone()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // A batch reports the failed move in its body instead
+        let response = post(
+            format!("/moves/{}", id),
+            format!(r#"{{"moves":[{}]}}"#, far),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let batch: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(batch["applied"], 0);
+        assert_eq!(batch["error"]["code"], "off_board");
+        assert_eq!(state.games()[&id].ply, 0);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_ignores_unknown_and_closed_sessions() {
+        use tower::ServiceExt;
+
+        let limiter = Arc::new(RateLimiter::new(2));
+        let state = AppState {
+            rate_limiter: Some(limiter.clone()),
+            session_limits: Some(Arc::new(SessionLimits::new(1, None))),
+            ..AppState::default()
+        };
+        let app = build_router(state.clone());
+        let post_move = |id: Uuid| {
+            let request = Request::post(format!("/move/{}", id))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(axum::body::Body::from(r#"{"notation":"d1-c1"}"#))
+                .unwrap();
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+
+        for _ in 0..3 {
+            assert_eq!(post_move(Uuid::new_v4()).await, StatusCode::NOT_FOUND);
+        }
+        assert!(limiter.buckets().by_session.is_empty());
+
+        let first = new_session(&state).await;
+        assert_eq!(post_move(first).await, StatusCode::OK);
+        assert_eq!(limiter.buckets().by_session.len(), 1);
+        // Opening another session closes the first, and its bucket goes with it
+        new_session(&state).await;
+        assert!(limiter.buckets().by_session.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_server_shuts_down_gracefully() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            axum::serve(listener, build_router(setup_state()))
+                .with_graceful_shutdown(async {
+                    let _ = stopped.await;
+                })
+                .await
+        });
+
+        stop.send(()).unwrap();
+        let result = tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("server did not stop in time");
+        assert!(result.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_server_listens_on_configured_address() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let args = Args::try_parse_from(["top-cap", "--addr", "127.0.0.1:0"]).unwrap();
+        let listener = tokio::net::TcpListener::bind(args.addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            axum::serve(listener, build_router(setup_state()))
+                .await
+                .unwrap();
+        });
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /healthz HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        server.abort();
+
+        let error = Args::try_parse_from(["top-cap", "--addr", "localhost"]).unwrap_err();
+        assert!(error.to_string().contains("invalid value 'localhost'"));
+    }
+
+    #[tokio::test]
+    async fn test_board_ascii_draws_starting_position() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+
+        let text = get_board_ascii(
+            State(state.clone()),
+            Path(id),
+            Query(AsciiQuery { emoji: true }),
+        )
+        .await
+        .unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "Turn: Player 1");
+        assert_eq!(lines[1], "Status: Ongoing");
+        assert_eq!(lines.len(), 2 + DEFAULT_BOARD_SIZE);
+        assert_eq!(lines[2], ". . . 🔴 . . .");
+        assert_eq!(lines[5], "🔴 . . . . . 🔵");
+        assert_eq!(lines[8], ". . . 🔵 . . .");
+
+        let text = get_board_ascii(State(state), Path(id), Query(AsciiQuery { emoji: false }))
+            .await
+            .unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[5], "1 . . . . . 2");
+        assert!(!text.contains('🔴'));
+    }
+
+    #[tokio::test]
+    async fn test_config_reports_goals_and_start_squares() {
+        let Json(config) = get_default_config().await;
+        let last = DEFAULT_BOARD_SIZE - 1;
+        assert_eq!(config.board_size, DEFAULT_BOARD_SIZE);
+        assert_eq!(config.goal_p1, Position { row: 0, col: 0 });
+        assert_eq!(
+            config.goal_p2,
+            Position {
+                row: last,
+                col: last
+            }
+        );
+
+        let state = setup_state();
+        let id = new_session(&state).await;
+        let Json(config) = get_config(State(state.clone()), Path(id)).await.unwrap();
+        let game = state.games()[&id].clone();
+        for square in &config.p1_start {
+            assert_eq!(game.board[square.row][square.col], Some(Player::P1));
+        }
+        for square in &config.p2_start {
+            assert_eq!(game.board[square.row][square.col], Some(Player::P2));
+        }
+        assert_eq!(config.p1_start.len() + config.p2_start.len(), 8);
+        assert_eq!(config.players, 2);
+        assert!(config.goal_p3.is_none() && config.p3_start.is_none());
+
+        // A four-player game also reports where Players 3 and 4 start and aim
+        let request = CreateGameRequest {
+            size: Some(8),
+            config: GameConfig {
+                players: 4,
+                ..GameConfig::default()
+            },
+        };
+        let Json(created) = create_game(
+            State(state.clone()),
+            Query(CreateGameQuery::default()),
+            Some(Json(request)),
+        )
+        .await
+        .unwrap();
+        let Json(config) = get_config(State(state.clone()), Path(created.id))
+            .await
+            .unwrap();
+        assert_eq!(config.players, 4);
+        assert_eq!(config.goal_p3, Some(Position { row: 0, col: 7 }));
+        assert_eq!(config.goal_p4, Some(Position { row: 7, col: 0 }));
+        let game = state.games()[&created.id].clone();
+        for (player, squares) in [
+            (Player::P3, &config.p3_start),
+            (Player::P4, &config.p4_start),
+        ] {
+            let squares = squares.as_ref().unwrap();
+            assert!(!squares.is_empty());
+            for square in squares {
+                assert_eq!(game.board[square.row][square.col], Some(player));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_autoplay_plays_game_to_the_end() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+
+        let query = AutoplayQuery {
+            depth: Some(1),
+            max_plies: Some(MAX_AUTOPLAY_PLIES),
+            seed: Some(3),
+        };
+        let Json(response) = autoplay(State(state.clone()), Path(id), Query(query))
+            .await
+            .unwrap();
+        assert!(matches!(
+            response.game.status,
+            GameStatus::Won(..) | GameStatus::Draw
+        ));
+        assert_eq!(response.plies, response.game.ply);
+        assert_eq!(state.games()[&id], response.game);
+
+        // The game is over, so there's nothing left to play
+        let result = autoplay(State(state), Path(id), Query(query)).await;
+        assert_eq!(result.unwrap_err().0, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_autoplay_stops_at_ply_cap() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+
+        let query = AutoplayQuery {
+            depth: Some(1),
+            max_plies: Some(2),
+            seed: None,
+        };
+        let Json(response) = autoplay(State(state), Path(id), Query(query))
+            .await
+            .unwrap();
+        assert_eq!(response.plies, 2);
+        assert_eq!(response.game.status, GameStatus::Ongoing);
+    }
+
+    #[tokio::test]
+    async fn test_list_games_reports_each_session() {
+        let state = setup_state();
+        let ongoing = new_session(&state).await;
+        let drawn = new_session(&state).await;
+        let fresh = new_session(&state).await;
+
+        make_move(
+            State(state.clone()),
+            Path(ongoing),
+            MoveBody(MoveRequest {
+                from: Position { row: 0, col: 3 },
+                to: Position { row: 0, col: 2 },
+            }),
+        )
+        .await
+        .unwrap();
+        state.games().get_mut(&drawn).unwrap().status = GameStatus::Draw;
+
+        let Json(list) = list_games(State(state.clone()), Query(ListGamesQuery::default())).await;
+        assert_eq!(list.len(), 3);
+        assert!(list.windows(2).all(|pair| pair[0].id < pair[1].id));
+        let summary = |id| list.iter().find(|summary| summary.id == id).unwrap();
+        assert_eq!(summary(ongoing).ply, 1);
+        assert_eq!(summary(ongoing).current_player, Player::P2);
+        assert_eq!(summary(drawn).status, GameStatus::Draw);
+        assert_eq!(summary(fresh).status, GameStatus::Ongoing);
+        assert_eq!(summary(fresh).ply, 0);
+
+        let query = ListGamesQuery {
+            limit: Some(1),
+            offset: 1,
+        };
+        let Json(page) = list_games(State(state), Query(query)).await;
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].id, list[1].id);
+    }
+
+    #[tokio::test]
+    async fn test_sessions_with_the_same_seed_play_the_same_game() {
+        let state = setup_state();
+        let play = |seed| {
+            let state = state.clone();
+            async move {
+                let id = new_session(&state).await;
+                let _ = set_seed(State(state.clone()), Path(id), Json(SeedRequest { seed }))
+                    .await
+                    .unwrap();
+                let query = AiMoveQuery {
+                    depth: Some(1),
+                    ..Default::default()
+                };
+                for _ in 0..12 {
+                    if state.games()[&id].status != GameStatus::Ongoing {
+                        break;
+                    }
+                    let _ = make_ai_move(State(state.clone()), Path(id), Query(query), None)
+                        .await
+                        .unwrap();
+                }
+                state.games()[&id].history.clone()
+            }
+        };
+
+        let first = play(42).await;
+        assert_eq!(play(42).await, first);
+        // Depth 1 sees many ties, so another seed goes its own way
+        assert_ne!(play(43).await, first);
+
+        let missing = set_seed(
+            State(state.clone()),
+            Path(Uuid::new_v4()),
+            Json(SeedRequest { seed: 1 }),
+        );
+        assert_eq!(missing.await.unwrap_err().0, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_stats_count_finished_games_once() {
+        let state = setup_state();
+        let mut expected = Scoreboard::default();
+        for seed in [1, 2] {
+            let id = new_session(&state).await;
+            let query = AutoplayQuery {
+                depth: Some(1),
+                max_plies: Some(MAX_AUTOPLAY_PLIES),
+                seed: Some(seed),
+            };
+            let Json(response) = autoplay(State(state.clone()), Path(id), Query(query))
+                .await
+                .unwrap();
+            *expected.counter(response.game.status).unwrap() += 1;
+        }
+
+        // Reading the stats or the finished games again doesn't count them twice
+        for _ in 0..2 {
+            let Json(stats) = get_stats(State(state.clone())).await;
+            assert_eq!(stats, expected);
+            assert_eq!(stats.p1_wins + stats.p2_wins + stats.draws, 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stats_forget_undone_result() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+        {
+            let mut games = state.games();
+            let game = games.get_mut(&id).unwrap();
+            game.set_board(vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE]);
+            game.set_square(Position { row: 3, col: 3 }, Some(Player::P1));
+            game.set_square(Position { row: 2, col: 2 }, Some(Player::P2));
+            game.set_square(Position { row: 2, col: 4 }, Some(Player::P2));
+            game.set_square(Position { row: 4, col: 2 }, Some(Player::P2));
+        }
+
+        let payload = MoveRequest {
+            from: Position { row: 3, col: 3 },
+            to: Position { row: 6, col: 6 },
+        };
+        make_move(State(state.clone()), Path(id), MoveBody(payload))
+            .await
+            .unwrap();
+        let Json(stats) = get_stats(State(state.clone())).await;
+        assert_eq!(stats.p1_wins, 1);
+
+        let _ = undo_move(State(state.clone()), Path(id), Query(UndoQuery::default()))
+            .await
+            .unwrap();
+        let Json(stats) = get_stats(State(state)).await;
+        assert_eq!(stats, Scoreboard::default());
+    }
+
+    #[tokio::test]
+    async fn test_move_list_uses_notation() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+        for notation in ["d1-c1", "d7-e7", "c1-d1"] {
+            let payload = MoveRequest::from_notation(notation).unwrap();
+            make_move(State(state.clone()), Path(id), MoveBody(payload))
+                .await
+                .unwrap();
+        }
+        let _ = undo_move(State(state.clone()), Path(id), Query(UndoQuery::default()))
+            .await
+            .unwrap();
+
+        let Json(moves) = get_move_list(State(state.clone()), Path(id)).await.unwrap();
+        assert_eq!(
+            moves,
+            vec![
+                NotatedMove {
+                    ply: 1,
+                    player: Player::P1,
+                    notation: "d1-c1".to_string(),
+                },
+                NotatedMove {
+                    ply: 2,
+                    player: Player::P2,
+                    notation: "d7-e7".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export_then_import_reproduces_game() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+        for (from, to) in [
+            ((0, 3), (0, 2)),
+            ((6, 3), (6, 4)),
+            ((0, 2), (0, 3)),
+            ((6, 4), (6, 5)),
+            ((3, 0), (4, 0)),
+        ] {
+            let payload = MoveRequest {
+                from: Position {
+                    row: from.0,
+                    col: from.1,
+                },
+                to: Position {
+                    row: to.0,
+                    col: to.1,
+                },
+            };
+            make_move(State(state.clone()), Path(id), MoveBody(payload))
+                .await
+                .unwrap();
+        }
+
+        let Json(transcript) = export_game(State(state.clone()), Path(id)).await.unwrap();
+        assert_eq!(transcript.moves.len(), 5);
+        let Json(imported) = import_game(State(state.clone()), Json(transcript))
+            .await
+            .unwrap();
+
+        let games = state.games();
+        assert_ne!(imported.id, id);
+        assert_eq!(imported.game, games[&id]);
+        assert_eq!(games[&imported.id], games[&id]);
+    }
+
+    #[tokio::test]
+    async fn test_resigned_game_can_be_exported_and_replayed() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+        for notation in ["d1-c1", "d7-e7"] {
+            let payload = MoveRequest::from_notation(notation).unwrap();
+            make_move(State(state.clone()), Path(id), MoveBody(payload))
+                .await
+                .unwrap();
+        }
+        let _ = surrender(
+            State(state.clone()),
+            Path(id),
+            Json(SurrenderRequest { player: Player::P1 }),
+        )
+        .await
+        .unwrap();
+
+        let Json(transcript) = export_game(State(state.clone()), Path(id)).await.unwrap();
+        assert_eq!(transcript.initial_config, Game::new().to_code());
+        let Json(imported) = import_game(State(state.clone()), Json(transcript))
+            .await
+            .unwrap();
+        let resigned = state.games()[&id].clone();
+        assert_eq!(imported.game.board, resigned.board);
+        assert_eq!(imported.game.ply, 2);
+
+        let response = replay_game(
+            State(state.clone()),
+            Path(id),
+            Query(ReplayQuery::default()),
+        )
+        .await
+        .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let snapshots: Vec<Game> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(snapshots.len(), 3);
+        assert_eq!(snapshots[0], Game::new());
+        assert_eq!(snapshots[2], resigned);
+    }
+
+    #[tokio::test]
+    async fn test_replay_returns_every_position() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+        for (from, to) in [((0, 3), (0, 2)), ((6, 3), (6, 4)), ((0, 2), (0, 3))] {
+            let payload = MoveRequest {
+                from: Position {
+                    row: from.0,
+                    col: from.1,
+                },
+                to: Position {
+                    row: to.0,
+                    col: to.1,
+                },
+            };
+            make_move(State(state.clone()), Path(id), MoveBody(payload))
+                .await
+                .unwrap();
+        }
+
+        let response = replay_game(
+            State(state.clone()),
+            Path(id),
+            Query(ReplayQuery::default()),
+        )
+        .await
+        .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let snapshots: Vec<Game> = serde_json::from_slice(&body).unwrap();
+        let ply = state.games()[&id].ply;
+        assert_eq!(snapshots.len(), ply as usize + 1);
+        assert_eq!(snapshots[0], Game::new());
+
+        let (status, _) = replay_game(
+            State(state.clone()),
+            Path(id),
+            Query(ReplayQuery { step: Some(4) }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_healthz_ignores_busy_state() {
+        let state = setup_state();
+
+        // Hold the game state lock on another thread, as a slow request would
+        let (locked_tx, locked_rx) = std::sync::mpsc::channel();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let games = state.games.clone();
+        let holder = std::thread::spawn(move || {
+            let _guard = games.lock().unwrap();
+            locked_tx.send(()).unwrap();
+            let _ = release_rx.recv();
+        });
+        locked_rx.recv().unwrap();
+
+        let Json(health) = tokio::time::timeout(Duration::from_secs(1), healthz())
+            .await
+            .expect("healthz waited on the game state");
+        assert_eq!(health.status, "ok");
+
+        release_tx.send(()).unwrap();
+        holder.join().unwrap();
+        let (status, _) = readyz(State(state.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_version_reports_the_crate_version() {
+        use tower::ServiceExt;
+
+        // No session needed
+        let app = build_router(setup_state());
+        let request = Request::get("/version")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let version: VersionResponse = serde_json::from_slice(&body).unwrap();
+
+        let manifest = include_str!("../Cargo.toml");
+        assert!(manifest.contains(&format!("\nversion = \"{}\"\n", version.version)));
+        assert!(!version.git_sha.is_empty());
+        // e.g. 2025-08-22T09:30:00Z
+        assert_eq!(version.build_time.len(), 20);
+        assert!(version.build_time.ends_with('Z'));
+    }
+
+    #[tokio::test]
+    async fn test_status_only_is_small_and_shows_the_winner() {
+        use tower::ServiceExt;
+
+        let state = setup_state();
+        // P1 wins at once by stepping onto P2's goal
+        let mut board = vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE];
+        board[4][4] = Some(Player::P1);
+        board[5][5] = Some(Player::P1);
+        board[1][1] = Some(Player::P2);
+        board[2][2] = Some(Player::P2);
+        let id = Uuid::new_v4();
+        state
+            .games()
+            .insert(id, Game::from_setup(board, Player::P1).unwrap());
+
+        let app = build_router(state.clone());
+        let fetch = |uri: String| {
+            let app = app.clone();
+            async move {
+                let request = Request::get(uri).body(axum::body::Body::empty()).unwrap();
+                let response = app.oneshot(request).await.unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let payload = MoveRequest::from_notation("f6-g7").unwrap();
+        make_move(State(state.clone()), Path(id), MoveBody(payload))
+            .await
+            .unwrap();
+
+        let body = fetch(format!("/status-only/{}", id)).await;
+        let status: StatusOnlyResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            status.status,
+            GameStatus::Won(Player::P1, WinReason::ReachedGoal)
+        );
+        assert_eq!(status.current_player, Player::P1);
+        assert_eq!(status.ply, 1);
+
+        let board = fetch(format!("/board/{}", id)).await;
+        assert!(body.len() < 100, "{} bytes", body.len());
+        assert!(body.len() * 10 < board.len());
+    }
+
+    #[tokio::test]
+    async fn test_poisoned_lock_is_recovered() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+
+        // A handler panicking mid-update poisons the lock
+        let games = state.games.clone();
+        let panicked = std::thread::spawn(move || {
+            let _guard = games.lock().unwrap();
+            panic!("deliberate panic while holding the game state");
+        })
+        .join();
+        assert!(panicked.is_err());
+        assert!(state.games.is_poisoned());
+
+        let result = make_move(
+            State(state.clone()),
+            Path(id),
+            MoveBody(MoveRequest {
+                from: Position { row: 0, col: 3 },
+                to: Position { row: 0, col: 2 },
+            }),
+        )
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(state.games()[&id].ply, 1);
+
+        let (status, _) = readyz(State(state.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_sessions_are_independent() {
+        let state = setup_state();
+        let first = new_session(&state).await;
+        let second = new_session(&state).await;
+        assert_ne!(first, second);
+
+        let payload = MoveRequest {
+            from: Position { row: 0, col: 3 },
+            to: Position { row: 0, col: 2 },
+        };
+        let (status, _) = make_move(State(state.clone()), Path(first), MoveBody(payload))
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+
+        let games = state.games();
+        assert_eq!(games[&first].board[0][2], Some(Player::P1));
+        assert_eq!(games[&second], Game::new());
+    }
+
+    #[tokio::test]
+    async fn test_zero_length_move_returns_json_error() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+
+        let square = Position { row: 0, col: 3 };
+        let payload = MoveRequest {
+            from: square,
+            to: square,
+        };
+        let (status, Json(body)) = make_move(State(state.clone()), Path(id), MoveBody(payload))
+            .await
+            .unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body.code, "same_square");
+        assert_eq!(body.error, "Source and destination must differ.");
+        assert_eq!(state.games()[&id], Game::new());
+    }
+
+    #[tokio::test]
+    async fn test_illegal_move_returns_json_error() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+
+        // (0,3) has a single neighbor, so it cannot reach (0,1)
+        let payload = MoveRequest {
+            from: Position { row: 0, col: 3 },
+            to: Position { row: 0, col: 1 },
+        };
+        let (status, Json(body)) = make_move(State(state.clone()), Path(id), MoveBody(payload))
+            .await
+            .unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let json = serde_json::to_string(&body).unwrap();
+        let parsed: ErrorResponse = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.code, "illegal_move");
+        assert_eq!(parsed.error, "Illegal move.");
+
+        let payload = MoveRequest {
+            from: Position { row: 0, col: 3 },
+            to: Position { row: 9, col: 9 },
+        };
+        let (_, Json(body)) = make_move(State(state.clone()), Path(id), MoveBody(payload))
+            .await
+            .unwrap_err();
+        assert_eq!(body.code, "off_board");
+    }
+
+    #[tokio::test]
+    async fn test_malformed_move_body_gets_friendly_error() {
+        for body in ["{}", r#"{ "from": "a1" }"#, "not json"] {
+            let request = Request::builder()
+                .method("POST")
+                .header("content-type", "application/json")
+                .body(axum::body::Body::from(body))
+                .unwrap();
+            let Err((status, Json(error))) = MoveBody::from_request(request, &()).await else {
+                panic!("{} was accepted", body);
+            };
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(error.code, "invalid_body");
+            assert!(error
+                .error
+                .contains("expected {from:{row,col},to:{row,col}}"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_undo_with_empty_history_is_rejected() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+
+        let (status, _) = undo_move(State(state.clone()), Path(id), Query(UndoQuery::default()))
+            .await
+            .unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_undo_several_moves_at_once() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+        let mut expected = Vec::new();
+        for (from, to) in [
+            ((0, 3), (0, 2)),
+            ((6, 3), (6, 4)),
+            ((0, 2), (0, 3)),
+            ((6, 4), (6, 5)),
+        ] {
+            expected.push(state.games()[&id].clone());
+            let payload = MoveRequest {
+                from: Position {
+                    row: from.0,
+                    col: from.1,
+                },
+                to: Position {
+                    row: to.0,
+                    col: to.1,
+                },
+            };
+            make_move(State(state.clone()), Path(id), MoveBody(payload))
+                .await
+                .unwrap();
+        }
+
+        let undo = |count| undo_move(State(state.clone()), Path(id), Query(UndoQuery { count }));
+        let Json(response) = undo(Some(3)).await.unwrap();
+        assert_eq!(response.undone, 3);
+        assert_eq!(response.game, expected[1]);
+        assert_eq!(state.games()[&id], expected[1]);
+
+        // Only one move is left to take back
+        let Json(response) = undo(Some(5)).await.unwrap();
+        assert_eq!(response.undone, 1);
+        assert_eq!(response.game, Game::new());
+    }
+
+    #[tokio::test]
+    async fn test_ai_move_reports_the_expected_line_on_request() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+        let query = |pv| AiMoveQuery {
+            depth: Some(3),
+            seed: Some(7),
+            pv,
+            ..Default::default()
+        };
+
+        let Json(played) = make_ai_move(State(state.clone()), Path(id), Query(query(true)), None)
+            .await
+            .unwrap();
+        let pv = played.pv.unwrap();
+        assert_eq!(pv.len(), 3);
+        assert_eq!(
+            pv[0],
+            MoveRequest {
+                from: played.from,
+                to: played.to
+            }
+        );
+
+        let Json(played) = make_ai_move(State(state.clone()), Path(id), Query(query(false)), None)
+            .await
+            .unwrap();
+        assert!(played.pv.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_ai_strategy_weakens_ai_moves_but_not_hints() {
+        let state = setup_state();
+        // P1 wins at once by stepping onto P2's goal
+        let mut board = vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE];
+        board[4][4] = Some(Player::P1);
+        board[5][5] = Some(Player::P1);
+        board[1][1] = Some(Player::P2);
+        board[2][2] = Some(Player::P2);
+        let mut game = Game::from_setup(board, Player::P1).unwrap();
+        game.config.ai_strategy = Strategy::Worst;
+        let id = Uuid::new_v4();
+        state.games().insert(id, game);
+        let winning_move = (Position { row: 5, col: 5 }, Position { row: 6, col: 6 });
+        let query = || AiMoveQuery {
+            depth: Some(2),
+            ..Default::default()
+        };
+
+        let Json(hint) = get_hint(State(state.clone()), Path(id), Query(query()), None)
+            .await
+            .unwrap();
+        assert_eq!((hint.from, hint.to), winning_move);
+
+        let Json(played) = make_ai_move(State(state.clone()), Path(id), Query(query()), None)
+            .await
+            .unwrap();
+        assert_ne!((played.from, played.to), winning_move);
+        let game = state.games()[&id].clone();
+        assert_eq!(game.ply, 1);
+        assert_eq!(game.status, GameStatus::Ongoing);
+    }
+
+    #[tokio::test]
+    async fn test_ai_move_dry_run_leaves_the_game_alone() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+        let query = |apply| AiMoveQuery {
+            depth: Some(2),
+            seed: Some(7),
+            apply,
+            ..Default::default()
+        };
+
+        let Json(dry_run) = make_ai_move(
+            State(state.clone()),
+            Path(id),
+            Query(query(Some(false))),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(state.games()[&id], Game::new());
+
+        // Playing it for real makes the same move
+        let Json(played) = make_ai_move(State(state.clone()), Path(id), Query(query(None)), None)
+            .await
+            .unwrap();
+        assert_eq!((played.from, played.to), (dry_run.from, dry_run.to));
+        let game = state.games()[&id].clone();
+        assert_eq!(game.ply, 1);
+        assert_eq!(game.current_player, Player::P2);
+    }
+
+    #[tokio::test]
+    async fn test_ai_move_rejects_out_of_range_depth() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+
+        for depth in [0, ai::MAX_SEARCH_DEPTH + 1] {
+            let query = AiMoveQuery {
+                depth: Some(depth),
+                ..Default::default()
+            };
+            let (status, _) = make_ai_move(State(state.clone()), Path(id), Query(query), None)
+                .await
+                .unwrap_err();
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+        }
+        assert_eq!(state.games()[&id], Game::new());
+    }
+
+    #[tokio::test]
+    async fn test_status_reports_winner_and_reason() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+
+        let Json(status) = get_status(State(state.clone()), Path(id)).await.unwrap();
+        assert!(!status.over);
+        assert_eq!(status.winner, None);
+
+        {
+            let mut games = state.games();
+            let game = games.get_mut(&id).unwrap();
+            game.set_board(vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE]);
+            game.set_square(Position { row: 3, col: 3 }, Some(Player::P1));
+            game.set_square(Position { row: 2, col: 2 }, Some(Player::P2));
+            game.set_square(Position { row: 2, col: 4 }, Some(Player::P2));
+            game.set_square(Position { row: 4, col: 2 }, Some(Player::P2));
+            game.make_move(Position { row: 3, col: 3 }, Position { row: 6, col: 6 })
+                .unwrap();
+        }
+
+        let Json(status) = get_status(State(state.clone()), Path(id)).await.unwrap();
+        assert!(status.over);
+        assert_eq!(status.winner, Some(Player::P1));
+        assert_eq!(status.reason, Some(WinReason::ReachedGoal));
+    }
+
+    #[tokio::test]
+    async fn test_setup_then_finish_puzzle() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+
+        // P1 at (3,3) has three neighbors and can slide onto P2's base
+        let mut board = vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE];
+        board[3][3] = Some(Player::P1);
+        board[2][2] = Some(Player::P2);
+        board[2][4] = Some(Player::P2);
+        board[4][2] = Some(Player::P2);
+        let payload = SetupRequest {
+            board,
+            current_player: Player::P1,
+            allow_won: false,
+        };
+        let (status, _) = setup_game(State(state.clone()), Path(id), Json(payload)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let payload = MoveRequest {
+            from: Position { row: 3, col: 3 },
+            to: Position { row: 6, col: 6 },
+        };
+        make_move(State(state.clone()), Path(id), MoveBody(payload))
+            .await
+            .unwrap();
+        let Json(status) = get_status(State(state.clone()), Path(id)).await.unwrap();
+        assert_eq!(status.winner, Some(Player::P1));
+    }
+
+    #[tokio::test]
+    async fn test_setup_rejects_won_position_without_flag() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+
+        let mut board = vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE];
+        board[6][6] = Some(Player::P1);
+        board[5][5] = Some(Player::P2);
+        let payload = SetupRequest {
+            board: board.clone(),
+            current_player: Player::P2,
+            allow_won: false,
+        };
+        let (status, _) = setup_game(State(state.clone()), Path(id), Json(payload)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let payload = SetupRequest {
+            board,
+            current_player: Player::P2,
+            allow_won: true,
+        };
+        let (status, _) = setup_game(State(state.clone()), Path(id), Json(payload)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_setup_keeps_session_config() {
+        let state = setup_state();
+        let config = GameConfig {
+            p2_goal: Some(Position { row: 5, col: 5 }),
+            clocks: Some(game::Clocks {
+                p1_ms: 60_000,
+                p2_ms: 60_000,
+            }),
+            mode: Some(GameMode::VsAi {
+                ai_player: Player::P2,
+            }),
+            ai_strategy: Strategy::Random,
+            ..GameConfig::default()
+        };
+        let request = CreateGameRequest {
+            size: None,
+            config: config.clone(),
+        };
+        let Json(created) = create_game(
+            State(state.clone()),
+            Query(CreateGameQuery::default()),
+            Some(Json(request)),
+        )
+        .await
+        .unwrap();
+        let id = created.id;
+
+        // P1 on (5,5) has reached P2's base under this config, though not the default one
+        let mut board = vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE];
+        board[5][5] = Some(Player::P1);
+        board[0][2] = Some(Player::P2);
+        board[0][3] = Some(Player::P2);
+        let payload = SetupRequest {
+            board: board.clone(),
+            current_player: Player::P2,
+            allow_won: false,
+        };
+        let (status, _) = setup_game(State(state.clone()), Path(id), Json(payload)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        board[5][5] = None;
+        board[4][4] = Some(Player::P1);
+        let payload = SetupRequest {
+            board,
+            current_player: Player::P2,
+            allow_won: false,
+        };
+        let (status, _) = setup_game(State(state.clone()), Path(id), Json(payload)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let game = state.games()[&id].clone();
+        assert_eq!(game.config.p2_goal, config.p2_goal);
+        assert_eq!(game.config.mode, config.mode);
+        assert_eq!(game.config.ai_strategy, Strategy::Random);
+        assert!(game.clock.is_some());
+
+        // The config also survives an export and import
+        let Json(transcript) = export_game(State(state.clone()), Path(id)).await.unwrap();
+        let imported = Game::from_transcript(&transcript).unwrap();
+        assert_eq!(imported.config, game.config);
+        assert!(imported.clock.is_some());
+    }
+
+    async fn new_session_with_mode(state: &AppState, mode: GameMode) -> Uuid {
+        let request = CreateGameRequest {
+            size: None,
+            config: GameConfig {
+                mode: Some(mode),
+                ..GameConfig::default()
+            },
+        };
+        let Json(created) = create_game(
+            State(state.clone()),
+            Query(CreateGameQuery::default()),
+            Some(Json(request)),
+        )
+        .await
+        .unwrap();
+        created.id
+    }
+
+    #[tokio::test]
+    async fn test_human_mode_disables_the_ai() {
+        let state = setup_state();
+        let id = new_session_with_mode(&state, GameMode::Human).await;
+        let ai_move = || {
+            let query = AiMoveQuery {
+                depth: Some(1),
+                ..Default::default()
+            };
+            make_ai_move(State(state.clone()), Path(id), Query(query), None)
+        };
+
+        assert_eq!(
+            ai_move().await.unwrap_err(),
+            (
+                StatusCode::BAD_REQUEST,
+                "AI disabled for this game".to_string()
+            )
+        );
+
+        // Both sides move through /move
+        for (from, to) in [((0, 3), (0, 2)), ((6, 3), (6, 4))] {
+            let payload = MoveRequest {
+                from: Position {
+                    row: from.0,
+                    col: from.1,
+                },
+                to: Position {
+                    row: to.0,
+                    col: to.1,
+                },
+            };
+            make_move(State(state.clone()), Path(id), MoveBody(payload))
+                .await
+                .unwrap();
+        }
+        assert!(ai_move().await.is_err());
+        assert_eq!(state.games()[&id].ply, 2);
+    }
+
+    #[tokio::test]
+    async fn test_vs_ai_mode_keeps_each_side_to_its_endpoint() {
+        let state = setup_state();
+        let id = new_session_with_mode(
+            &state,
+            GameMode::VsAi {
+                ai_player: Player::P2,
+            },
+        )
+        .await;
+        let ai_move = || {
+            let query = AiMoveQuery {
+                depth: Some(1),
+                ..Default::default()
+            };
+            make_ai_move(State(state.clone()), Path(id), Query(query), None)
+        };
+        let human_move = |from: (usize, usize), to: (usize, usize)| {
+            let payload = MoveRequest {
+                from: Position {
+                    row: from.0,
+                    col: from.1,
+                },
+                to: Position {
+                    row: to.0,
+                    col: to.1,
+                },
+            };
+            make_move(State(state.clone()), Path(id), MoveBody(payload))
+        };
+
+        // The AI can't take the human's turn
+        let (status, message) = ai_move().await.unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(message, "It's not the AI's turn.");
+
+        human_move((0, 3), (0, 2)).await.unwrap();
+
+        // Nor can the human move the AI's pieces
+        let (status, Json(error)) = human_move((6, 3), (6, 4)).await.unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error.code, "ai_turn");
+
+        let _ = ai_move().await.unwrap();
+        let game = state.games()[&id].clone();
+        assert_eq!(game.ply, 2);
+        assert_eq!(game.current_player, Player::P1);
+    }
+
+    #[tokio::test]
+    async fn test_ai_move_with_time_budget() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+
+        let query = AiMoveQuery {
+            ms: Some(20),
+            stats: true,
+            ..Default::default()
+        };
+        let Json(played) = make_ai_move(State(state.clone()), Path(id), Query(query), None)
+            .await
+            .unwrap();
+        assert!(played.stats.is_some_and(|stats| stats.nodes > 0));
+
+        let games = state.games();
+        assert_eq!(games[&id].current_player, Player::P2);
+        assert_eq!(
+            games[&id].board[played.to.row][played.to.col],
+            Some(Player::P1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_game_from_code() {
+        let state = setup_state();
+        let original = new_session(&state).await;
+        {
+            let mut games = state.games();
+            let game = games.get_mut(&original).unwrap();
+            game.make_move(Position { row: 0, col: 3 }, Position { row: 0, col: 2 })
+                .unwrap();
+        }
+
+        let Json(CodeResponse { code }) = get_code(State(state.clone()), Path(original))
+            .await
+            .unwrap();
+        let query = CreateGameQuery { code: Some(code) };
+        let Json(copy) = create_game(State(state.clone()), Query(query), None)
+            .await
+            .unwrap();
+
+        {
+            let games = state.games();
+            assert_eq!(games[&copy.id].board, games[&original].board);
+            assert_eq!(games[&copy.id].current_player, Player::P2);
+        }
+
+        let query = CreateGameQuery {
+            code: Some("nonsense".to_string()),
+        };
+        let (status, _) = create_game(State(state.clone()), Query(query), None)
+            .await
+            .unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_create_game_from_code_with_config() {
+        let state = setup_state();
+        let square = |row, col| Position { row, col };
+        // Player 1's pieces, with one on (5,5), and Player 2 to move
+        let mut board = vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE];
+        board[5][5] = Some(Player::P1);
+        board[4][4] = Some(Player::P1);
+        board[1][1] = Some(Player::P2);
+        board[2][2] = Some(Player::P2);
+        let code = Game::from_setup(board, Player::P2).unwrap().to_code();
+        let create = |config: GameConfig| {
+            let query = CreateGameQuery {
+                code: Some(code.clone()),
+            };
+            let request = CreateGameRequest { size: None, config };
+            create_game(State(state.clone()), Query(query), Some(Json(request)))
+        };
+
+        let Json(created) = create(GameConfig {
+            clocks: Some(game::Clocks {
+                p1_ms: 60_000,
+                p2_ms: 30_000,
+            }),
+            p1_start: Some(vec![square(0, 3), square(0, 2)]),
+            ..GameConfig::default()
+        })
+        .await
+        .unwrap();
+        let game = state.games()[&created.id].clone();
+        assert_eq!(game.to_code(), code);
+        assert_eq!(game.clock.unwrap().remaining.p2_ms, 30_000);
+        assert_eq!(game.config.first_player, Player::P2);
+        assert_eq!(game.config.p1_start, Some(vec![square(0, 2), square(0, 3)]));
+        assert_eq!(game.status, GameStatus::Ongoing);
+
+        // With Player 2's base moved under Player 1's piece, the game is already won
+        let Json(created) = create(GameConfig {
+            p2_goal: Some(square(5, 5)),
+            ..GameConfig::default()
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            state.games()[&created.id].status,
+            GameStatus::Won(Player::P1, WinReason::ReachedGoal)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_neighbors_at_start() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+
+        for (row, col, expected) in [(0, 3, 1), (3, 0, 1), (2, 1, 2)] {
+            let Json(neighbors) =
+                get_neighbors(State(state.clone()), Path(id), Query(Position { row, col }))
+                    .await
+                    .unwrap();
+            assert_eq!(neighbors.count, expected, "({},{})", row, col);
+        }
+
+        let (status, _) = get_neighbors(
+            State(state.clone()),
+            Path(id),
+            Query(Position { row: 7, col: 0 }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_piece_describes_a_square() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+        let piece =
+            |row, col| get_piece(State(state.clone()), Path(id), Query(Position { row, col }));
+
+        let Json(response) = piece(2, 1).await.unwrap();
+        assert_eq!(
+            response,
+            PieceResponse {
+                occupant: Some(Player::P1),
+                neighbors: 2,
+                valid_moves: vec![
+                    Position { row: 0, col: 1 },
+                    Position { row: 2, col: 3 },
+                    Position { row: 4, col: 1 },
+                    Position { row: 4, col: 3 },
+                ],
+                is_goal_for: Vec::new(),
+            }
+        );
+
+        // Player 1 wins by reaching Player 2's base in the far corner
+        let Json(response) = piece(6, 6).await.unwrap();
+        assert_eq!(response.occupant, None);
+        assert!(response.valid_moves.is_empty());
+        assert_eq!(response.is_goal_for, vec![Player::P1]);
+
+        let (status, _) = piece(7, 0).await.unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_all_moves_empty_when_game_over() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+
+        let Json(moves) = get_all_moves(State(state.clone()), Path(id)).await.unwrap();
+        assert_eq!(moves.len(), 16);
+
+        state.games().get_mut(&id).unwrap().status = GameStatus::Draw;
+        let Json(moves) = get_all_moves(State(state.clone()), Path(id)).await.unwrap();
+        assert!(moves.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_movable_lists_pieces_of_player_to_move() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+
+        let Json(pieces) = get_movable_pieces(State(state.clone()), Path(id))
+            .await
+            .unwrap();
+        assert_eq!(pieces.len(), 4);
+        let game = state.games()[&id].clone();
+        assert!(pieces
+            .iter()
+            .all(|pos| game.board[pos.row][pos.col] == Some(Player::P1)));
+
+        state.games().get_mut(&id).unwrap().status = GameStatus::Draw;
+        let Json(pieces) = get_movable_pieces(State(state), Path(id)).await.unwrap();
+        assert!(pieces.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_each_difficulty_finds_a_legal_opening_move() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+        let game = state.games()[&id].clone();
+
+        for difficulty in ai::Difficulty::ALL {
+            let query = AiMoveQuery {
+                difficulty,
+                stats: true,
+                ..Default::default()
+            };
+            let Json(hint) = get_hint(State(state.clone()), Path(id), Query(query), None)
+                .await
+                .unwrap();
+            assert!(
+                game.get_valid_moves_for_piece(hint.from).contains(&hint.to),
+                "{:?}",
+                difficulty
+            );
+            let stats = hint.stats.unwrap();
+            assert!(stats.depth <= difficulty.settings().depth);
+        }
+
+        let Json(config) = get_default_config().await;
+        assert_eq!(config.difficulties[&ai::Difficulty::Easy].depth, 2);
+    }
+
+    #[tokio::test]
+    async fn test_legal_checks_move_without_playing_it() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+        let legal = |fr, fc, tr, tc| {
+            check_legal(
+                State(state.clone()),
+                Path(id),
+                Query(LegalQuery { fr, fc, tr, tc }),
+            )
+        };
+
+        let Json(response) = legal(0, 3, 0, 2).await.unwrap();
+        assert!(response.legal);
+        assert_eq!(response.reason, None);
+
+        // Not Player 1's piece
+        let Json(response) = legal(6, 3, 6, 2).await.unwrap();
+        assert!(!response.legal);
+        assert_eq!(
+            response.reason.as_deref(),
+            Some("Invalid starting square or that's not your piece.")
+        );
+
+        // Too far for a piece with one neighbor
+        let Json(response) = legal(0, 3, 0, 1).await.unwrap();
+        assert!(!response.legal);
+        assert!(response.reason.is_some());
+
+        assert_eq!(state.games()[&id], Game::new());
+    }
+
+    #[tokio::test]
+    async fn test_analyze_returns_top_moves_best_first() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+        let all_moves = Game::new().all_valid_moves(Player::P1).len();
+
+        let analyze_with = |top| {
+            analyze(
+                State(state.clone()),
+                Path(id),
+                Query(AnalyzeQuery {
+                    depth: Some(2),
+                    top,
+                }),
+            )
+        };
+        let Json(every) = analyze_with(None).await.unwrap();
+        assert_eq!(every.len(), all_moves);
+        assert!(every.windows(2).all(|pair| pair[0].score >= pair[1].score));
+
+        let Json(top) = analyze_with(Some(3)).await.unwrap();
+        assert_eq!(top, every[..3]);
+        assert_eq!(state.games()[&id], Game::new());
+    }
+
+    #[tokio::test]
+    async fn test_threats_show_the_chosen_players_reach() {
+        use tower::ServiceExt;
+
+        let state = setup_state();
+        let id = new_session(&state).await;
+        let app = build_router(state);
+        let threats = |query: &str| {
+            let request = Request::get(format!("/threats/{}{}", id, query))
+                .body(axum::body::Body::empty())
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                serde_json::from_slice::<Vec<Position>>(&body).unwrap()
+            }
+        };
+
+        let game = Game::new();
+        for (query, player) in [("", Player::P2), ("?player=P1", Player::P1)] {
+            let mut expected: Vec<Position> = game.reachable_squares(player).into_iter().collect();
+            expected.sort_by_key(|pos| (pos.row, pos.col));
+            assert_eq!(threats(query).await, expected, "{}", query);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_heatmap_matches_the_evaluation() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+        let game = {
+            let mut games = state.games();
+            let game = games.get_mut(&id).unwrap();
+            game.set_board(vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE]);
+            game.set_square(Position { row: 2, col: 1 }, Some(Player::P1));
+            game.set_square(Position { row: 2, col: 3 }, Some(Player::P2));
+            game.clone()
+        };
+
+        for metric in [ai::DistanceMetric::Manhattan, ai::DistanceMetric::Chebyshev] {
+            let query = HeatmapQuery { distance: metric };
+            let Json(pieces) = get_heatmap(State(state.clone()), Path(id), Query(query))
+                .await
+                .unwrap();
+            let expected = |row, col, owner, distance| PieceDistance {
+                row,
+                col,
+                owner,
+                distance,
+            };
+            let (p1_distance, p2_distance) = match metric {
+                // Player 1 heads for (6,6), Player 2 for (0,0)
+                ai::DistanceMetric::Manhattan => (9, 5),
+                ai::DistanceMetric::Chebyshev => (5, 3),
+            };
+            assert_eq!(
+                pieces,
+                vec![
+                    expected(2, 1, Player::P1, p1_distance),
+                    expected(2, 3, Player::P2, p2_distance),
+                ]
+            );
+
+            // With only the distance term, the evaluation is the difference
+            let weights = ai::EvalWeights {
+                goal_distance: 1,
+                mobility: 0,
+                threat: 0,
+                blocked: 0,
+            };
+            assert_eq!(
+                ai::evaluate(&game, Player::P1, metric, weights),
+                p2_distance - p1_distance
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate_config_lists_every_problem() {
+        let square = |row, col| Position { row, col };
+        let check = |size: Option<usize>, config: GameConfig| async move {
+            // Whatever is wrong first is also why POST /games would refuse the setup
+            let created = Game::new_with_config(size.unwrap_or(DEFAULT_BOARD_SIZE), config.clone());
+            let Json(result) = validate_config(Json(CreateGameRequest { size, config })).await;
+            assert_eq!(result.valid, result.errors.is_empty());
+            assert_eq!(created.err().as_ref(), result.errors.first());
+            result.errors
+        };
+
+        assert!(check(None, GameConfig::default()).await.is_empty());
+        assert!(check(Some(MAX_BOARD_SIZE), GameConfig::default())
+            .await
+            .is_empty());
+
+        assert_eq!(
+            check(Some(MAX_BOARD_SIZE + 1), GameConfig::default()).await,
+            [format!(
+                "Board size must be between {} and {}, got {}.",
+                MIN_BOARD_SIZE,
+                MAX_BOARD_SIZE,
+                MAX_BOARD_SIZE + 1
+            )]
+        );
+
+        let overlapping = GameConfig {
+            p2_start: Some(vec![square(0, 3), square(6, 3)]),
+            ..GameConfig::default()
+        };
+        assert_eq!(
+            check(None, overlapping).await,
+            ["Starting square (0,3) is used twice."]
+        );
+
+        let goal_off_board = GameConfig {
+            p1_goal: Some(square(7, 0)),
+            ..GameConfig::default()
+        };
+        assert_eq!(
+            check(None, goal_off_board).await,
+            ["Goals must be on the board."]
+        );
+
+        let start_on_goal = GameConfig {
+            p1_start: Some(vec![square(0, 3), square(6, 6)]),
+            ..GameConfig::default()
+        };
+        assert_eq!(
+            check(None, start_on_goal).await,
+            ["Starting square (6,6) is a goal."]
+        );
+
+        // Several problems at once are all reported
+        let everything = GameConfig {
+            p1_goal: Some(square(9, 9)),
+            p2_start: Some(vec![square(0, 3), square(6, 6), square(8, 0)]),
+            ..GameConfig::default()
+        };
+        assert_eq!(
+            check(None, everything).await,
+            [
+                "Goals must be on the board.",
+                "Starting square (0,3) is used twice.",
+                "Starting square (6,6) is a goal.",
+                "Starting square (8,0) is off the board.",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_can_move_reports_a_stuck_player() {
+        use tower::ServiceExt;
+
+        let state = setup_state();
+        let opening = new_session(&state).await;
+
+        // Player 2's only piece sits in a corner, hemmed in by three pieces: it must
+        // move three squares, and every path is blocked
+        let mut board = vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE];
+        board[0][6] = Some(Player::P2);
+        board[0][5] = Some(Player::P1);
+        board[1][5] = Some(Player::P1);
+        board[1][6] = Some(Player::P1);
+        let stuck = Uuid::new_v4();
+        state
+            .games()
+            .insert(stuck, Game::from_setup(board, Player::P1).unwrap());
+
+        let app = build_router(state);
+        let get = |id: Uuid, player: &str| {
+            let request = Request::get(format!("/can-move/{}?player={}", id, player))
+                .body(axum::body::Body::empty())
+                .unwrap();
+            app.clone().oneshot(request)
+        };
+        let can_move = |id, player| async move {
+            let response = get(id, player).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice::<CanMoveResponse>(&body)
+                .unwrap()
+                .can_move
+        };
+
+        assert!(can_move(opening, "P2").await);
+        assert!(!can_move(stuck, "P2").await);
+        assert!(can_move(stuck, "P1").await);
+
+        let response = get(opening, "P3").await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_canonical_key_matches_for_mirror_images() {
+        let mut board = vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE];
+        board[1][3] = Some(Player::P1);
+        board[2][3] = Some(Player::P1);
+        board[5][2] = Some(Player::P2);
+        let mut mirrored = vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE];
+        mirrored[3][1] = Some(Player::P1);
+        mirrored[3][2] = Some(Player::P1);
+        mirrored[2][5] = Some(Player::P2);
+
+        let key = |board| async {
+            let request = CanonicalRequest {
+                board,
+                current_player: Player::P1,
+            };
+            canonical_key(Json(request)).await.unwrap().0.key
+        };
+        assert_eq!(key(board).await, key(mirrored).await);
+
+        let request = CanonicalRequest {
+            board: vec![vec![None; 3]; 3],
+            current_player: Player::P1,
+        };
+        let (status, _) = canonical_key(Json(request)).await.unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_scores_near_win_without_a_session() {
+        let state = setup_state();
+
+        // Player 1's piece on (5,5) can step onto the goal at (6,6)
+        let mut board = vec![vec![None; DEFAULT_BOARD_SIZE]; DEFAULT_BOARD_SIZE];
+        board[5][5] = Some(Player::P1);
+        board[4][4] = Some(Player::P1);
+        board[0][5] = Some(Player::P2);
+        board[1][6] = Some(Player::P2);
+        let game = Game::from_setup(board, Player::P1).unwrap();
+
+        let Json(response) = evaluate_position(Query(EvaluateQuery::default()), Json(game.clone()))
+            .await
+            .unwrap();
+        assert!(response.score > 100, "score {}", response.score);
+        assert_eq!(response.best_move, None);
+
+        let Json(response) = evaluate_position(Query(EvaluateQuery { depth: Some(1) }), Json(game))
+            .await
+            .unwrap();
+        assert_eq!(response.score, 1000);
+        assert_eq!(
+            response.best_move,
+            Some(MoveRequest {
+                from: Position { row: 5, col: 5 },
+                to: Position { row: 6, col: 6 },
+            })
+        );
+        assert!(state.games().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_hint_does_not_change_the_game() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+        let before = state.games()[&id].clone();
+
+        let query = AiMoveQuery {
+            depth: Some(2),
+            ..Default::default()
+        };
+        let Json(hint) = get_hint(State(state.clone()), Path(id), Query(query), None)
+            .await
+            .unwrap();
+
+        let after = state.games()[&id].clone();
+        assert_eq!(after, before);
+        assert!(before
+            .get_valid_moves_for_piece(hint.from)
+            .contains(&hint.to));
+    }
+
+    #[tokio::test]
+    async fn test_ai_refuses_games_of_more_than_two_players() {
+        let state = setup_state();
+        let request = CreateGameRequest {
+            size: Some(8),
+            config: GameConfig {
+                players: 3,
+                ..GameConfig::default()
+            },
+        };
+        let Json(created) = create_game(
+            State(state.clone()),
+            Query(CreateGameQuery::default()),
+            Some(Json(request)),
+        )
+        .await
+        .unwrap();
+
+        let (status, message) = get_hint(
+            State(state.clone()),
+            Path(created.id),
+            Query(AiMoveQuery::default()),
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(message, "The AI only plays two-player games.");
+    }
+
+    #[tokio::test]
+    async fn test_hint_rejected_when_game_over() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+        state.games().get_mut(&id).unwrap().status = GameStatus::Draw;
+
+        let (status, _) = get_hint(
+            State(state.clone()),
+            Path(id),
+            Query(AiMoveQuery::default()),
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_create_game_with_size() {
+        let state = setup_state();
+        let body = CreateGameRequest {
+            size: Some(9),
+            ..Default::default()
+        };
+        let Json(created) = create_game(
+            State(state.clone()),
+            Query(CreateGameQuery::default()),
+            Some(Json(body)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(state.games()[&created.id].size, 9);
+
+        let body = CreateGameRequest {
+            size: Some(2),
+            ..Default::default()
+        };
+        let (status, _) = create_game(
+            State(state.clone()),
+            Query(CreateGameQuery::default()),
+            Some(Json(body)),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_board_since_lists_changed_squares() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+        for notation in ["d1-c1", "d7-e7"] {
+            let payload = MoveRequest::from_notation(notation).unwrap();
+            make_move(State(state.clone()), Path(id), MoveBody(payload))
+                .await
+                .unwrap();
+        }
+        let since =
+            |ply| get_board_since(State(state.clone()), Path(id), Query(SinceQuery { ply }));
+
+        let Json(diff) = since(0).await.unwrap();
+        assert_eq!(diff.ply, 2);
+        let square = |row, col, occupant| SquareChange { row, col, occupant };
+        assert_eq!(
+            diff.changes,
+            vec![
+                square(0, 2, Some(Player::P1)),
+                square(0, 3, None),
+                square(6, 3, None),
+                square(6, 4, Some(Player::P2)),
+            ]
+        );
+
+        let Json(diff) = since(2).await.unwrap();
+        assert!(diff.changes.is_empty());
+        let (status, _) = since(3).await.unwrap_err();
+        assert_eq!(status, StatusCode::CONFLICT);
+    }
+
+    // Records the fields of every span opened and how often spans are entered.
+    #[derive(Clone, Default)]
+    struct SpanRecorder {
+        spans: Arc<Mutex<Vec<HashMap<String, String>>>>,
+        entered: Arc<Mutex<usize>>,
+    }
+
+    struct FieldVisitor<'a>(&'a mut HashMap<String, String>);
+
+    impl tracing::field::Visit for FieldVisitor<'_> {
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    impl<S> tracing_subscriber::Layer<S> for SpanRecorder
+    where
+        S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+    {
+        fn on_new_span(
+            &self,
+            attrs: &tracing::span::Attributes<'_>,
+            id: &tracing::span::Id,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            let mut fields =
+                HashMap::from([("name".to_string(), attrs.metadata().name().to_string())]);
+            attrs.record(&mut FieldVisitor(&mut fields));
+            fields.insert("span".to_string(), id.into_u64().to_string());
+            self.spans.lock().unwrap().push(fields);
+        }
+
+        fn on_record(
+            &self,
+            id: &tracing::span::Id,
+            values: &tracing::span::Record<'_>,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            let mut spans = self.spans.lock().unwrap();
+            let span = id.into_u64().to_string();
+            if let Some(fields) = spans.iter_mut().find(|fields| fields["span"] == span) {
+                values.record(&mut FieldVisitor(fields));
+            }
+        }
+
+        fn on_enter(
+            &self,
+            _id: &tracing::span::Id,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            *self.entered.lock().unwrap() += 1;
+        }
+    }
+
+    // Collects log output in memory.
+    #[derive(Clone, Default)]
+    struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for LogBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for LogBuffer {
+        type Writer = LogBuffer;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn test_json_logs_are_one_object_per_line() {
+        let args = Args::try_parse_from(["top-cap", "--log-format", "json"]).unwrap();
+        assert_eq!(args.log_format, LogFormat::Json);
+        assert_eq!(
+            Args::try_parse_from(["top-cap"]).unwrap().log_format,
+            LogFormat::Text
+        );
+
+        let buffer = LogBuffer::default();
+        let subscriber = tracing_subscriber::registry()
+            .with(log_layer(args.log_format, buffer.clone()))
+            .with(EnvFilter::new(DEFAULT_LOG_FILTER));
+        tracing::subscriber::with_default(subscriber, || {
+            info!(game = 7, "Game created.");
+            tracing::debug!("Filtered out at the default level.");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 1, "{}", output);
+        let event: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert!(event.is_object());
+        assert_eq!(event["level"], "INFO");
+        assert_eq!(event["fields"]["message"], "Game created.");
+        assert_eq!(event["fields"]["game"], 7);
+    }
+
+    #[tokio::test]
+    async fn test_requests_run_in_a_span_with_their_id() {
+        use tower::ServiceExt;
+
+        let recorder = SpanRecorder::default();
+        let _guard = tracing_subscriber::registry()
+            .with(recorder.clone())
+            .set_default();
+
+        let state = setup_state();
+        let id = new_session(&state).await;
+        let request = Request::get(format!("/board/{}", id))
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = build_router(state).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let request_id = response.headers()[REQUEST_ID_HEADER].to_str().unwrap();
+
+        let spans = recorder.spans.lock().unwrap();
+        let span = spans
+            .iter()
+            .find(|fields| fields["name"] == "request")
+            .expect("a request span");
+        assert_eq!(span["id"], request_id);
+        assert_eq!(span["method"], "GET");
+        assert_eq!(span["route"], "/board/{game_id}");
+        assert_eq!(span["game_id"], id.to_string());
+        assert!(*recorder.entered.lock().unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_events_stream_sends_the_game_after_a_move() {
+        use tower::ServiceExt;
+
+        let state = setup_state();
+        let id = new_session(&state).await;
+        let other = new_session(&state).await;
+        let request = Request::get(format!("/events/{}", id))
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = build_router(state.clone()).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/event-stream"
+        );
+        let mut body = response.into_body().into_data_stream();
+
+        // Reads the next `game` event, skipping keep-alive comments
+        async fn next_game(body: &mut axum::body::BodyDataStream) -> Game {
+            loop {
+                let chunk = tokio::time::timeout(Duration::from_secs(5), body.next())
+                    .await
+                    .expect("an event in time")
+                    .unwrap()
+                    .unwrap();
+                let text = String::from_utf8(chunk.to_vec()).unwrap();
+                if let Some(data) = text.lines().find_map(|line| line.strip_prefix("data: ")) {
+                    assert!(text.starts_with("event: game"), "{}", text);
+                    return serde_json::from_str(data).unwrap();
+                }
+            }
+        }
+
+        assert_eq!(next_game(&mut body).await, Game::new());
+
+        // A move in another session isn't sent; the one in this session is
+        for session in [other, id] {
+            let payload = MoveRequest::from_notation("d1-c1").unwrap();
+            make_move(State(state.clone()), Path(session), MoveBody(payload))
+                .await
+                .unwrap();
+        }
+        let game = next_game(&mut body).await;
+        assert_eq!(game.ply, 1);
+        assert_eq!(game, state.games()[&id]);
+    }
+
+    #[tokio::test]
+    async fn test_move_batch_stops_at_the_first_illegal_move() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+
+        let mut expected = Game::new();
+        let mut moves = Vec::new();
+        for _ in 0..3 {
+            let mv = expected.all_valid_moves(expected.current_player)[0];
+            expected.make_move(mv.from, mv.to).unwrap();
+            moves.push(mv);
+        }
+        // An empty square, and then a move that would have been legal
+        moves.push(MoveRequest::from_notation("d4-d5").unwrap());
+        moves.push(expected.all_valid_moves(expected.current_player)[0]);
+
+        let Json(response) = make_moves(
+            State(state.clone()),
+            Path(id),
+            Json(MoveBatchRequest { moves }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.applied, 3);
+        assert_eq!(response.final_board.board, expected.board);
+        assert_eq!(response.final_board.ply, 3);
+        assert_eq!(response.error.unwrap().code, "wrong_piece");
+
+        // The legal moves stay played
+        let Json(game) = get_board(State(state.clone()), Path(id)).await.unwrap();
+        assert_eq!(game.ply, 3);
+
+        // A batch that is all legal reports no error
+        let mv = expected.all_valid_moves(expected.current_player)[0];
+        let Json(response) = make_moves(
+            State(state.clone()),
+            Path(id),
+            Json(MoveBatchRequest { moves: vec![mv] }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.applied, 1);
+        assert!(response.error.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_surrender_ends_the_game() {
+        let state = setup_state();
+        let id = new_session(&state).await;
+        let resign = |player| {
+            surrender(
+                State(state.clone()),
+                Path(id),
+                Json(SurrenderRequest { player }),
+            )
+        };
+
+        let (status, _) = resign(Player::P2).await.unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let Json(game) = resign(Player::P1).await.unwrap();
+        assert_eq!(
+            game.status,
+            GameStatus::Won(Player::P2, WinReason::Resignation)
+        );
+        let Json(stats) = get_stats(State(state.clone())).await;
+        assert_eq!(stats.p2_wins, 1);
+
+        // The game is over: no more moves, and no second surrender
+        let payload = MoveRequest::from_notation("d1-c1").unwrap();
+        let (_, Json(error)) = make_move(State(state.clone()), Path(id), MoveBody(payload))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, "game_over");
+        assert!(resign(Player::P1).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_player_two_can_open() {
+        let state = setup_state();
+        let request = CreateGameRequest {
+            size: None,
+            config: serde_json::from_str(r#"{ "first_player": "P2" }"#).unwrap(),
+        };
+        let Json(created) = create_game(
+            State(state.clone()),
+            Query(CreateGameQuery::default()),
+            Some(Json(request)),
+        )
+        .await
+        .unwrap();
+        let id = created.id;
+        assert_eq!(state.games()[&id].current_player, Player::P2);
+
+        let play = |notation| {
+            let payload = MoveRequest::from_notation(notation).unwrap();
+            make_move(State(state.clone()), Path(id), MoveBody(payload))
+        };
+        let (_, Json(error)) = play("d1-c1").await.unwrap_err();
+        assert_eq!(error.code, "wrong_piece");
+        play("d7-e7").await.unwrap();
+        play("d1-c1").await.unwrap();
+
+        // Starting over keeps Player 2 first
+        reset_game(State(state.clone()), Path(id)).await;
+        assert_eq!(state.games()[&id].current_player, Player::P2);
+    }
+
+    #[tokio::test]
+    async fn test_reset_keeps_size_and_rules() {
+        let state = setup_state();
+        let request = CreateGameRequest {
+            size: Some(9),
+            config: GameConfig {
+                stalemate_rule: StalemateRule::Draw,
+                ..GameConfig::default()
+            },
+        };
+        let Json(created) = create_game(
+            State(state.clone()),
+            Query(CreateGameQuery::default()),
+            Some(Json(request)),
+        )
+        .await
+        .unwrap();
+        let id = created.id;
+
+        let payload = MoveRequest {
+            from: Position { row: 0, col: 3 },
+            to: Position { row: 0, col: 2 },
+        };
+        make_move(State(state.clone()), Path(id), MoveBody(payload))
+            .await
+            .unwrap();
+        let (status, _) = reset_game(State(state.clone()), Path(id)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let games = state.games();
+        assert_eq!(games[&id].size, 9);
+        assert_eq!(games[&id].ply, 0);
+        assert_eq!(games[&id].config.stalemate_rule, StalemateRule::Draw);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_session_is_not_found() {
+        let state = setup_state();
+        let result = get_board(State(state.clone()), Path(Uuid::new_v4())).await;
+        assert_eq!(result.unwrap_err().0, StatusCode::NOT_FOUND);
+
+        let (status, _) = reset_game(State(state), Path(Uuid::new_v4())).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
 }
@@ -0,0 +1,612 @@
+use serde_json::{json, Value};
+
+/// The OpenAPI 3 description of the main endpoints, served at GET /openapi.json.
+/// Written by hand; the tests check its schemas against what the types serialize to.
+pub fn document() -> Value {
+    let game_id = json!({
+        "name": "game_id",
+        "in": "path",
+        "required": true,
+        "description": "Session id returned by POST /games.",
+        "schema": { "type": "string", "format": "uuid" }
+    });
+    let text = |description: &str| {
+        json!({
+            "description": description,
+            "content": { "text/plain": { "schema": { "type": "string" } } }
+        })
+    };
+    let not_found = text("No game with that id.");
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Top-Cap",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Play Top-Cap against a friend or the AI. Every game is a session created with POST /games."
+        },
+        "paths": {
+            "/games": {
+                "post": {
+                    "summary": "Start a new game session.",
+                    "parameters": [{
+                        "name": "code",
+                        "in": "query",
+                        "required": false,
+                        "description": "Start from a position code returned by GET /code.",
+                        "schema": { "type": "string" }
+                    }],
+                    "requestBody": {
+                        "required": false,
+                        "content": { "application/json": { "schema": {
+                            "type": "object",
+                            "properties": {
+                                "size": { "type": "integer", "minimum": 0 },
+                                "config": { "$ref": "#/components/schemas/GameConfig" }
+                            }
+                        } } }
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "The new session.",
+                            "content": { "application/json": { "schema": {
+                                "type": "object",
+                                "required": ["id"],
+                                "properties": { "id": { "type": "string", "format": "uuid" } }
+                            } } }
+                        },
+                        "400": text("Invalid size, configuration or code.")
+                    }
+                }
+            },
+            "/board/{game_id}": {
+                "get": {
+                    "summary": "The full state of a game.",
+                    "parameters": [game_id],
+                    "responses": {
+                        "200": {
+                            "description": "The game.",
+                            "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Game" } } }
+                        },
+                        "404": not_found
+                    }
+                }
+            },
+            "/move/{game_id}": {
+                "post": {
+                    "summary": "Play a move for the player to move.",
+                    "parameters": [game_id],
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": { "$ref": "#/components/schemas/MoveRequest" } } }
+                    },
+                    "responses": {
+                        "200": text("The move was played."),
+                        "400": {
+                            "description": "The move is illegal, the body malformed or the game over.",
+                            "content": { "application/json": { "schema": { "$ref": "#/components/schemas/ErrorResponse" } } }
+                        },
+                        "404": {
+                            "description": "No game with that id.",
+                            "content": { "application/json": { "schema": { "$ref": "#/components/schemas/ErrorResponse" } } }
+                        },
+                        "429": text("Too many requests for this session; see Retry-After.")
+                    }
+                }
+            },
+            "/ai-move/{game_id}": {
+                "post": {
+                    "summary": "Let the AI play a move for the player to move.",
+                    "parameters": [
+                        game_id,
+                        {
+                            "name": "difficulty",
+                            "in": "query",
+                            "required": false,
+                            "schema": { "type": "string", "enum": ["easy", "medium", "hard"] }
+                        },
+                        {
+                            "name": "depth",
+                            "in": "query",
+                            "required": false,
+                            "description": "Search exactly this many plies ahead.",
+                            "schema": { "type": "integer", "minimum": 1, "maximum": crate::ai::MAX_SEARCH_DEPTH }
+                        },
+                        {
+                            "name": "ms",
+                            "in": "query",
+                            "required": false,
+                            "description": "Thinking time in milliseconds.",
+                            "schema": { "type": "integer", "minimum": 1, "maximum": crate::ai::MAX_THINK_TIME_MS }
+                        },
+                        {
+                            "name": "stats",
+                            "in": "query",
+                            "required": false,
+                            "schema": { "type": "boolean" }
+                        },
+                        {
+                            "name": "distance",
+                            "in": "query",
+                            "required": false,
+                            "description": "How the evaluation measures a piece's distance to its goal.",
+                            "schema": { "type": "string", "enum": ["manhattan", "chebyshev"], "default": "manhattan" }
+                        },
+                        {
+                            "name": "seed",
+                            "in": "query",
+                            "required": false,
+                            "description": "Breaks ties between equally good moves reproducibly.",
+                            "schema": { "type": "integer", "minimum": 0 }
+                        },
+                        {
+                            "name": "apply",
+                            "in": "query",
+                            "required": false,
+                            "description": "Set to false to get the move without playing it.",
+                            "schema": { "type": "boolean", "default": true }
+                        },
+                        {
+                            "name": "pv",
+                            "in": "query",
+                            "required": false,
+                            "description": "Also return the line of play the AI expects.",
+                            "schema": { "type": "boolean" }
+                        }
+                    ],
+                    "requestBody": {
+                        "required": false,
+                        "content": { "application/json": { "schema": {
+                            "type": "object",
+                            "properties": { "weights": { "$ref": "#/components/schemas/EvalWeights" } }
+                        } } }
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "The move the AI played, or would play with apply=false.",
+                            "content": { "application/json": { "schema": { "$ref": "#/components/schemas/AiMoveResponse" } } }
+                        },
+                        "400": text("Invalid parameters, the game is over, or the game's mode doesn't let the AI move now."),
+                        "404": not_found,
+                        "409": text("The game changed while the AI was thinking."),
+                        "429": text("Too many requests for this session; see Retry-After.")
+                    }
+                }
+            },
+            "/reset/{game_id}": {
+                "post": {
+                    "summary": "Start the game over with the same size and rules.",
+                    "parameters": [game_id],
+                    "responses": {
+                        "200": text("The game was reset."),
+                        "404": not_found
+                    }
+                }
+            },
+            "/config": {
+                "get": {
+                    "summary": "Layout of a standard game and the AI difficulty presets.",
+                    "responses": {
+                        "200": {
+                            "description": "The configuration.",
+                            "content": { "application/json": { "schema": { "$ref": "#/components/schemas/ConfigResponse" } } }
+                        }
+                    }
+                }
+            },
+            "/config/{game_id}": {
+                "get": {
+                    "summary": "Layout of a particular game and the AI difficulty presets.",
+                    "parameters": [game_id],
+                    "responses": {
+                        "200": {
+                            "description": "The configuration.",
+                            "content": { "application/json": { "schema": { "$ref": "#/components/schemas/ConfigResponse" } } }
+                        },
+                        "404": not_found
+                    }
+                }
+            }
+        },
+        "components": { "schemas": schemas() }
+    })
+}
+
+/// Schemas of the types the documented endpoints exchange.
+fn schemas() -> Value {
+    let position_list =
+        json!({ "type": "array", "items": { "$ref": "#/components/schemas/Position" } });
+    let nullable_position =
+        json!({ "allOf": [{ "$ref": "#/components/schemas/Position" }], "nullable": true });
+    let nullable_position_list = json!({
+        "type": "array",
+        "items": { "$ref": "#/components/schemas/Position" },
+        "nullable": true
+    });
+
+    json!({
+        "Player": {
+            "type": "string",
+            "enum": ["P1", "P2", "P3", "P4"],
+            "description": "Also accepted as red/blue/green/yellow or 1 to 4, in any case. P3 and P4 only play in games configured for three or four players."
+        },
+        "Position": {
+            "type": "object",
+            "required": ["row", "col"],
+            "properties": {
+                "row": { "type": "integer", "minimum": 0, "description": "Counted from 0 at the top." },
+                "col": { "type": "integer", "minimum": 0, "description": "Counted from 0 on the left." }
+            }
+        },
+        "MoveRequest": {
+            "oneOf": [
+                {
+                    "type": "object",
+                    "required": ["from", "to"],
+                    "properties": {
+                        "from": { "$ref": "#/components/schemas/Position" },
+                        "to": { "$ref": "#/components/schemas/Position" }
+                    }
+                },
+                {
+                    "type": "object",
+                    "required": ["notation"],
+                    "properties": {
+                        "notation": { "type": "string", "example": "d1-c1" }
+                    }
+                }
+            ]
+        },
+        "GameStatus": {
+            "oneOf": [
+                { "type": "string", "enum": ["Ongoing", "Draw"] },
+                {
+                    "type": "object",
+                    "required": ["Won"],
+                    "properties": {
+                        "Won": {
+                            "type": "array",
+                            "description": "The winner, then how they won: ReachedGoal, NoMovesLeft, TimeForfeit or Resignation.",
+                            "minItems": 2,
+                            "maxItems": 2,
+                            "items": { "type": "string" }
+                        }
+                    }
+                }
+            ]
+        },
+        "MoveRecord": {
+            "type": "object",
+            "required": ["from", "to", "player", "previous_status"],
+            "properties": {
+                "from": { "$ref": "#/components/schemas/Position" },
+                "to": { "$ref": "#/components/schemas/Position" },
+                "player": { "$ref": "#/components/schemas/Player" },
+                "previous_status": { "$ref": "#/components/schemas/GameStatus" }
+            }
+        },
+        "GameConfig": {
+            "type": "object",
+            "properties": {
+                "players": {
+                    "type": "integer",
+                    "minimum": 2,
+                    "maximum": 4,
+                    "description": "How many sides play, taking turns P1, P2, P3, P4. Games of more than two can't be timed or played by the AI."
+                },
+                "stalemate_rule": { "type": "string", "enum": ["OpponentWins", "Draw"] },
+                "ply_limit": { "type": "integer", "minimum": 0 },
+                "p1_start": nullable_position_list,
+                "p2_start": nullable_position_list,
+                "p1_goal": nullable_position,
+                "p2_goal": nullable_position,
+                "p3_start": nullable_position_list,
+                "p4_start": nullable_position_list,
+                "p3_goal": nullable_position,
+                "p4_goal": nullable_position,
+                "allow_single_step_when_isolated": { "type": "boolean" },
+                "clocks": {
+                    "allOf": [{ "$ref": "#/components/schemas/Clocks" }],
+                    "nullable": true,
+                    "description": "Thinking time for the whole game; untimed when null."
+                },
+                "mode": {
+                    "oneOf": [
+                        { "type": "string", "enum": ["Human"] },
+                        {
+                            "type": "object",
+                            "required": ["VsAi"],
+                            "properties": {
+                                "VsAi": {
+                                    "type": "object",
+                                    "required": ["ai_player"],
+                                    "properties": { "ai_player": { "$ref": "#/components/schemas/Player" } }
+                                }
+                            }
+                        }
+                    ],
+                    "nullable": true,
+                    "description": "Who plays which side. Human games refuse /ai-move; VsAi games refuse /move on the AI's turn and /ai-move on the human's. Unrestricted when null."
+                },
+                "first_player": { "$ref": "#/components/schemas/Player" },
+                "resign_out_of_turn": { "type": "boolean" },
+                "ai_strategy": {
+                    "type": "string",
+                    "enum": ["Minimax", "Random", "Worst"],
+                    "description": "How /ai-move plays: its best move, any legal move, or the move that looks worst for it. Hints always look for the best move."
+                }
+            }
+        },
+        "Clocks": {
+            "type": "object",
+            "required": ["p1_ms", "p2_ms"],
+            "properties": {
+                "p1_ms": { "type": "integer", "minimum": 0 },
+                "p2_ms": { "type": "integer", "minimum": 0 }
+            }
+        },
+        "ClockState": {
+            "type": "object",
+            "required": ["remaining", "turn_started_ms"],
+            "properties": {
+                "remaining": { "$ref": "#/components/schemas/Clocks" },
+                "turn_started_ms": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "description": "When the player to move got the turn, in milliseconds since the Unix epoch."
+                }
+            }
+        },
+        "Game": {
+            "type": "object",
+            "required": ["board", "current_player", "status"],
+            "properties": {
+                "size": { "type": "integer", "minimum": 0 },
+                "board": {
+                    "type": "array",
+                    "description": "Rows from the top; each square holds the player on it or null.",
+                    "items": {
+                        "type": "array",
+                        "items": { "allOf": [{ "$ref": "#/components/schemas/Player" }], "nullable": true }
+                    }
+                },
+                "current_player": { "$ref": "#/components/schemas/Player" },
+                "status": { "$ref": "#/components/schemas/GameStatus" },
+                "ply": { "type": "integer", "minimum": 0 },
+                "history": { "type": "array", "items": { "$ref": "#/components/schemas/MoveRecord" } },
+                "position_history": { "type": "array", "items": { "type": "integer", "minimum": 0 } },
+                "config": { "$ref": "#/components/schemas/GameConfig" },
+                "clock": {
+                    "allOf": [{ "$ref": "#/components/schemas/ClockState" }],
+                    "nullable": true,
+                    "description": "Time left for each side in timed games, as of the request."
+                }
+            }
+        },
+        "EvalWeights": {
+            "type": "object",
+            "properties": {
+                "goal_distance": { "type": "integer" },
+                "mobility": { "type": "integer" },
+                "threat": { "type": "integer" },
+                "blocked": { "type": "integer" }
+            }
+        },
+        "AiMoveResponse": {
+            "type": "object",
+            "required": ["from", "to", "score"],
+            "properties": {
+                "from": { "$ref": "#/components/schemas/Position" },
+                "to": { "$ref": "#/components/schemas/Position" },
+                "score": { "type": "integer", "description": "Positive when the side that moved is ahead." },
+                "stats": {
+                    "type": "object",
+                    "properties": {
+                        "nodes": { "type": "integer", "minimum": 0 },
+                        "elapsed_ms": { "type": "number" },
+                        "depth": { "type": "integer", "minimum": 0 }
+                    }
+                },
+                "pv": {
+                    "type": "array",
+                    "description": "The line of play the AI expects, starting with this move.",
+                    "items": { "$ref": "#/components/schemas/MoveRequest" }
+                }
+            }
+        },
+        "ConfigResponse": {
+            "type": "object",
+            "required": ["board_size", "players", "goal_p1", "goal_p2", "p1_start", "p2_start", "difficulties"],
+            "properties": {
+                "board_size": { "type": "integer", "minimum": 0 },
+                "players": { "type": "integer", "minimum": 2, "maximum": 4 },
+                "goal_p1": { "$ref": "#/components/schemas/Position" },
+                "goal_p2": { "$ref": "#/components/schemas/Position" },
+                "goal_p3": {
+                    "$ref": "#/components/schemas/Position",
+                    "description": "Only in games with a Player 3."
+                },
+                "goal_p4": {
+                    "$ref": "#/components/schemas/Position",
+                    "description": "Only in games with a Player 4."
+                },
+                "p1_start": position_list.clone(),
+                "p2_start": position_list.clone(),
+                "p3_start": position_list.clone(),
+                "p4_start": position_list,
+                "difficulties": {
+                    "type": "object",
+                    "description": "Settings of each difficulty: easy, medium and hard.",
+                    "additionalProperties": {
+                        "type": "object",
+                        "properties": {
+                            "depth": { "type": "integer", "minimum": 0 },
+                            "weights": { "$ref": "#/components/schemas/EvalWeights" },
+                            "time_ms": { "type": "integer", "minimum": 0 }
+                        }
+                    }
+                }
+            }
+        },
+        "ErrorResponse": {
+            "type": "object",
+            "required": ["error", "code"],
+            "properties": {
+                "error": { "type": "string" },
+                "code": { "type": "string", "description": "Stable identifier to branch on, e.g. illegal_move." }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::EvalWeights;
+    use crate::game::{Game, GameConfig, MoveRequest, Position};
+    use serde::de::{self, Deserialize, Deserializer, Visitor};
+    use std::collections::BTreeSet;
+
+    // The property names a schema declares
+    fn schema_properties(name: &str) -> BTreeSet<String> {
+        document()["components"]["schemas"][name]["properties"]
+            .as_object()
+            .unwrap_or_else(|| panic!("schema {} has no properties", name))
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    // The field names `value` serializes with
+    fn serialized_fields(value: impl serde::Serialize) -> BTreeSet<String> {
+        serde_json::to_value(value)
+            .unwrap()
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    // The query parameters documented for `method` on `path`
+    fn query_parameters(path: &str, method: &str) -> BTreeSet<String> {
+        document()["paths"][path][method]["parameters"]
+            .as_array()
+            .unwrap_or_else(|| panic!("{} {} has no parameters", method, path))
+            .iter()
+            .filter(|parameter| parameter["in"] == "query")
+            .map(|parameter| parameter["name"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    // A deserializer that only notes the field names a struct asks for
+    struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> Deserializer<'de> for FieldNames<'_> {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _visitor: V,
+        ) -> Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(de::Error::custom("fields noted"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes
+            byte_buf option unit unit_struct newtype_struct seq tuple tuple_struct map
+            enum identifier ignored_any
+        }
+    }
+
+    // The field names `T` is deserialized from, e.g. the query parameters a handler reads
+    fn deserialized_fields<T: for<'de> Deserialize<'de>>() -> BTreeSet<String> {
+        let mut fields: &'static [&'static str] = &[];
+        let _ = T::deserialize(FieldNames(&mut fields));
+        fields.iter().map(|field| field.to_string()).collect()
+    }
+
+    #[test]
+    fn test_query_parameters_match_handlers() {
+        assert_eq!(
+            query_parameters("/ai-move/{game_id}", "post"),
+            deserialized_fields::<crate::AiMoveQuery>()
+        );
+        assert_eq!(
+            query_parameters("/games", "post"),
+            deserialized_fields::<crate::CreateGameQuery>()
+        );
+    }
+
+    #[test]
+    fn test_document_is_json_listing_every_route() {
+        let text = serde_json::to_string(&document()).unwrap();
+        let parsed: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(parsed["openapi"], "3.0.3");
+
+        let paths = parsed["paths"].as_object().unwrap();
+        for route in [
+            "/games",
+            "/board/{game_id}",
+            "/move/{game_id}",
+            "/ai-move/{game_id}",
+            "/reset/{game_id}",
+            "/config",
+            "/config/{game_id}",
+        ] {
+            assert!(paths.contains_key(route), "{} is missing", route);
+        }
+
+        // Every reference points at a schema that exists
+        let schemas = parsed["components"]["schemas"].as_object().unwrap();
+        for reference in text.split("\"$ref\":\"").skip(1) {
+            let target = reference.split('"').next().unwrap();
+            let name = target.trim_start_matches("#/components/schemas/");
+            assert!(schemas.contains_key(name), "dangling $ref {}", target);
+        }
+    }
+
+    #[test]
+    fn test_schemas_match_serialized_types() {
+        let mut game = Game::new();
+        game.make_move(Position { row: 0, col: 3 }, Position { row: 0, col: 2 })
+            .unwrap();
+        assert_eq!(schema_properties("Game"), serialized_fields(&game));
+        assert_eq!(
+            schema_properties("MoveRecord"),
+            serialized_fields(game.history[0])
+        );
+        assert_eq!(
+            schema_properties("GameConfig"),
+            serialized_fields(GameConfig::default())
+        );
+        assert_eq!(
+            schema_properties("Position"),
+            serialized_fields(Position { row: 0, col: 0 })
+        );
+        assert_eq!(
+            schema_properties("EvalWeights"),
+            serialized_fields(EvalWeights::default())
+        );
+
+        let mv = MoveRequest {
+            from: Position { row: 0, col: 3 },
+            to: Position { row: 0, col: 2 },
+        };
+        let coordinates = &document()["components"]["schemas"]["MoveRequest"]["oneOf"][0];
+        let declared: BTreeSet<String> = coordinates["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        assert_eq!(declared, serialized_fields(mv));
+    }
+}
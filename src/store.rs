@@ -0,0 +1,126 @@
+use crate::game::Game;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
+use std::collections::HashMap;
+use tracing::error;
+use uuid::Uuid;
+
+/// SQLite-backed copy of every game session, so games survive a restart.
+/// Each game is stored as its JSON serialization, keyed by session id.
+#[derive(Debug, Clone)]
+pub struct Store {
+    pool: SqlitePool,
+}
+
+impl Store {
+    /// Opens (creating if needed) the database at `path`.
+    pub async fn open(path: &str) -> Result<Self, String> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+        let pool = SqlitePool::connect_with(options)
+            .await
+            .map_err(|e| format!("Could not open database {}: {}", path, e))?;
+
+        sqlx::query("CREATE TABLE IF NOT EXISTS games (id TEXT PRIMARY KEY, game TEXT NOT NULL)")
+            .execute(&pool)
+            .await
+            .map_err(|e| format!("Could not create the games table: {}", e))?;
+
+        Ok(Store { pool })
+    }
+
+    /// Inserts or replaces the stored copy of a game.
+    pub async fn save(&self, id: Uuid, game: &Game) -> Result<(), String> {
+        let json = serde_json::to_string(game).map_err(|e| e.to_string())?;
+        sqlx::query(
+            "INSERT INTO games (id, game) VALUES (?, ?)
+             ON CONFLICT(id) DO UPDATE SET game = excluded.game",
+        )
+        .bind(id.to_string())
+        .bind(json)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| format!("Could not save game {}: {}", id, e))?;
+        Ok(())
+    }
+
+    /// Removes the stored copy of a game, if there is one.
+    pub async fn delete(&self, id: Uuid) -> Result<(), String> {
+        sqlx::query("DELETE FROM games WHERE id = ?")
+            .bind(id.to_string())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| format!("Could not delete game {}: {}", id, e))?;
+        Ok(())
+    }
+
+    /// Checks that the database still answers queries.
+    pub async fn ping(&self) -> Result<(), String> {
+        sqlx::query("SELECT 1")
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|e| format!("Database unavailable: {}", e))
+    }
+
+    /// Waits for pending writes and closes every connection.
+    pub async fn close(&self) {
+        self.pool.close().await;
+    }
+
+    /// Loads every stored game. Rows that no longer parse are logged and skipped.
+    pub async fn load_all(&self) -> Result<HashMap<Uuid, Game>, String> {
+        let rows: Vec<(String, String)> = sqlx::query_as("SELECT id, game FROM games")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| format!("Could not load games: {}", e))?;
+
+        let mut games = HashMap::new();
+        for (id, json) in rows {
+            match (Uuid::parse_str(&id), serde_json::from_str::<Game>(&json)) {
+                (Ok(uuid), Ok(game)) => {
+                    games.insert(uuid, game);
+                }
+                (Err(e), _) => error!("Skipping stored game {}: {}", id, e),
+                (_, Err(e)) => error!("Skipping stored game {}: {}", id, e),
+            }
+        }
+        Ok(games)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Position;
+
+    fn temp_db_path() -> String {
+        std::env::temp_dir()
+            .join(format!("top-cap-{}.db", Uuid::new_v4()))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[tokio::test]
+    async fn test_games_survive_reopening_the_store() {
+        let path = temp_db_path();
+        let id = Uuid::new_v4();
+        let mut game = Game::new();
+        game.make_move(Position { row: 0, col: 3 }, Position { row: 0, col: 2 })
+            .unwrap();
+
+        {
+            let store = Store::open(&path).await.unwrap();
+            store.save(id, &Game::new()).await.unwrap();
+            // Saving again replaces the earlier copy
+            store.save(id, &game).await.unwrap();
+        }
+
+        let store = Store::open(&path).await.unwrap();
+        let games = store.load_all().await.unwrap();
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[&id], game);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}